@@ -736,6 +736,9 @@ pub struct Config {
     #[dynamic(default = "default_status_update_interval")]
     pub status_update_interval: u64,
 
+    #[dynamic(default = "default_pane_title_changed_event_delay")]
+    pub pane_title_changed_event_delay: u64,
+
     #[dynamic(default)]
     pub experimental_pixel_positioning: bool,
 
@@ -775,6 +778,12 @@ pub struct Config {
     #[dynamic(default)]
     pub pane_focus_follows_mouse: bool,
 
+    /// The `window-focus-changed` event is deferred until the focus
+    /// state has been stable for this many milliseconds.
+    /// 0 emits the event each time the focus state changes.
+    #[dynamic(default = "default_window_focus_event_debounce_ms")]
+    pub window_focus_event_debounce_ms: u64,
+
     #[dynamic(default = "default_true")]
     pub unzoom_on_switch_pane: bool,
 
@@ -1442,7 +1451,13 @@ impl Config {
 
     pub fn resolve_color_scheme(&self) -> Option<&Palette> {
         let scheme_name = self.color_scheme.as_ref()?;
+        self.color_scheme_by_name(scheme_name)
+    }
 
+    /// Look up a color scheme by name, considering both the schemes
+    /// loaded from `color_scheme_dirs`/`color_schemes` and the
+    /// built-in schemes.
+    pub fn color_scheme_by_name(&self, scheme_name: &str) -> Option<&Palette> {
         if let Some(palette) = self.color_schemes.get(scheme_name) {
             Some(palette)
         } else {
@@ -1605,6 +1620,10 @@ fn default_mux_output_parser_coalesce_delay_ms() -> u64 {
     3
 }
 
+fn default_window_focus_event_debounce_ms() -> u64 {
+    100
+}
+
 fn default_mux_output_parser_buffer_size() -> usize {
     128 * 1024
 }
@@ -1770,6 +1789,10 @@ fn default_status_update_interval() -> u64 {
     1_000
 }
 
+fn default_pane_title_changed_event_delay() -> u64 {
+    250
+}
+
 fn default_alternate_buffer_wheel_scroll_speed() -> u8 {
     3
 }
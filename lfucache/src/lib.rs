@@ -271,6 +271,32 @@ impl<K: Hash + Eq + Clone + Debug, V, S: Default + BuildHasher> LfuCache<K, V, S
         None
     }
 
+    /// Remove the entry for `k`, if any, returning true if it was present
+    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let bucket = self.bucket_for_key(&k);
+        let mut cursor = match self.buckets.get_mut(bucket) {
+            Some(bucket) => bucket.front_mut(),
+            None => return false,
+        };
+        while let Some(entry) = cursor.get() {
+            if entry.key.borrow() == k {
+                unsafe {
+                    self.frequency_index.cursor_mut_from_ptr(entry).remove();
+                    self.recency_index.cursor_mut_from_ptr(entry).remove();
+                }
+                cursor.remove();
+                self.len -= 1;
+                return true;
+            }
+            cursor.move_next();
+        }
+        false
+    }
+
     pub fn put(&mut self, k: K, v: V) {
         let bucket = self.bucket_for_key(&k);
 
@@ -364,6 +390,22 @@ mod test {
         entries
     }
 
+    #[test]
+    fn remove() {
+        let mut cache = LfuCacheU64::with_capacity(4);
+        for i in 0..3 {
+            cache.put(i, i);
+        }
+        assert!(cache.remove(&1));
+        assert!(!cache.remove(&1));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&0), Some(&0));
+        assert_eq!(cache.get(&2), Some(&2));
+        assert_eq!(frequency_order(&cache).len(), 2);
+        assert_eq!(recency_order(&cache).len(), 2);
+    }
+
     #[test]
     fn decay() {
         let mut cache = LfuCacheU64::with_capacity(4);
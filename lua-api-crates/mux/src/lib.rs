@@ -20,7 +20,7 @@ mod tab;
 mod window;
 
 pub use domain::MuxDomain;
-pub use pane::MuxPane;
+pub use pane::{MuxPane, SplitPane};
 pub use tab::MuxTab;
 pub use window::MuxWindow;
 
@@ -47,6 +47,14 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    mux_mod.set(
+        "recent_workspaces",
+        lua.create_function(|_, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux.recent_workspaces())
+        })?,
+    )?;
+
     mux_mod.set(
         "set_active_workspace",
         lua.create_function(|_, workspace: String| {
@@ -18,7 +18,7 @@ impl MuxPane {
             .ok_or_else(|| mlua::Error::external(format!("pane id {} not found in mux", self.0)))
     }
 
-    fn get_text_from_semantic_zone(&self, zone: SemanticZone) -> mlua::Result<String> {
+    pub fn get_text_from_semantic_zone(&self, zone: SemanticZone) -> mlua::Result<String> {
         let mux = get_mux()?;
         let pane = self.resolve(&mux)?;
 
@@ -429,7 +429,7 @@ impl UserData for MuxPane {
 }
 
 #[derive(Debug, Default, FromDynamic, ToDynamic)]
-struct SplitPane {
+pub struct SplitPane {
     #[dynamic(flatten)]
     cmd_builder: CommandBuilderFrag,
     #[dynamic(default = "spawn_tab_default_domain")]
@@ -448,7 +448,7 @@ fn default_split_size() -> f32 {
 }
 
 impl SplitPane {
-    async fn run(&self, pane: &MuxPane) -> mlua::Result<MuxPane> {
+    pub async fn run(&self, pane: &MuxPane) -> mlua::Result<MuxPane> {
         let (command, command_dir) = self.cmd_builder.to_command_builder();
         let source = SplitSource::Spawn {
             command,
@@ -52,6 +52,10 @@ pub struct ClientInfo {
     pub last_input: DateTime<Utc>,
     /// The currently-focused pane
     pub focused_pane_id: Option<PaneId>,
+    /// Workspace names, most recently active first.
+    /// This is local bookkeeping and is not sent to remote clients.
+    #[serde(skip)]
+    pub recent_workspaces: Vec<String>,
 }
 
 impl ClientInfo {
@@ -62,6 +66,7 @@ impl ClientInfo {
             active_workspace: None,
             last_input: Utc::now(),
             focused_pane_id: None,
+            recent_workspaces: vec![],
         }
     }
 
@@ -620,13 +620,41 @@ impl Mux {
     }
 
     pub fn set_active_workspace_for_client(&self, ident: &Arc<ClientId>, workspace: &str) {
+        let existing = self.iter_workspaces();
         let mut clients = self.clients.write();
         if let Some(info) = clients.get_mut(&ident) {
             info.active_workspace.replace(workspace.to_string());
+            // Move this workspace to the front, and take the opportunity
+            // to prune any workspaces that have since been destroyed
+            info.recent_workspaces
+                .retain(|w| w != workspace && existing.contains(w));
+            info.recent_workspaces.insert(0, workspace.to_string());
             self.notify(MuxNotification::ActiveWorkspaceChanged(ident.clone()));
         }
     }
 
+    /// Returns the names of the workspaces that have been active for
+    /// the current identity, most recently active first.
+    /// Workspaces that no longer exist are omitted.
+    pub fn recent_workspaces(&self) -> Vec<String> {
+        let active = self.active_workspace();
+        let existing = self.iter_workspaces();
+        let mut recent = self
+            .identity
+            .read()
+            .as_ref()
+            .and_then(|ident| {
+                self.clients
+                    .read()
+                    .get(&ident)
+                    .map(|info| info.recent_workspaces.clone())
+            })
+            .unwrap_or_default();
+        recent.retain(|w| *w != active && existing.contains(w));
+        recent.insert(0, active);
+        recent
+    }
+
     /// Assigns the active workspace name for the current identity
     pub fn set_active_workspace(&self, workspace: &str) {
         if let Some(ident) = self.identity.read().clone() {
@@ -650,6 +678,11 @@ impl Mux {
         }
         self.recompute_pane_count();
         for client in self.clients.write().values_mut() {
+            for w in client.recent_workspaces.iter_mut() {
+                if w == old_workspace {
+                    *w = new_workspace.to_string();
+                }
+            }
             if client.active_workspace.as_deref() == Some(old_workspace) {
                 client.active_workspace.replace(new_workspace.to_string());
                 self.notify(MuxNotification::ActiveWorkspaceChanged(
@@ -509,6 +509,20 @@ impl Pane for LocalPane {
             .or_else(|| self.divine_current_working_dir(policy))
     }
 
+    fn set_current_working_dir(&self, url: Url) -> anyhow::Result<()> {
+        self.terminal.lock().set_current_dir(url);
+        Ok(())
+    }
+
+    fn get_scrollback_size(&self) -> Option<usize> {
+        Some(self.terminal.lock().scrollback_size())
+    }
+
+    fn set_scrollback_size(&self, size: Option<usize>) -> anyhow::Result<()> {
+        self.terminal.lock().set_scrollback_size(size);
+        Ok(())
+    }
+
     fn tty_name(&self) -> Option<String> {
         #[cfg(unix)]
         {
@@ -635,6 +649,14 @@ impl Pane for LocalPane {
         term.get_semantic_zones()
     }
 
+    fn get_pty_size(&self) -> Option<PtySize> {
+        self.pty.lock().get_size().ok()
+    }
+
+    fn get_last_command_status(&self) -> Option<i32> {
+        self.terminal.lock().last_command_status()
+    }
+
     async fn search(
         &self,
         pattern: Pattern,
@@ -5,6 +5,7 @@ use async_trait::async_trait;
 use config::keyassignment::{KeyAssignment, ScrollbackEraseMode};
 use downcast_rs::{impl_downcast, Downcast};
 use parking_lot::MappedMutexGuard;
+use portable_pty::PtySize;
 use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -284,6 +285,19 @@ pub trait Pane: Downcast + Send + Sync {
         Ok(vec![])
     }
 
+    /// Returns the size of the pty as known by the system, which is
+    /// what is reported to the program running in the pane, or None
+    /// if the pane is not directly backed by a pty
+    fn get_pty_size(&self) -> Option<PtySize> {
+        None
+    }
+
+    /// Returns the exit status of the most recently completed command,
+    /// as reported by the shell via OSC 133;D
+    fn get_last_command_status(&self) -> Option<i32> {
+        None
+    }
+
     /// Returns true if the terminal has grabbed the mouse and wants to
     /// give the embedded application a chance to process events.
     /// In practice this controls whether the gui will perform local
@@ -299,6 +313,23 @@ pub trait Pane: Downcast + Send + Sync {
     }
 
     fn get_current_working_dir(&self, policy: CachePolicy) -> Option<Url>;
+
+    /// Overrides the current working directory that is associated with
+    /// the pane, as though the application had set it via OSC 7
+    fn set_current_working_dir(&self, _url: Url) -> anyhow::Result<()> {
+        anyhow::bail!("this pane doesn't support setting the current working directory");
+    }
+    /// Returns the maximum number of rows of scrollback, if known
+    fn get_scrollback_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Overrides the maximum number of rows of scrollback for the pane,
+    /// or reverts to the configured value if `size` is None
+    fn set_scrollback_size(&self, _size: Option<usize>) -> anyhow::Result<()> {
+        anyhow::bail!("this pane doesn't support changing the scrollback size");
+    }
+
     fn get_foreground_process_name(&self, _policy: CachePolicy) -> Option<String> {
         None
     }
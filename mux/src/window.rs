@@ -134,6 +134,28 @@ impl Window {
         }
     }
 
+    /// Moves the tab at `from_idx` to `to_idx`, shifting the tabs
+    /// in between to make room.  The active tab remains active.
+    /// Returns an error if either index is out of range.
+    pub fn move_tab(&mut self, from_idx: usize, to_idx: usize) -> anyhow::Result<()> {
+        let len = self.tabs.len();
+        anyhow::ensure!(
+            from_idx < len && to_idx < len,
+            "cannot move tab {from_idx} to {to_idx} in a window with {len} tabs"
+        );
+        if from_idx == to_idx {
+            return Ok(());
+        }
+        let active = self.get_active().map(|tab| tab.tab_id());
+        let tab = self.tabs.remove(from_idx);
+        self.tabs.insert(to_idx, tab);
+        if let Some(idx) = active.and_then(|id| self.idx_by_id(id)) {
+            self.active = idx;
+        }
+        self.invalidate();
+        Ok(())
+    }
+
     pub fn remove_by_idx(&mut self, idx: usize) -> Arc<Tab> {
         self.invalidate();
         let active = self.get_active().map(Arc::clone);
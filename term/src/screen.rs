@@ -35,6 +35,9 @@ pub struct Screen {
     /// that we're the primary rather than the alternate screen.
     allow_scrollback: bool,
 
+    /// If set, overrides the scrollback size from the config
+    scrollback_size_override: Option<usize>,
+
     pub(crate) keyboard_stack: Vec<KeyboardEncoding>,
 
     /// Physical, visible height of the screen (not including scrollback)
@@ -78,6 +81,7 @@ impl Screen {
             lines,
             config: Arc::clone(config),
             allow_scrollback,
+            scrollback_size_override: None,
             physical_rows,
             physical_cols,
             stable_row_index_offset: 0,
@@ -91,7 +95,30 @@ impl Screen {
     }
 
     fn scrollback_size(&self) -> usize {
-        scrollback_size(&self.config, self.allow_scrollback)
+        match self.scrollback_size_override {
+            Some(size) if self.allow_scrollback => size,
+            _ => scrollback_size(&self.config, self.allow_scrollback),
+        }
+    }
+
+    /// Returns the maximum number of rows of scrollback
+    pub fn scrollback_capacity(&self) -> usize {
+        self.scrollback_size()
+    }
+
+    /// Overrides the maximum number of rows of scrollback, or reverts
+    /// to the configured value if `size` is None.
+    /// If the scrollback currently holds more rows than the new limit
+    /// allows, the oldest rows are discarded.
+    pub fn set_scrollback_size(&mut self, size: Option<usize>) {
+        self.scrollback_size_override = size;
+        let max_allowed = self.physical_rows + self.scrollback_size();
+        if self.lines.len() > max_allowed {
+            let excess = self.lines.len() - max_allowed;
+            self.lines.drain(0..excess);
+            self.lines.shrink_to_fit();
+            self.stable_row_index_offset += excess;
+        }
     }
 
     fn rewrap_lines(
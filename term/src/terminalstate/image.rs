@@ -9,6 +9,10 @@ use termwiz::image::{ImageCell, ImageDataType};
 use termwiz::surface::change::ImageData;
 use termwiz::surface::TextureCoordinate;
 
+/// Animations whose decoded frames would occupy more than this many
+/// bytes keep only this many bytes of recently used frames decoded
+const LAZY_ANIMATION_BUDGET: usize = 64 * 1024 * 1024;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PlacementInfo {
     pub first_row: StableRowIndex,
@@ -254,6 +258,8 @@ impl TerminalState {
     }
 
     /// cache recent images and avoid assigning a new id for repeated data!
+    /// Large animations are decoded on demand rather than being held
+    /// in the blob storage and decoded in their entirety.
     pub(crate) fn raw_image_to_image_data(
         &mut self,
         data: ImageDataType,
@@ -262,8 +268,18 @@ impl TerminalState {
         if let Some(item) = self.image_cache.get(&key) {
             Ok(Arc::clone(item))
         } else {
-            let data = data.swap_out()?;
-            let image_data = Arc::new(ImageData::with_data(data));
+            let image_data = match data {
+                ImageDataType::EncodedFile(data) => {
+                    match ImageData::try_lazy_animation(data, LAZY_ANIMATION_BUDGET) {
+                        Ok(image_data) => image_data,
+                        Err(data) => {
+                            ImageData::with_data(ImageDataType::EncodedFile(data).swap_out()?)
+                        }
+                    }
+                }
+                data => ImageData::with_data(data.swap_out()?),
+            };
+            let image_data = Arc::new(image_data);
             self.image_cache.put(key, Arc::clone(&image_data));
             Ok(image_data)
         }
@@ -447,7 +447,9 @@ impl TerminalState {
 
         let mut img = img.data();
         match &mut *img {
-            ImageDataType::EncodedLease(_) | ImageDataType::EncodedFile(_) => {
+            ImageDataType::EncodedLease(_)
+            | ImageDataType::EncodedFile(_)
+            | ImageDataType::Indexed8 { .. } => {
                 anyhow::bail!("invalid image type")
             }
             ImageDataType::Rgba8 {
@@ -455,6 +457,8 @@ impl TerminalState {
                 height,
                 data,
                 hash,
+                premultiplied,
+                ..
             } => {
                 anyhow::ensure!(
                     src_frame == target_frame && src_frame == 1,
@@ -487,7 +491,7 @@ impl TerminalState {
 
                 drop(dest);
 
-                *hash = ImageDataType::hash_bytes(data);
+                *hash = ImageDataType::hash_rgba8(*width, *height, *premultiplied, data);
             }
             ImageDataType::AnimRgba8 {
                 width,
@@ -605,11 +609,16 @@ impl TerminalState {
             ImageDataType::EncodedLease(_) | ImageDataType::EncodedFile(_) => {
                 anyhow::bail!("Expected decoded image for image id {}", image_id)
             }
+            ImageDataType::Indexed8 { .. } => {
+                anyhow::bail!("Cannot animate palettized image id {}", image_id)
+            }
             ImageDataType::Rgba8 {
                 data,
                 width,
                 height,
                 hash,
+                premultiplied,
+                ..
             } => {
                 let base_frame = match frame.base_frame {
                     Some(1) => Some(1),
@@ -639,7 +648,7 @@ impl TerminalState {
                         blit(&mut anim_img, &img, x, y, frame.composition_mode)?;
 
                         drop(anim_img);
-                        *hash = ImageDataType::hash_bytes(data);
+                        *hash = ImageDataType::hash_rgba8(*width, *height, *premultiplied, data);
                     }
                     Some(2) | None => {
                         // Create a second frame
@@ -665,6 +674,7 @@ impl TerminalState {
                             frames,
                             durations,
                             hashes,
+                            frame_dimensions: vec![(*width, *height); 2],
                         };
                     }
                     Some(n) => anyhow::bail!(
@@ -679,6 +689,7 @@ impl TerminalState {
                 frames,
                 durations,
                 hashes,
+                frame_dimensions,
             } => {
                 let frame_no = frame.frame_number.unwrap_or(frames.len() as u32 + 1);
                 if frame_no == frames.len() as u32 + 1 {
@@ -706,6 +717,9 @@ impl TerminalState {
                     frames.push(new_frame_data);
                     hashes.push(new_frame_hash);
                     durations.push(frame_gap);
+                    if !frame_dimensions.is_empty() {
+                        frame_dimensions.push((*width, *height));
+                    }
                 } else {
                     anyhow::ensure!(
                         frame_no > 0 && frame_no <= frames.len() as u32,
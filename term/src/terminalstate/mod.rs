@@ -363,6 +363,12 @@ pub struct TerminalState {
 
     user_vars: HashMap<String, String>,
 
+    /// The status reported by the most recent OSC 133;D
+    last_command_status: Option<i32>,
+    /// True if the output of a command started (OSC 133;C) and
+    /// its status has not yet been reported
+    awaiting_command_status: bool,
+
     kitty_img: KittyImageState,
     seqno: SequenceNo,
 
@@ -404,30 +410,11 @@ struct UnicodeVersionStackEntry {
 }
 
 fn default_color_map() -> HashMap<u16, RgbColor> {
-    let mut color_map = HashMap::new();
-    // Match colors to the VT340 color table:
-    // https://github.com/hackerb9/vt340test/blob/main/colormap/showcolortable.png
-    for (idx, r, g, b) in [
-        (0, 0, 0, 0),
-        (1, 0x33, 0x33, 0xcc),
-        (2, 0xcc, 0x23, 0x23),
-        (3, 0x33, 0xcc, 0x33),
-        (4, 0xcc, 0x33, 0xcc),
-        (5, 0x33, 0xcc, 0xcc),
-        (6, 0xcc, 0xcc, 0xcc),
-        (7, 0x77, 0x77, 0x77),
-        (8, 0x44, 0x44, 0x44),
-        (9, 0x56, 0x56, 0x99),
-        (10, 0x99, 0x44, 0x44),
-        (11, 0x56, 0x99, 0x56),
-        (12, 0x99, 0x56, 0x99),
-        (13, 0x56, 0x99, 0x99),
-        (14, 0x99, 0x99, 0x56),
-        (15, 0xcc, 0xcc, 0xcc),
-    ] {
-        color_map.insert(idx, RgbColor::new_8bpc(r, g, b));
-    }
-    color_map
+    termwiz::image::SIXEL_DEFAULT_REGISTERS
+        .iter()
+        .enumerate()
+        .map(|(idx, &[r, g, b])| (idx as u16, RgbColor::new_8bpc(r, g, b)))
+        .collect()
 }
 
 /// This struct implements a writer that sends the data across
@@ -571,6 +558,8 @@ impl TerminalState {
             writer,
             image_cache: lru::LruCache::new(NonZeroUsize::new(16).unwrap()),
             user_vars: HashMap::new(),
+            last_command_status: None,
+            awaiting_command_status: false,
             kitty_img: Default::default(),
             seqno,
             unicode_version,
@@ -651,6 +640,31 @@ impl TerminalState {
         self.current_dir.as_ref()
     }
 
+    /// Returns the maximum number of rows of scrollback for the
+    /// primary screen
+    pub fn scrollback_size(&self) -> usize {
+        self.screen.screen.scrollback_capacity()
+    }
+
+    /// Overrides the maximum number of rows of scrollback for the
+    /// primary screen, or reverts to the configured value if `size`
+    /// is None.  Reducing the size immediately discards the oldest
+    /// rows that no longer fit.
+    pub fn set_scrollback_size(&mut self, size: Option<usize>) {
+        self.screen.screen.set_scrollback_size(size);
+        self.increment_seqno();
+    }
+
+    /// Overrides the current working directory associated with the
+    /// terminal session, as though the application had used the
+    /// OSC 7 escape sequence to set it.
+    pub fn set_current_dir(&mut self, url: Url) {
+        self.current_dir.replace(url);
+        if let Some(handler) = self.alert_handler.as_mut() {
+            handler.alert(Alert::CurrentWorkingDirectoryChanged);
+        }
+    }
+
     /// Returns a copy of the palette.
     /// By default we don't keep a copy in the terminal state,
     /// preferring to take the config values from the users
@@ -961,6 +975,26 @@ impl TerminalState {
         &self.user_vars
     }
 
+    /// Returns the exit status of the most recently completed command,
+    /// as reported by the shell via OSC 133;D.
+    /// Returns None if no status has been reported, or if the shell
+    /// started a new prompt after a command without reporting the
+    /// status of that command.
+    pub fn last_command_status(&self) -> Option<i32> {
+        self.last_command_status
+    }
+
+    /// Called when a new prompt or command-end marker is seen.
+    /// If the previous command finished without its status being
+    /// reported, forget the status of the command before it so that
+    /// it cannot be mistaken for that of the previous command.
+    fn forget_unreported_command_status(&mut self) {
+        if self.awaiting_command_status {
+            self.awaiting_command_status = false;
+            self.last_command_status = None;
+        }
+    }
+
     fn clear_semantic_attribute_due_to_movement(&mut self) {
         if self.clear_semantic_attribute_on_newline {
             self.clear_semantic_attribute_on_newline = false;
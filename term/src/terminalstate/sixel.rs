@@ -4,7 +4,7 @@ use crate::TerminalState;
 use ::image::RgbaImage;
 use termwiz::color::RgbColor;
 use termwiz::escape::{Sixel, SixelData};
-use termwiz::image::ImageDataType;
+use termwiz::image::{sixel_hls_to_rgb, ImageDataType};
 
 impl TerminalState {
     pub(crate) fn sixel(&mut self, sixel: Box<Sixel>) {
@@ -84,21 +84,8 @@ impl TerminalState {
                     saturation,
                     lightness,
                 } => {
-                    // Sixel's hue angles are: blue=0, red=120, green=240,
-                    // whereas Hsl has red=0, green=120, blue=240.
-                    // Looking at red, we need to rotate left by 120 to
-                    // go from sixel red to standard hsl red.
-                    // Negative values wrap around the circle.
                     // https://github.com/wez/wezterm/issues/775
-                    let angle = (*hue_angle as f64) - 120.0;
-                    let angle = if angle < 0. { 360.0 + angle } else { angle };
-                    let c = csscolorparser::Color::from_hsla(
-                        angle,
-                        *saturation as f64 / 100.,
-                        *lightness as f64 / 100.,
-                        1.,
-                    );
-                    let [r, g, b, _] = c.to_rgba8();
+                    let [r, g, b] = sixel_hls_to_rgb(*hue_angle, *lightness, *saturation);
                     color_map.insert(*color_number, RgbColor::new_8bpc(r, g, b));
                 }
 
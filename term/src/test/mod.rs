@@ -346,6 +346,58 @@ fn test_semantic_1539() {
     );
 }
 
+#[test]
+fn test_last_command_status() {
+    use termwiz::escape::osc::{FinalTermPromptKind, FinalTermSemanticPrompt};
+    let mut term = TestTerm::new(5, 10, 0);
+    let prompt = |term: &mut TestTerm| {
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::StartPrompt(
+                FinalTermPromptKind::Initial
+            ))
+        ));
+    };
+    let output = |term: &mut TestTerm| {
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None }
+            )
+        ));
+    };
+    let status = |term: &mut TestTerm, status: i32| {
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandStatus { status, aid: None }
+            )
+        ));
+    };
+
+    assert_eq!(term.last_command_status(), None);
+
+    prompt(&mut term);
+    output(&mut term);
+    status(&mut term, 1);
+    assert_eq!(term.last_command_status(), Some(1));
+
+    // The status remains available while the next prompt is shown
+    // and the next command runs
+    prompt(&mut term);
+    output(&mut term);
+    assert_eq!(term.last_command_status(), Some(1));
+
+    // That command finished without reporting its status, so the
+    // status of the command before it must not be reported as its own
+    prompt(&mut term);
+    assert_eq!(term.last_command_status(), None);
+
+    output(&mut term);
+    status(&mut term, 0);
+    assert_eq!(term.last_command_status(), Some(0));
+}
+
 #[test]
 fn test_semantic() {
     use termwiz::escape::osc::FinalTermSemanticPrompt;
@@ -712,6 +764,39 @@ fn test_delete_lines() {
     term.assert_dirty_lines(seqno, &[4], None);
 }
 
+#[test]
+fn test_shrink_scrollback() {
+    let mut term = TestTerm::new(2, 3, 4);
+    term.print("111\r\n222\r\n333\r\n444\r\n555\r\n666");
+    assert_all_contents(
+        &term,
+        file!(),
+        line!(),
+        &["111", "222", "333", "444", "555", "666"],
+    );
+    assert_eq!(term.scrollback_size(), 4);
+
+    // Shrinking trims the oldest lines, leaving the visible screen alone
+    term.set_scrollback_size(Some(1));
+    assert_eq!(term.scrollback_size(), 1);
+    assert_all_contents(&term, file!(), line!(), &["444", "555", "666"]);
+    assert_visible_contents(&term, file!(), line!(), &["555", "666"]);
+
+    // Growing allows more lines to accumulate again
+    term.set_scrollback_size(None);
+    term.print("\r\n777\r\n888");
+    assert_all_contents(
+        &term,
+        file!(),
+        line!(),
+        &["444", "555", "666", "777", "888"],
+    );
+
+    // Shrinking to zero leaves just the visible screen
+    term.set_scrollback_size(Some(0));
+    assert_all_contents(&term, file!(), line!(), &["777", "888"]);
+}
+
 /// Test DEC Special Graphics character set.
 #[test]
 fn test_dec_special_graphics() {
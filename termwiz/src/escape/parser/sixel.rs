@@ -100,21 +100,22 @@ impl SixelBuilder {
                 let color_number = self.params[0] as u16;
                 let system = self.params[1] as u16;
                 let a = self.params[2] as u16;
-                let b = self.params[3] as u8;
-                let c = self.params[4] as u8;
+                let b = self.params[3];
+                let c = self.params[4];
 
                 if system == 1 {
                     self.sixel.data.push(SixelData::DefineColorMapHSL {
                         color_number,
                         hue_angle: a,
-                        lightness: b,
-                        saturation: c,
+                        lightness: b as u8,
+                        saturation: c as u8,
                     });
                 } else {
-                    let r = a as f32 * 255.0 / 100.;
-                    let g = b as f32 * 255.0 / 100.;
-                    let b = c as f32 * 255.0 / 100.;
-                    let rgb = RgbColor::new_8bpc(r as u8, g as u8, b as u8); // FIXME: from linear
+                    // The components are percentages of full intensity.
+                    // Clamp them before narrowing so that out of range
+                    // values saturate rather than wrap.
+                    let percent = |v: i64| (v.clamp(0, 100) as f32 * 255.0 / 100.).round() as u8;
+                    let rgb = RgbColor::new_8bpc(percent(self.params[2]), percent(b), percent(c));
                     self.sixel
                         .data
                         .push(SixelData::DefineColorMapRGB { color_number, rgb });
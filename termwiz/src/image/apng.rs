@@ -0,0 +1,144 @@
+use crate::image::ImageDataType;
+
+impl ImageDataType {
+    /// Encodes the image as a PNG.  An `AnimRgba8` animation is encoded
+    /// as an APNG that preserves the duration of each frame and loops
+    /// forever, while a still `Rgba8` image produces a regular PNG.
+    /// PNG stores straight alpha, so a premultiplied image is converted
+    /// with `to_straight_alpha` first.
+    /// The encoded variants are not supported.
+    pub fn encode_apng(&self) -> anyhow::Result<Vec<u8>> {
+        let mut encoded = vec![];
+        match self {
+            Self::Rgba8 {
+                premultiplied: true,
+                ..
+            } => return self.clone().to_straight_alpha().encode_apng(),
+            Self::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => {
+                let mut encoder = png::Encoder::new(&mut encoded, *width, *height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(data)?;
+                writer.finish()?;
+            }
+            Self::AnimRgba8 {
+                width,
+                height,
+                frames,
+                durations,
+                ..
+            } => {
+                anyhow::ensure!(
+                    !frames.is_empty(),
+                    "cannot encode an animation with no frames"
+                );
+                let mut encoder = png::Encoder::new(&mut encoded, *width, *height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                // A play count of 0 means that the animation loops forever
+                encoder.set_animated(frames.len() as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for (idx, data) in frames.iter().enumerate() {
+                    let (w, h) = self
+                        .frame_dimensions(idx)
+                        .ok_or_else(|| anyhow::anyhow!("missing frame {idx}"))?;
+                    let delay = durations
+                        .get(idx)
+                        .map(|d| d.as_millis().min(u16::MAX as u128) as u16)
+                        .unwrap_or(0);
+                    writer.set_frame_delay(delay, 1000)?;
+                    writer.set_frame_dimension(w, h)?;
+                    writer.write_image_data(data)?;
+                }
+                writer.finish()?;
+            }
+            Self::Indexed8 { .. } => return self.clone().to_rgba8().encode_apng(),
+            Self::EncodedFile(_) | Self::EncodedLease(_) => {
+                anyhow::bail!("cannot encode image data that has not been decoded")
+            }
+        }
+        Ok(encoded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn apng_roundtrip() {
+        // Opaque frames, so that compositing during decode is a no-op
+        let frames: Vec<Vec<u8>> = (0..3u8)
+            .map(|v| [v * 0x40, 0x10, 0x20, 0xff].repeat(4 * 2))
+            .collect();
+        let durations = vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(50),
+        ];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 4,
+            height: 2,
+            durations: durations.clone(),
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(4, 2); frames.len()],
+            frames: frames.clone(),
+        };
+
+        let encoded = anim.encode_apng().unwrap();
+        match ImageDataType::EncodedFile(encoded).decode() {
+            ImageDataType::AnimRgba8 {
+                width,
+                height,
+                frames: decoded_frames,
+                durations: decoded_durations,
+                ..
+            } => {
+                assert_eq!((width, height), (4, 2));
+                assert_eq!(decoded_frames, frames);
+                assert_eq!(decoded_durations, durations);
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+
+        let still = ImageDataType::new_single_frame(4, 2, frames[1].clone());
+        let encoded = still.encode_apng().unwrap();
+        assert!(ImageDataType::EncodedFile(encoded).decode() == still);
+
+        assert!(ImageDataType::EncodedFile(vec![]).encode_apng().is_err());
+    }
+
+    #[test]
+    fn apng_roundtrip_premultiplied() {
+        let straight = [200u8, 100, 50, 128];
+        let premultiplied = ImageDataType::new_single_frame(1, 1, straight.to_vec()).premultiply();
+        let encoded = premultiplied.encode_apng().unwrap();
+        match ImageDataType::EncodedFile(encoded).decode() {
+            ImageDataType::Rgba8 {
+                data,
+                premultiplied: false,
+                ..
+            } => {
+                for (got, want) in data.iter().zip(straight.iter()) {
+                    assert!(
+                        got.abs_diff(*want) <= 1,
+                        "got {:?}, want {:?}",
+                        data,
+                        straight
+                    );
+                }
+            }
+            other => panic!("expected straight Rgba8, got {:?}", other),
+        }
+    }
+}
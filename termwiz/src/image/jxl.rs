@@ -0,0 +1,98 @@
+use crate::image::{DecodeError, DecodeErrorReason, DecodeOptions, ImageDataType};
+
+impl ImageDataType {
+    /// Returns true if data looks like a JPEG XL image, either as
+    /// a bare codestream or wrapped in the ISOBMFF based container
+    pub(super) fn is_jxl(data: &[u8]) -> bool {
+        const CODESTREAM: &[u8] = &[0xff, 0x0a];
+        const CONTAINER: &[u8] = &[
+            0, 0, 0, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+        ];
+        data.starts_with(CODESTREAM) || data.starts_with(CONTAINER)
+    }
+
+    pub(super) fn decode_jxl(data: Vec<u8>, options: &DecodeOptions) -> Result<Self, DecodeError> {
+        match Self::render_jxl(std::io::Cursor::new(&*data)) {
+            Ok(image) => Ok(Self::from_dynamic_image(image, options)),
+            Err(err) => Err(DecodeError::new(
+                data,
+                None,
+                DecodeErrorReason::Jxl(format!("{err:#}")),
+            )),
+        }
+    }
+
+    /// Renders the first keyframe of a JPEG XL image as sRGB.
+    /// Animated JPEG XL files are therefore shown as still images.
+    pub(super) fn render_jxl<R: std::io::Read>(reader: R) -> anyhow::Result<image::DynamicImage> {
+        use jxl_oxide::{EnumColourEncoding, JxlImage, PixelFormat, RenderingIntent};
+
+        let mut image = JxlImage::builder()
+            .read(reader)
+            .map_err(|err| anyhow::anyhow!("{err:#}"))?;
+        image.request_color_encoding(EnumColourEncoding::srgb(RenderingIntent::Relative));
+        let format = image.pixel_format();
+        let fb = image
+            .render_frame(0)
+            .map_err(|err| anyhow::anyhow!("{err:#}"))?
+            .image();
+
+        let to_u8 = |v: f32| (v.clamp(0., 1.) * 255.).round() as u8;
+        let buf = fb.buf();
+        let mut rgba = Vec::with_capacity(fb.width() * fb.height() * 4);
+        match format {
+            PixelFormat::Gray => {
+                for &v in buf {
+                    let v = to_u8(v);
+                    rgba.extend_from_slice(&[v, v, v, 0xff]);
+                }
+            }
+            PixelFormat::Graya => {
+                for px in buf.chunks_exact(2) {
+                    let v = to_u8(px[0]);
+                    rgba.extend_from_slice(&[v, v, v, to_u8(px[1])]);
+                }
+            }
+            PixelFormat::Rgb => {
+                for px in buf.chunks_exact(3) {
+                    rgba.extend_from_slice(&[to_u8(px[0]), to_u8(px[1]), to_u8(px[2]), 0xff]);
+                }
+            }
+            PixelFormat::Rgba => rgba.extend(buf.iter().map(|&v| to_u8(v))),
+            PixelFormat::Cmyk | PixelFormat::Cmyka => {
+                anyhow::bail!("CMYK JPEG XL images are not supported");
+            }
+        }
+
+        let image = image::RgbaImage::from_raw(fb.width() as u32, fb.height() as u32, rgba)
+            .ok_or_else(|| anyhow::anyhow!("JPEG XL frame buffer has unexpected size"))?;
+        Ok(image::DynamicImage::ImageRgba8(image))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_jxl() {
+        // A 2x2 pixel JPEG XL codestream
+        let data = vec![
+            0xff, 0x0a, 0x08, 0x10, 0xb0, 0x12, 0x08, 0x08, 0x10, 0x00, 0x5c, 0x00, 0x4b, 0x12,
+            0xc5, 0x82, 0x45, 0x80, 0x1c, 0xbd, 0x31, 0xaa, 0x70, 0x81, 0x09, 0x5d, 0x2a, 0x0e,
+            0x9f, 0x74, 0xd5, 0x37, 0xbf, 0xe5, 0x03,
+        ];
+        match ImageDataType::EncodedFile(data).decode() {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => {
+                assert_eq!((width, height), (2, 2));
+                assert_eq!(data.len(), 2 * 2 * 4);
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+    }
+}
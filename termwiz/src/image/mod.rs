@@ -0,0 +1,5266 @@
+//! Images.
+//! This module has some helpers for modeling terminal cells that are filled
+//! with image data.
+//! We're targeting the iTerm image protocol initially, with sixel as an obvious
+//! follow up.
+//! Kitty has an extensive and complex graphics protocol
+//! whose docs are here:
+//! <https://github.com/kovidgoyal/kitty/blob/master/docs/graphics-protocol.rst>
+//! Both iTerm2 and Sixel appear to have semantics that allow replacing the
+//! contents of a single chararcter cell with image data, whereas the kitty
+//! protocol appears to track the images out of band as attachments with
+//! z-order.
+
+use crate::error::InternalError;
+use ordered_float::NotNan;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "use_image")]
+use std::collections::HashMap;
+#[cfg(feature = "use_image")]
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "use_image")]
+use std::io::{BufRead, Read, Seek};
+use std::ops::RangeInclusive;
+use std::path::Path;
+#[cfg(feature = "use_image")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+#[cfg(feature = "use_image")]
+use std::time::Instant;
+use thiserror::Error;
+use wezterm_blob_leases::{BlobLease, BlobManager};
+use wezterm_color_types::LinearRgba;
+
+#[cfg(feature = "use_image")]
+mod apng;
+#[cfg(feature = "jxl")]
+mod jxl;
+mod quantize;
+mod resize;
+mod sixel;
+#[cfg(feature = "use_image")]
+mod thumbhash;
+
+pub use self::resize::{FitMode, ResampleFilter};
+pub use self::sixel::{sixel_hls_to_rgb, SixelOptions, SIXEL_DEFAULT_REGISTERS};
+
+#[cfg(feature = "use_serde")]
+fn deserialize_notnan<'de, D>(deserializer: D) -> Result<NotNan<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = f32::deserialize(deserializer)?;
+    NotNan::new(value).map_err(|e| serde::de::Error::custom(format!("{:?}", e)))
+}
+
+#[cfg(feature = "use_serde")]
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::trivially_copy_pass_by_ref))]
+fn serialize_notnan<S>(value: &NotNan<f32>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    value.into_inner().serialize(serializer)
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureCoordinate {
+    #[cfg_attr(
+        feature = "use_serde",
+        serde(
+            deserialize_with = "deserialize_notnan",
+            serialize_with = "serialize_notnan"
+        )
+    )]
+    pub x: NotNan<f32>,
+    #[cfg_attr(
+        feature = "use_serde",
+        serde(
+            deserialize_with = "deserialize_notnan",
+            serialize_with = "serialize_notnan"
+        )
+    )]
+    pub y: NotNan<f32>,
+}
+
+impl TextureCoordinate {
+    pub fn new(x: NotNan<f32>, y: NotNan<f32>) -> Self {
+        Self { x, y }
+    }
+
+    pub fn new_f32(x: f32, y: f32) -> Self {
+        let x = NotNan::new(x).unwrap();
+        let y = NotNan::new(y).unwrap();
+        Self::new(x, y)
+    }
+
+    /// Validating version of `new_f32` that rejects NaN and
+    /// coordinates outside of the range 0.0..=1.0
+    pub fn try_new_f32(x: f32, y: f32) -> Result<Self, ImageCellError> {
+        for value in [x, y] {
+            if value.is_nan() {
+                return Err(ImageCellError::NaN);
+            }
+            if !(0.0..=1.0).contains(&value) {
+                return Err(ImageCellError::OutOfRange(value));
+            }
+        }
+        Ok(Self::new_f32(x, y))
+    }
+}
+
+/// Describes why a set of texture coordinates was rejected
+/// by `ImageCell::try_new`
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum ImageCellError {
+    #[error("texture coordinate is NaN")]
+    NaN,
+    #[error("texture coordinate {0} is outside the range 0.0..=1.0")]
+    OutOfRange(f32),
+}
+
+/// Describes why `ImageDataType::try_decode` was unable to decode
+/// an encoded image.  The encoded data is retained so that the caller
+/// can fall back to keeping it as is; see `into_data`.
+#[cfg(feature = "use_image")]
+#[derive(Debug)]
+pub struct DecodeError {
+    /// The format that the data was detected as, if it was recognized
+    pub format: Option<image::ImageFormat>,
+    pub reason: DecodeErrorReason,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "use_image")]
+impl DecodeError {
+    fn new(data: Vec<u8>, format: Option<image::ImageFormat>, reason: DecodeErrorReason) -> Self {
+        Self {
+            format,
+            reason,
+            data,
+        }
+    }
+
+    fn image(data: Vec<u8>, format: image::ImageFormat, err: image::ImageError) -> Self {
+        Self::new(data, Some(format), DecodeErrorReason::Image(err))
+    }
+
+    /// Returns the encoded data that could not be decoded
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(feature = "use_image")]
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.format {
+            Some(format) => write!(fmt, "Unable to decode {format:?} image data: ")?,
+            None => write!(fmt, "Unable to decode raw image data: ")?,
+        }
+        write!(fmt, "{}", self.reason)
+    }
+}
+
+#[cfg(feature = "use_image")]
+impl std::error::Error for DecodeError {}
+
+#[cfg(feature = "use_image")]
+#[derive(Error, Debug)]
+pub enum DecodeErrorReason {
+    #[error("the {width}x{height} image exceeds the limit of {max_pixels} pixels")]
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_pixels: u64,
+    },
+    #[error("{0:#}")]
+    Image(image::ImageError),
+    #[cfg(feature = "jxl")]
+    #[error("JPEG XL: {0}")]
+    Jxl(String),
+    /// `DecodeOptions::premultiply_alpha` was requested for an animation,
+    /// whose frames cannot be marked as premultiplied
+    #[error("premultiplied alpha is only supported for still images")]
+    PremultipliedAnimation,
+}
+
+/// The filter that the renderer should use when sampling an image
+/// that is displayed at a size other than its native resolution
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFilter {
+    /// Blend neighboring pixels; suitable for photographic content
+    #[default]
+    Linear,
+    /// Use the nearest pixel; preserves hard edges in pixel art
+    Nearest,
+}
+
+/// Advises the renderer about how an image is intended to be displayed,
+/// for example that it will be shown much smaller than its native
+/// resolution and would benefit from mipmaps.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RenderHint {
+    /// The preferred sampling filter
+    pub filter: ImageFilter,
+    /// Whether mipmaps should be generated for the image
+    pub mipmaps: bool,
+}
+
+/// The layer in which an image cell is drawn relative to the text,
+/// as determined by its z_index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageLayer {
+    /// Drawn beneath cells with non-default background colors
+    UnderBackground,
+    /// Drawn above the background, but beneath the text
+    UnderText,
+    /// Drawn above the text
+    OverText,
+}
+
+impl ImageLayer {
+    /// Returns the range of z_index values that are drawn in this layer
+    pub fn z_range(self) -> RangeInclusive<i32> {
+        match self {
+            Self::UnderBackground => i32::MIN..=i32::MIN / 2 - 1,
+            Self::UnderText => i32::MIN / 2..=-1,
+            Self::OverText => 0..=i32::MAX,
+        }
+    }
+
+    /// Returns the layer in which an image with `z_index` is drawn
+    pub fn for_z_index(z_index: i32) -> Self {
+        if Self::UnderBackground.z_range().contains(&z_index) {
+            Self::UnderBackground
+        } else if Self::UnderText.z_range().contains(&z_index) {
+            Self::UnderText
+        } else {
+            Self::OverText
+        }
+    }
+}
+
+/// Tracks data for displaying an image in the place of the normal cell
+/// character data.  Since an Image can span multiple cells, we need to logically
+/// carve up the image and track each slice of it.  Each cell needs to know
+/// its "texture coordinates" within that image so that we can render the
+/// right slice.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageCell {
+    /// Texture coordinate for the top left of this cell.
+    /// (0,0) is the top left of the ImageData. (1, 1) is
+    /// the bottom right.
+    top_left: TextureCoordinate,
+    /// Texture coordinates for the bottom right of this cell.
+    bottom_right: TextureCoordinate,
+    /// References the underlying image data
+    data: Arc<ImageData>,
+    z_index: i32,
+    /// When rendering in the cell, use this offset from the top left
+    /// of the cell
+    padding_left: u16,
+    padding_top: u16,
+    padding_right: u16,
+    padding_bottom: u16,
+
+    image_id: Option<u32>,
+    placement_id: Option<u32>,
+
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    render_hint: Option<RenderHint>,
+}
+
+impl ImageCell {
+    pub fn new(
+        top_left: TextureCoordinate,
+        bottom_right: TextureCoordinate,
+        data: Arc<ImageData>,
+    ) -> Self {
+        Self::with_z_index(top_left, bottom_right, data, 0, 0, 0, 0, 0, None, None)
+    }
+
+    /// Create a new ImageCell from `(x, y)` texture coordinates,
+    /// returning an error if any of them are NaN or fall outside
+    /// of the range 0.0..=1.0.
+    pub fn try_new(
+        top_left: (f32, f32),
+        bottom_right: (f32, f32),
+        data: Arc<ImageData>,
+    ) -> Result<Self, ImageCellError> {
+        let top_left = TextureCoordinate::try_new_f32(top_left.0, top_left.1)?;
+        let bottom_right = TextureCoordinate::try_new_f32(bottom_right.0, bottom_right.1)?;
+        Ok(Self::new(top_left, bottom_right, data))
+    }
+
+    pub fn compute_shape_hash<H: Hasher>(&self, hasher: &mut H) {
+        self.top_left.hash(hasher);
+        self.bottom_right.hash(hasher);
+        self.data.hash.hash(hasher);
+        self.z_index.hash(hasher);
+        self.padding_left.hash(hasher);
+        self.padding_top.hash(hasher);
+        self.padding_right.hash(hasher);
+        self.padding_bottom.hash(hasher);
+        self.image_id.hash(hasher);
+        self.placement_id.hash(hasher);
+        self.render_hint.hash(hasher);
+    }
+
+    pub fn with_z_index(
+        top_left: TextureCoordinate,
+        bottom_right: TextureCoordinate,
+        data: Arc<ImageData>,
+        z_index: i32,
+        padding_left: u16,
+        padding_top: u16,
+        padding_right: u16,
+        padding_bottom: u16,
+        image_id: Option<u32>,
+        placement_id: Option<u32>,
+    ) -> Self {
+        Self {
+            top_left,
+            bottom_right,
+            data,
+            z_index,
+            padding_left,
+            padding_top,
+            padding_right,
+            padding_bottom,
+            image_id,
+            placement_id,
+            render_hint: None,
+        }
+    }
+
+    /// Returns a copy of this cell with its render hint set to `hint`
+    pub fn with_render_hint(mut self, hint: Option<RenderHint>) -> Self {
+        self.render_hint = hint;
+        self
+    }
+
+    /// Returns true if this cell would render identically to `other`.
+    /// Unlike the derived `PartialEq`, this ignores the image and
+    /// placement ids, which are assigned per protocol interaction,
+    /// so that visually identical cells can share render caches.
+    pub fn same_content(&self, other: &Self) -> bool {
+        self.top_left == other.top_left
+            && self.bottom_right == other.bottom_right
+            && self.z_index == other.z_index
+            && self.padding() == other.padding()
+            && self.render_hint == other.render_hint
+            && self.data.hash == other.data.hash
+    }
+
+    pub fn matches_placement(&self, image_id: u32, placement_id: Option<u32>) -> bool {
+        self.image_id == Some(image_id) && self.placement_id == placement_id
+    }
+
+    pub fn has_placement_id(&self) -> bool {
+        self.placement_id.is_some()
+    }
+
+    pub fn image_id(&self) -> Option<u32> {
+        self.image_id
+    }
+
+    pub fn placement_id(&self) -> Option<u32> {
+        self.placement_id
+    }
+
+    pub fn render_hint(&self) -> Option<RenderHint> {
+        self.render_hint
+    }
+
+    pub fn set_render_hint(&mut self, hint: Option<RenderHint>) {
+        self.render_hint = hint;
+    }
+
+    pub fn top_left(&self) -> TextureCoordinate {
+        self.top_left
+    }
+
+    pub fn bottom_right(&self) -> TextureCoordinate {
+        self.bottom_right
+    }
+
+    pub fn image_data(&self) -> &Arc<ImageData> {
+        &self.data
+    }
+
+    /// negative z_index is rendered beneath the text layer.
+    /// >= 0 is rendered above the text.
+    /// negative z_index < INT32_MIN/2 will be drawn under cells
+    /// with non-default background colors.
+    /// See `layer` and `ImageLayer::z_range`.
+    pub fn z_index(&self) -> i32 {
+        self.z_index
+    }
+
+    /// Returns the layer in which this cell is drawn
+    pub fn layer(&self) -> ImageLayer {
+        ImageLayer::for_z_index(self.z_index)
+    }
+
+    /// Returns padding (left, top, right, bottom)
+    pub fn padding(&self) -> (u16, u16, u16, u16) {
+        (
+            self.padding_left,
+            self.padding_top,
+            self.padding_right,
+            self.padding_bottom,
+        )
+    }
+}
+
+/// A rectangular region of an image, in pixels, that has been
+/// modified; see `ImageDataType::update_region`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Options that influence how `ImageDataType::decode_with_options`
+/// decodes image data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DecodeOptions {
+    /// If set, images whose width * height exceeds this number of
+    /// pixels are not decoded, and are left as `EncodedFile`.
+    pub max_pixels: Option<u64>,
+    /// Animation frames with a delay shorter than this are clamped
+    /// up to this value, to avoid spinning the CPU on files that
+    /// specify 0ms or absurdly short delays.
+    /// Use `Duration::ZERO` to preserve the delays from the file as-is.
+    pub min_frame_delay: Duration,
+    /// If true, the EXIF orientation recorded in a JPEG file is
+    /// applied to the decoded pixels, so that eg: photos taken
+    /// with the camera held sideways are shown upright.
+    pub apply_orientation: bool,
+    /// If set, decoded images that are larger than `(width, height)`
+    /// are scaled down to fit within those dimensions, preserving
+    /// their aspect ratio.
+    pub max_dimensions: Option<(u32, u32)>,
+    /// If true, decoded still images have their color channels
+    /// multiplied by alpha; see `ImageDataType::premultiply`.
+    /// An animation with a single frame is decoded as a still image.
+    /// Animations with more frames have no way to record that they are
+    /// premultiplied, so they fail to decode with
+    /// `DecodeErrorReason::PremultipliedAnimation`, and are not loaded
+    /// lazily.  `ImageDataType::decode_frame` premultiplies the frame
+    /// that it returns.
+    pub premultiply_alpha: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_pixels: None,
+            min_frame_delay: Duration::from_millis(20),
+            apply_orientation: false,
+            max_dimensions: None,
+            premultiply_alpha: false,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// If `(width, height)` exceeds max_dimensions, returns the
+    /// largest dimensions with the same aspect ratio that fit
+    /// within max_dimensions.
+    #[cfg(feature = "use_image")]
+    fn fit_dimensions(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (max_width, max_height) = self.max_dimensions?;
+        if width <= max_width && height <= max_height {
+            return None;
+        }
+        let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+        Some((
+            ((width as f64 * scale).round() as u32).max(1),
+            ((height as f64 * scale).round() as u32).max(1),
+        ))
+    }
+
+    #[cfg(feature = "use_image")]
+    fn constrain(&self, image: image::RgbaImage) -> image::RgbaImage {
+        match self.fit_dimensions(image.width(), image.height()) {
+            Some((width, height)) => image::imageops::resize(
+                &image,
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            ),
+            None => image,
+        }
+    }
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq)]
+pub enum ImageDataType {
+    /// Data is in the native image file format
+    /// (best for file formats that have animated content)
+    EncodedFile(Vec<u8>),
+    /// Data is in the native image file format,
+    /// (best for file formats that have animated content)
+    /// and is stored as a blob via the blob manager.
+    EncodedLease(
+        #[cfg_attr(
+            feature = "use_serde",
+            serde(with = "wezterm_blob_leases::lease_bytes")
+        )]
+        BlobLease,
+    ),
+    /// Data is RGBA u8 data
+    Rgba8 {
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+        hash: [u8; 32],
+        /// true if the color channels have been multiplied by alpha
+        #[cfg_attr(feature = "use_serde", serde(default))]
+        premultiplied: bool,
+        /// The ICC color profile that was embedded in the encoded
+        /// image, which describes the color space of `data`.
+        /// It is not included in `hash`, so that otherwise identical
+        /// images are still recognized as the same.
+        /// It is not serialized, to avoid sending a potentially
+        /// large profile along with every image.
+        #[cfg_attr(feature = "use_serde", serde(skip))]
+        icc_profile: Option<Vec<u8>>,
+    },
+    /// Data is an animated sequence
+    AnimRgba8 {
+        /// The overall size of the animation; each frame is no
+        /// larger than this
+        width: u32,
+        height: u32,
+        durations: Vec<Duration>,
+        frames: Vec<Vec<u8>>,
+        hashes: Vec<[u8; 32]>,
+        /// The dimensions of each of the frames, as some formats
+        /// allow frames to differ in size.  If empty, every frame
+        /// is `width` x `height`.  Use `frame_dimensions` rather than
+        /// accessing this directly.
+        #[cfg_attr(feature = "use_serde", serde(default))]
+        frame_dimensions: Vec<(u32, u32)>,
+    },
+    /// Data is a palettized still image, where each u8 in `indices`
+    /// selects the RGBA color of the corresponding pixel from `palette`.
+    /// This is a compact representation for images with few distinct
+    /// colors; use `to_rgba8` to expand it.
+    Indexed8 {
+        palette: Vec<[u8; 4]>,
+        indices: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl std::fmt::Debug for ImageDataType {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::EncodedFile(data) => fmt
+                .debug_struct("EncodedFile")
+                .field("data_of_len", &data.len())
+                .finish(),
+            Self::EncodedLease(lease) => lease.fmt(fmt),
+            Self::Rgba8 {
+                data,
+                width,
+                height,
+                hash,
+                premultiplied,
+                icc_profile,
+            } => fmt
+                .debug_struct("Rgba8")
+                .field("data_of_len", &data.len())
+                .field("width", &width)
+                .field("height", &height)
+                .field("hash", &hash)
+                .field("premultiplied", &premultiplied)
+                .field("icc_profile_of_len", &icc_profile.as_ref().map(Vec::len))
+                .finish(),
+            Self::AnimRgba8 {
+                frames,
+                width,
+                height,
+                durations,
+                hashes,
+                frame_dimensions,
+            } => fmt
+                .debug_struct("AnimRgba8")
+                .field("frames_of_len", &frames.len())
+                .field("width", &width)
+                .field("height", &height)
+                .field("frame_dimensions", frame_dimensions)
+                .field("durations", durations)
+                .field("hashes", hashes)
+                .finish(),
+            Self::Indexed8 {
+                palette,
+                indices,
+                width,
+                height,
+            } => fmt
+                .debug_struct("Indexed8")
+                .field("palette_of_len", &palette.len())
+                .field("indices_of_len", &indices.len())
+                .field("width", &width)
+                .field("height", &height)
+                .finish(),
+        }
+    }
+}
+
+impl ImageDataType {
+    pub fn new_single_frame(width: u32, height: u32, data: Vec<u8>) -> Self {
+        let hash = Self::hash_rgba8(width, height, false, &data);
+        assert_eq!(
+            width * height * 4,
+            data.len() as u32,
+            "invalid dimensions {}x{} for pixel data of length {}",
+            width,
+            height,
+            data.len()
+        );
+        Self::Rgba8 {
+            width,
+            height,
+            data,
+            hash,
+            premultiplied: false,
+            icc_profile: None,
+        }
+    }
+
+    /// Black pixels
+    pub fn placeholder() -> Self {
+        let mut data = vec![];
+        let size = 8;
+        for _ in 0..size * size {
+            data.extend_from_slice(&[0, 0, 0, 0xff]);
+        }
+        ImageDataType::new_single_frame(size, size, data)
+    }
+
+    pub fn hash_bytes(bytes: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(bytes);
+        hasher.finalize().into()
+    }
+
+    /// Returns the `compute_hash` of `ImageDataType::EncodedFile(data)`,
+    /// without requiring ownership of `data`.
+    pub fn hash_encoded_file(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        let mut hasher = sha2::Sha256::new();
+        hasher.update([0u8]);
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    /// Returns the `compute_hash` of an `ImageDataType::Rgba8` with the
+    /// provided dimensions and pixel data.  This is the value that is
+    /// stored in its `hash` field.
+    pub fn hash_rgba8(width: u32, height: u32, premultiplied: bool, data: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        let mut hasher = sha2::Sha256::new();
+        hasher.update([1u8]);
+        hasher.update(width.to_le_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update([premultiplied as u8]);
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    /// Computes a hash that reflects the logical image rather than
+    /// just its raw bytes: the variant and the dimensions (and the
+    /// frame layout, for animations) are mixed in ahead of the
+    /// pixel data so that eg: two images with the same bytes but
+    /// different dimensions, or straight and premultiplied alpha,
+    /// don't collide.
+    pub fn compute_hash(&self) -> [u8; 32] {
+        use sha2::Digest;
+        let mut hasher = sha2::Sha256::new();
+        match self {
+            ImageDataType::EncodedFile(data) => return Self::hash_encoded_file(data),
+            ImageDataType::EncodedLease(lease) => return lease.content_id().as_hash_bytes(),
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                premultiplied,
+                ..
+            } => return Self::hash_rgba8(*width, *height, *premultiplied, data),
+            ImageDataType::AnimRgba8 {
+                frames,
+                durations,
+                width,
+                height,
+                ..
+            } => {
+                hasher.update([2u8]);
+                hasher.update(width.to_le_bytes());
+                hasher.update(height.to_le_bytes());
+                hasher.update((frames.len() as u64).to_le_bytes());
+                for (idx, data) in frames.iter().enumerate() {
+                    let (w, h) = self.frame_dimensions(idx).unwrap_or((*width, *height));
+                    hasher.update(w.to_le_bytes());
+                    hasher.update(h.to_le_bytes());
+                    hasher.update(data);
+                }
+                for d in durations {
+                    let d = d.as_secs_f32();
+                    let b = d.to_le_bytes();
+                    hasher.update(b);
+                }
+            }
+            ImageDataType::Indexed8 {
+                palette,
+                indices,
+                width,
+                height,
+            } => {
+                hasher.update([3u8]);
+                hasher.update(width.to_le_bytes());
+                hasher.update(height.to_le_bytes());
+                hasher.update((palette.len() as u64).to_le_bytes());
+                for color in palette {
+                    hasher.update(color);
+                }
+                hasher.update(indices);
+            }
+        };
+        hasher.finalize().into()
+    }
+
+    /// Divides the animation frame durations by the provided
+    /// speed_factor, so a factor of 2 will halve the duration.
+    /// # Panics
+    /// if the speed_factor is negative, non-finite or the result
+    /// overflows the allow Duration range.
+    pub fn adjust_speed(&mut self, speed_factor: f32) {
+        match self {
+            Self::AnimRgba8 { durations, .. } => {
+                for d in durations {
+                    *d = d.mul_f32(1. / speed_factor);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns an iterator over the rows of pixels of an `Rgba8` image,
+    /// with each row being a slice of `width * 4` bytes.
+    /// Returns `None` for the other variants.
+    pub fn rows(&self) -> Option<impl Iterator<Item = &[u8]>> {
+        match self {
+            Self::Rgba8 { data, width, .. } => {
+                Some(data.chunks_exact((*width as usize * 4).max(1)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the RGBA value of the pixel at the specified coordinates
+    /// of an `Rgba8` or `Indexed8` image, or `None` if the coordinates
+    /// are out of bounds or this is some other variant.
+    pub fn pixel_at(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        match self {
+            Self::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } if x < *width && y < *height => {
+                let offset = (y as usize * *width as usize + x as usize) * 4;
+                let mut pixel = [0u8; 4];
+                pixel.copy_from_slice(data.get(offset..offset + 4)?);
+                Some(pixel)
+            }
+            Self::Indexed8 {
+                palette,
+                indices,
+                width,
+                height,
+            } if x < *width && y < *height => {
+                let idx = *indices.get(y as usize * *width as usize + x as usize)?;
+                palette.get(idx as usize).copied()
+            }
+            _ => None,
+        }
+    }
+
+    /// Computes the mean color of a decoded image, or of the first frame
+    /// of an animation.  The color channels are weighted by alpha, so
+    /// that transparent pixels don't darken the result, while the alpha
+    /// channel is the plain mean.
+    /// Large images are sampled sparsely so that this remains cheap.
+    /// Returns `None` for the encoded variants and for empty images.
+    pub fn average_color(&self) -> Option<[u8; 4]> {
+        /// The approximate upper bound on the number of pixels sampled
+        const MAX_SAMPLES: usize = 64 * 1024;
+
+        let (pixels, premultiplied): (Box<dyn Iterator<Item = [u8; 4]> + '_>, bool) = match self {
+            Self::Rgba8 {
+                data,
+                premultiplied,
+                ..
+            } => (
+                Box::new(data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]])),
+                *premultiplied,
+            ),
+            Self::AnimRgba8 { frames, .. } => (
+                Box::new(
+                    frames
+                        .first()?
+                        .chunks_exact(4)
+                        .map(|p| [p[0], p[1], p[2], p[3]]),
+                ),
+                false,
+            ),
+            Self::Indexed8 {
+                palette, indices, ..
+            } => (
+                Box::new(
+                    indices
+                        .iter()
+                        .map(move |&idx| palette.get(idx as usize).copied().unwrap_or([0; 4])),
+                ),
+                false,
+            ),
+            Self::EncodedFile(_) | Self::EncodedLease(_) => return None,
+        };
+
+        let (width, height) = self.frame_dimensions(0)?;
+        let num_pixels = width as usize * height as usize;
+        let step = (num_pixels / MAX_SAMPLES).max(1);
+
+        let mut weighted = [0u64; 3];
+        let mut alpha = 0u64;
+        let mut samples = 0u64;
+        for pixel in pixels.step_by(step) {
+            let a = pixel[3] as u64;
+            for (sum, &c) in weighted.iter_mut().zip(&pixel[0..3]) {
+                // Premultiplied color is already weighted by alpha
+                *sum += if premultiplied {
+                    c as u64 * 255
+                } else {
+                    c as u64 * a
+                };
+            }
+            alpha += a;
+            samples += 1;
+        }
+        if samples == 0 {
+            return None;
+        }
+
+        let mut color = [0u8; 4];
+        if alpha > 0 {
+            for (c, sum) in color.iter_mut().zip(weighted) {
+                *c = ((sum + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+        color[3] = ((alpha + samples / 2) / samples) as u8;
+        Some(color)
+    }
+
+    /// Returns an opaque `Rgba8` copy of the image that has been
+    /// alpha-composited over a gray checkerboard, whose squares are
+    /// `size` pixels across, so that the transparent areas of the image
+    /// can be seen.  This is intended as a diagnostic aid.
+    /// An animation is represented by its poster frame.
+    /// The encoded variants are returned unchanged; `decode` them first.
+    pub fn composite_over_checker(&self, size: u32) -> ImageDataType {
+        const CHECKER_LIGHT: u8 = 0xcc;
+        const CHECKER_DARK: u8 = 0x99;
+
+        let (mut data, width, height, premultiplied, icc_profile) = match self.poster_frame() {
+            Some(Self::Rgba8 {
+                data,
+                width,
+                height,
+                premultiplied,
+                icc_profile,
+                ..
+            }) => (data, width, height, premultiplied, icc_profile),
+            _ => return self.clone(),
+        };
+
+        let size = size.max(1);
+        for (idx, pixel) in data.chunks_exact_mut(4).enumerate() {
+            let x = idx as u32 % width;
+            let y = idx as u32 / width;
+            let checker = if (x / size + y / size) % 2 == 0 {
+                CHECKER_LIGHT
+            } else {
+                CHECKER_DARK
+            } as u32;
+            let alpha = pixel[3] as u32;
+            for c in &mut pixel[0..3] {
+                // Premultiplied color is already weighted by alpha
+                let src = if premultiplied {
+                    *c as u32 * 255
+                } else {
+                    *c as u32 * alpha
+                };
+                *c = ((src + checker * (255 - alpha) + 127) / 255).min(255) as u8;
+            }
+            pixel[3] = 0xff;
+        }
+
+        let hash = Self::hash_rgba8(width, height, false, &data);
+        Self::Rgba8 {
+            data,
+            width,
+            height,
+            hash,
+            premultiplied: false,
+            icc_profile,
+        }
+    }
+
+    /// Replaces the pixels within the `width` x `height` rectangle whose
+    /// top left corner is at `x`, `y` in an `Rgba8` image with `rgba`,
+    /// which holds the replacement pixels in row order, in the same
+    /// representation (premultiplied or not) as the image.
+    /// The hash of the image is recomputed to reflect the new content.
+    /// Returns the modified rectangle, so that a renderer can upload
+    /// just that portion of the image, or an error if the rectangle
+    /// doesn't fit within the image, `rgba` is not the right length
+    /// for the rectangle, or this is not an `Rgba8` image.
+    pub fn update_region(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> Result<DirtyRect, InternalError> {
+        match self {
+            Self::Rgba8 {
+                data,
+                width: image_width,
+                height: image_height,
+                hash,
+                premultiplied,
+                ..
+            } => {
+                let fits =
+                    |start: u32, len: u32, limit: u32| start as u64 + len as u64 <= limit as u64;
+                if !fits(x, width, *image_width) || !fits(y, height, *image_height) {
+                    return Err(format!(
+                        "region {width}x{height} at ({x},{y}) is outside of \
+                         the {image_width}x{image_height} image"
+                    )
+                    .into());
+                }
+                let row_len = width as usize * 4;
+                if rgba.len() != row_len * height as usize {
+                    return Err(format!(
+                        "expected {} bytes of data for a {width}x{height} region, got {}",
+                        row_len * height as usize,
+                        rgba.len()
+                    )
+                    .into());
+                }
+
+                if row_len > 0 {
+                    let stride = *image_width as usize * 4;
+                    for (row, src) in rgba.chunks_exact(row_len).enumerate() {
+                        let start = (y as usize + row) * stride + x as usize * 4;
+                        data[start..start + row_len].copy_from_slice(src);
+                    }
+                    *hash = Self::hash_rgba8(*image_width, *image_height, *premultiplied, data);
+                }
+
+                Ok(DirtyRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                })
+            }
+            _ => Err("update_region is only supported for Rgba8 images"
+                .to_string()
+                .into()),
+        }
+    }
+
+    /// Returns a copy of the `width` x `height` rectangle whose top left
+    /// corner is at `x`, `y` in an `Rgba8` or `AnimRgba8` image.
+    /// Every frame of an animation is cropped to the same rectangle;
+    /// the portions of a frame that is smaller than the animation are
+    /// filled with transparent pixels.
+    /// Returns an error if the rectangle is empty or doesn't fit within
+    /// the image, or for the other variants, which should be decoded or
+    /// expanded with `to_rgba8` first.
+    pub fn crop(self, x: u32, y: u32, width: u32, height: u32) -> Result<Self, InternalError> {
+        let (image_width, image_height) = match &self {
+            Self::Rgba8 { width, height, .. } | Self::AnimRgba8 { width, height, .. } => {
+                (*width, *height)
+            }
+            _ => {
+                return Err("crop is only supported for Rgba8 and AnimRgba8 images"
+                    .to_string()
+                    .into())
+            }
+        };
+        let fits = |start: u32, len: u32, limit: u32| start as u64 + len as u64 <= limit as u64;
+        if width == 0 || height == 0 {
+            return Err(format!("cannot crop to an empty {width}x{height} region").into());
+        }
+        if !fits(x, width, image_width) || !fits(y, height, image_height) {
+            return Err(format!(
+                "region {width}x{height} at ({x},{y}) is outside of \
+                 the {image_width}x{image_height} image"
+            )
+            .into());
+        }
+
+        let crop_frame = |data: &[u8], (frame_width, frame_height): (u32, u32)| {
+            let row_len = width as usize * 4;
+            let mut cropped = vec![0u8; row_len * height as usize];
+            let avail_width = frame_width.saturating_sub(x).min(width) as usize * 4;
+            let avail_height = frame_height.saturating_sub(y).min(height) as usize;
+            if avail_width > 0 {
+                let stride = frame_width as usize * 4;
+                for (row, dest) in cropped
+                    .chunks_exact_mut(row_len)
+                    .take(avail_height)
+                    .enumerate()
+                {
+                    let start = (y as usize + row) * stride + x as usize * 4;
+                    dest[..avail_width].copy_from_slice(&data[start..start + avail_width]);
+                }
+            }
+            cropped
+        };
+
+        let dims: Vec<(u32, u32)> = (0..self.frame_count())
+            .map(|idx| self.frame_dimensions(idx).unwrap_or((0, 0)))
+            .collect();
+        match self {
+            Self::Rgba8 {
+                data,
+                premultiplied,
+                icc_profile,
+                ..
+            } => {
+                let data = crop_frame(&data, dims[0]);
+                let hash = Self::hash_rgba8(width, height, premultiplied, &data);
+                Ok(Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied,
+                    icc_profile,
+                })
+            }
+            Self::AnimRgba8 {
+                durations, frames, ..
+            } => {
+                let frames: Vec<Vec<u8>> = frames
+                    .iter()
+                    .zip(dims)
+                    .map(|(data, dims)| crop_frame(data, dims))
+                    .collect();
+                let hashes = frames.iter().map(|data| Self::hash_bytes(data)).collect();
+                let frame_dimensions = vec![(width, height); frames.len()];
+                Ok(Self::AnimRgba8 {
+                    width,
+                    height,
+                    durations,
+                    frames,
+                    hashes,
+                    frame_dimensions,
+                })
+            }
+            _ => unreachable!("other variants were rejected above"),
+        }
+    }
+
+    /// Returns true if this is an animation with more than a single
+    /// frame; see `frame_count` regarding the encoded variants.
+    pub fn is_animated(&self) -> bool {
+        self.frame_count() > 1
+    }
+
+    /// Returns the number of frames in the image: 1 for a still
+    /// `Rgba8` or `Indexed8` image and the number of frames for an
+    /// `AnimRgba8`.
+    /// For `EncodedFile`, this is the number of frames recorded in the
+    /// headers of an animated GIF, APNG or WebP file, which are read
+    /// without decoding any frames, and 0 for other files, whose frames
+    /// are not known until they are decoded.  It is always 0 for
+    /// `EncodedLease`.
+    pub fn frame_count(&self) -> usize {
+        match self {
+            Self::Rgba8 { .. } | Self::Indexed8 { .. } => 1,
+            Self::AnimRgba8 { frames, .. } => frames.len(),
+            #[cfg(feature = "use_image")]
+            Self::EncodedFile(data) => {
+                animation_header(data).map_or(0, |header| header.durations.len())
+            }
+            #[cfg(not(feature = "use_image"))]
+            Self::EncodedFile(_) => 0,
+            Self::EncodedLease(_) => 0,
+        }
+    }
+
+    /// Returns the `(width, height)` of the frame at `idx`, or `None`
+    /// if there is no such frame.  A still `Rgba8` or `Indexed8` image
+    /// has a single frame, while the encoded variants have none.
+    pub fn frame_dimensions(&self, idx: usize) -> Option<(u32, u32)> {
+        match self {
+            Self::Rgba8 { width, height, .. } | Self::Indexed8 { width, height, .. }
+                if idx == 0 =>
+            {
+                Some((*width, *height))
+            }
+            Self::AnimRgba8 {
+                width,
+                height,
+                frames,
+                frame_dimensions,
+                ..
+            } if idx < frames.len() => Some(
+                frame_dimensions
+                    .get(idx)
+                    .copied()
+                    .unwrap_or((*width, *height)),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the frame that is showing once `elapsed`
+    /// time has passed since the start of the animation, wrapping
+    /// around to the start when the animation loops.
+    /// Images that are not animated always show frame 0.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        match self {
+            Self::AnimRgba8 { durations, .. } => frame_index_at(durations, elapsed),
+            _ => 0,
+        }
+    }
+
+    /// Returns an iterator over the `(duration, pixels)` of each frame;
+    /// use `frame_dimensions` to find the size of a given frame.
+    /// A still `Rgba8` image yields a single frame with a zero duration,
+    /// while the encoded variants, which have not yet been decoded,
+    /// yield no frames.  An `Indexed8` image has no RGBA pixels to
+    /// borrow and also yields no frames until it is converted with
+    /// `to_rgba8`, after which it yields its single frame.
+    pub fn frames(&self) -> impl Iterator<Item = (Duration, &[u8])> {
+        let (still, anim) = match self {
+            Self::Rgba8 { data, .. } => (Some((Duration::ZERO, data.as_slice())), None),
+            Self::AnimRgba8 {
+                frames, durations, ..
+            } => (
+                None,
+                Some(
+                    durations
+                        .iter()
+                        .copied()
+                        .zip(frames.iter().map(|data| data.as_slice())),
+                ),
+            ),
+            Self::EncodedFile(_) | Self::EncodedLease(_) | Self::Indexed8 { .. } => (None, None),
+        };
+        still.into_iter().chain(anim.into_iter().flatten())
+    }
+
+    /// Returns a single representative frame of the image as an `Rgba8`.
+    /// For an animation, this is the frame that is showing about 25% of
+    /// the way through its total duration, which usually skips past any
+    /// blank intro frames.  A still `Rgba8` image is returned as-is,
+    /// while an `Indexed8` image is expanded to `Rgba8`.
+    /// Returns `None` for the encoded variants; you should `decode`
+    /// the image first if you want a poster frame for those.
+    pub fn poster_frame(&self) -> Option<ImageDataType> {
+        match self {
+            Self::Rgba8 { .. } => Some(self.clone()),
+            Self::Indexed8 { .. } => Some(self.clone().to_rgba8()),
+            Self::AnimRgba8 {
+                durations, frames, ..
+            } => {
+                if frames.is_empty() {
+                    return None;
+                }
+                let total: Duration = durations.iter().sum();
+                let idx = if total.is_zero() {
+                    frames.len() / 4
+                } else {
+                    let target = total / 4;
+                    let mut elapsed = Duration::ZERO;
+                    durations
+                        .iter()
+                        .position(|d| {
+                            elapsed += *d;
+                            elapsed > target
+                        })
+                        .unwrap_or(frames.len() - 1)
+                };
+                let idx = idx.min(frames.len() - 1);
+                let (width, height) = self.frame_dimensions(idx)?;
+                Some(Self::Rgba8 {
+                    width,
+                    height,
+                    hash: Self::hash_rgba8(width, height, false, &frames[idx]),
+                    data: frames[idx].clone(),
+                    premultiplied: false,
+                    icc_profile: None,
+                })
+            }
+            Self::EncodedFile(_) | Self::EncodedLease(_) => None,
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    pub fn dimensions(&self) -> Result<(u32, u32), InternalError> {
+        match self {
+            ImageDataType::EncodedFile(data) => Ok(dimensions_for_data(data)?),
+            ImageDataType::EncodedLease(lease) => Ok(dimensions_for_data(&lease.get_data()?)?),
+            ImageDataType::AnimRgba8 { width, height, .. }
+            | ImageDataType::Rgba8 { width, height, .. }
+            | ImageDataType::Indexed8 { width, height, .. } => Ok((*width, *height)),
+        }
+    }
+
+    /// Returns the in-memory footprint
+    pub fn len(&self) -> usize {
+        match self {
+            ImageDataType::EncodedFile(d) => d.len(),
+            ImageDataType::EncodedLease(_) => 0,
+            ImageDataType::Rgba8 { data, .. } => data.len(),
+            ImageDataType::AnimRgba8 { frames, .. } => frames.iter().map(|f| f.len()).sum(),
+            ImageDataType::Indexed8 {
+                palette, indices, ..
+            } => palette.len() * 4 + indices.len(),
+        }
+    }
+
+    /// Returns true if `len` is zero
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Makes pixels that match `rgb` fully transparent, so that a solid
+    /// background color can be treated as transparent.  A pixel matches
+    /// if each of its color channels is within `tolerance` of the
+    /// corresponding channel of `rgb`.  A premultiplied image is compared
+    /// in terms of its straight colors.
+    /// This is intended to be applied once when the image is loaded;
+    /// every frame of an animation is processed.
+    /// The encoded variants are returned unchanged.
+    pub fn apply_color_key(self, rgb: [u8; 3], tolerance: u8) -> Self {
+        fn key_out(data: &mut [u8], rgb: [u8; 3], tolerance: u8) {
+            for pixel in data.chunks_exact_mut(4) {
+                let matches = pixel[0..3]
+                    .iter()
+                    .zip(rgb.iter())
+                    .all(|(&c, &k)| c.abs_diff(k) <= tolerance);
+                if matches {
+                    pixel[3] = 0;
+                }
+            }
+        }
+
+        match self {
+            Self::Rgba8 {
+                premultiplied: true,
+                ..
+            } => {
+                // Premultiplied channels are darkened by alpha, so compare
+                // the straight colors, and leave keyed out pixels as
+                // transparent black
+                self.to_straight_alpha()
+                    .apply_color_key(rgb, tolerance)
+                    .premultiply()
+            }
+            Self::Rgba8 {
+                mut data,
+                width,
+                height,
+                premultiplied,
+                icc_profile,
+                ..
+            } => {
+                key_out(&mut data, rgb, tolerance);
+                let hash = Self::hash_rgba8(width, height, premultiplied, &data);
+                Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied,
+                    icc_profile,
+                }
+            }
+            Self::AnimRgba8 {
+                width,
+                height,
+                durations,
+                mut frames,
+                frame_dimensions,
+                ..
+            } => {
+                let hashes = frames
+                    .iter_mut()
+                    .map(|data| {
+                        key_out(data, rgb, tolerance);
+                        Self::hash_bytes(data)
+                    })
+                    .collect();
+                Self::AnimRgba8 {
+                    width,
+                    height,
+                    durations,
+                    frames,
+                    hashes,
+                    frame_dimensions,
+                }
+            }
+            Self::Indexed8 {
+                mut palette,
+                indices,
+                width,
+                height,
+            } => {
+                // Keying out the palette entries is equivalent to
+                // keying out every pixel that uses them
+                for color in &mut palette {
+                    key_out(color, rgb, tolerance);
+                }
+                Self::Indexed8 {
+                    palette,
+                    indices,
+                    width,
+                    height,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Bakes a hue, saturation and brightness adjustment into the pixels
+    /// of a decoded image, so that static images, such as a window
+    /// background, don't need to be adjusted by the shader every frame.
+    /// Each component is a multiplier, with 1.0 leaving it unchanged.
+    /// This mirrors the `apply_hsv` function in the shaders, including
+    /// operating on linear color values as they are sampled from the
+    /// sRGB texture, so the result is visually identical.
+    /// A premultiplied image is adjusted in terms of its straight colors.
+    /// The encoded variants are returned unchanged.
+    pub fn adjust_hsb(self, hue: f32, saturation: f32, brightness: f32) -> Self {
+        let transform = [hue, saturation, brightness];
+        if transform == [1.0; 3] {
+            return self;
+        }
+        if let Self::Rgba8 {
+            premultiplied: true,
+            ..
+        } = self
+        {
+            return self
+                .to_straight_alpha()
+                .adjust_hsb(hue, saturation, brightness)
+                .premultiply();
+        }
+        self.map_pixels(|pixel| apply_hsv(pixel, transform))
+    }
+
+    /// Bakes a grayscale conversion into the pixels of a decoded image,
+    /// replacing each color with its luma, computed using the Rec. 601
+    /// weights (0.299, 0.587, 0.114), while preserving alpha.
+    /// Like `adjust_hsb`, this is intended to be applied once to a
+    /// static image rather than every frame.
+    /// The encoded variants are returned unchanged.
+    pub fn to_grayscale(self) -> Self {
+        self.map_pixels(|[r, g, b, a]| {
+            // Luma is a linear combination of the channels, so this
+            // also works for premultiplied pixels
+            let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+            let luma = luma.round().min(255.) as u8;
+            [luma, luma, luma, a]
+        })
+    }
+
+    /// Applies `adjust` to each pixel of a decoded image, or to each
+    /// color in the palette of an `Indexed8` image, and recomputes
+    /// the hashes.  The encoded variants are returned unchanged.
+    fn map_pixels(self, adjust: impl Fn([u8; 4]) -> [u8; 4]) -> Self {
+        let adjust_all = |data: &mut [u8]| {
+            for pixel in data.chunks_exact_mut(4) {
+                let adjusted = adjust([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                pixel.copy_from_slice(&adjusted);
+            }
+        };
+
+        match self {
+            Self::Rgba8 {
+                mut data,
+                width,
+                height,
+                premultiplied,
+                icc_profile,
+                ..
+            } => {
+                adjust_all(&mut data);
+                let hash = Self::hash_rgba8(width, height, premultiplied, &data);
+                Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied,
+                    icc_profile,
+                }
+            }
+            Self::AnimRgba8 {
+                width,
+                height,
+                durations,
+                mut frames,
+                frame_dimensions,
+                ..
+            } => {
+                let hashes = frames
+                    .iter_mut()
+                    .map(|data| {
+                        adjust_all(data);
+                        Self::hash_bytes(data)
+                    })
+                    .collect();
+                Self::AnimRgba8 {
+                    width,
+                    height,
+                    durations,
+                    frames,
+                    hashes,
+                    frame_dimensions,
+                }
+            }
+            Self::Indexed8 {
+                mut palette,
+                indices,
+                width,
+                height,
+            } => {
+                for color in &mut palette {
+                    *color = adjust(*color);
+                }
+                Self::Indexed8 {
+                    palette,
+                    indices,
+                    width,
+                    height,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Expands an `Indexed8` image into an `Rgba8` image, as required
+    /// for rendering.  Indices that fall outside of the palette, and
+    /// palette entries with zero alpha, produce `[0, 0, 0, 0]` pixels so
+    /// that no stray color bleeds into their neighbors when the image
+    /// is filtered.  The other variants are returned unchanged.
+    pub fn to_rgba8(self) -> Self {
+        match self {
+            Self::Indexed8 {
+                palette,
+                indices,
+                width,
+                height,
+            } => {
+                let mut data = Vec::with_capacity(indices.len() * 4);
+                for idx in indices {
+                    match palette.get(idx as usize) {
+                        Some(color) if color[3] != 0 => data.extend_from_slice(color),
+                        _ => data.extend_from_slice(&TRANSPARENT),
+                    }
+                }
+                Self::new_single_frame(width, height, data)
+            }
+            other => other,
+        }
+    }
+
+    /// Multiplies the color channels of an `Rgba8` image by its alpha
+    /// channel, as expected by premultiplied-alpha blending, and marks
+    /// it as premultiplied.  An image that is already premultiplied is
+    /// returned unchanged, as are the other variants.
+    pub fn premultiply(self) -> Self {
+        match self {
+            Self::Rgba8 {
+                mut data,
+                width,
+                height,
+                premultiplied: false,
+                icc_profile,
+                ..
+            } => {
+                premultiply_pixels(&mut data);
+                let hash = Self::hash_rgba8(width, height, true, &data);
+                Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied: true,
+                    icc_profile,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Returns this image with premultiplied alpha; this is the same
+    /// as `premultiply`, and is the counterpart of `to_straight_alpha`.
+    pub fn to_premultiplied_alpha(self) -> Self {
+        self.premultiply()
+    }
+
+    /// Divides the color channels of a premultiplied `Rgba8` image by its
+    /// alpha channel, reversing `premultiply`, and marks it as having
+    /// straight alpha.  Fully transparent pixels become transparent black,
+    /// and the result is clamped so that pixels with very low alpha cannot
+    /// overflow.  An image that already has straight alpha is returned
+    /// unchanged, as are the other variants.
+    pub fn to_straight_alpha(self) -> Self {
+        match self {
+            Self::Rgba8 {
+                mut data,
+                width,
+                height,
+                premultiplied: true,
+                icc_profile,
+                ..
+            } => {
+                for pixel in data.chunks_exact_mut(4) {
+                    let alpha = pixel[3] as u16;
+                    for c in &mut pixel[0..3] {
+                        *c = if alpha == 0 {
+                            0
+                        } else {
+                            ((*c as u16 * 255 + alpha / 2) / alpha).min(255) as u8
+                        };
+                    }
+                }
+                let hash = Self::hash_rgba8(width, height, false, &data);
+                Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied: false,
+                    icc_profile,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Migrate an in-memory encoded image blob to on-disk to reduce
+    /// the memory footprint
+    pub fn swap_out(self) -> Result<Self, InternalError> {
+        match self {
+            Self::EncodedFile(data) => match BlobManager::store(&data) {
+                Ok(lease) => Ok(Self::EncodedLease(lease)),
+                Err(wezterm_blob_leases::Error::StorageNotInit) => Ok(Self::EncodedFile(data)),
+                Err(err) => Err(err.into()),
+            },
+            other => Ok(other),
+        }
+    }
+
+    /// Decode an encoded file into either an Rgba8 or AnimRgba8 variant
+    /// if we recognize the file format, otherwise the EncodedFile data
+    /// is preserved as is.
+    pub fn decode(self) -> Self {
+        self.decode_with_options(&DecodeOptions::default())
+    }
+
+    /// Decode an encoded file into either an Rgba8 or AnimRgba8 variant
+    /// if we recognize the file format, otherwise the EncodedFile data
+    /// is preserved as is.
+    /// `options` influences how the data is decoded.
+    pub fn decode_with_options(self, options: &DecodeOptions) -> Self {
+        self.decode_timed(options).0
+    }
+
+    /// Like `decode_with_options`, additionally returning the time
+    /// spent decoding, or None if no decoding was attempted
+    #[cfg(feature = "use_image")]
+    fn decode_timed(self, options: &DecodeOptions) -> (Self, Option<Duration>) {
+        match self.try_decode_timed(options) {
+            (Ok(decoded), elapsed) => (decoded, elapsed),
+            (Err(err), elapsed) => {
+                log::warn!("{err}");
+                (Self::EncodedFile(err.into_data()), elapsed)
+            }
+        }
+    }
+
+    /// Decode an encoded file into either an Rgba8 or AnimRgba8 variant,
+    /// returning an error that describes the problem if the data cannot
+    /// be decoded.  The other variants are returned unchanged.
+    #[cfg(feature = "use_image")]
+    pub fn try_decode(self) -> Result<Self, DecodeError> {
+        self.try_decode_with_options(&DecodeOptions::default())
+    }
+
+    /// Like `try_decode`, with `options` influencing how the data is decoded
+    #[cfg(feature = "use_image")]
+    pub fn try_decode_with_options(self, options: &DecodeOptions) -> Result<Self, DecodeError> {
+        self.try_decode_timed(options).0
+    }
+
+    /// Like `try_decode_with_options`, additionally returning the time
+    /// spent decoding, which is also accumulated into the totals
+    /// reported by `image_decode_stats`.
+    /// Returns None for the duration if `self` is not an EncodedFile.
+    #[cfg(feature = "use_image")]
+    fn try_decode_timed(
+        self,
+        options: &DecodeOptions,
+    ) -> (Result<Self, DecodeError>, Option<Duration>) {
+        if !matches!(self, Self::EncodedFile(_)) {
+            return (Ok(self), None);
+        }
+        let start = Instant::now();
+        let result = self.try_decode_untimed(options);
+        let elapsed = start.elapsed();
+        record_decode(elapsed);
+        (result, Some(elapsed))
+    }
+
+    #[cfg(feature = "use_image")]
+    fn try_decode_untimed(self, options: &DecodeOptions) -> Result<Self, DecodeError> {
+        use image::{AnimationDecoder, ImageFormat};
+
+        match self {
+            Self::EncodedFile(data) => {
+                if let Some(max_pixels) = options.max_pixels {
+                    match dimensions_for_data(&data) {
+                        Ok((width, height)) if width as u64 * height as u64 > max_pixels => {
+                            let format = image::guess_format(&data).ok();
+                            return Err(DecodeError::new(
+                                data,
+                                format,
+                                DecodeErrorReason::TooLarge {
+                                    width,
+                                    height,
+                                    max_pixels,
+                                },
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+
+                // The image crate doesn't know about JPEG XL,
+                // so we need to recognize it for ourselves
+                #[cfg(feature = "jxl")]
+                if Self::is_jxl(&data) {
+                    return Self::decode_jxl(data, options);
+                }
+
+                let format = match image::guess_format(&data) {
+                    Ok(format) => format,
+                    Err(err) => return Self::decode_unsniffable(data, err, options),
+                };
+                let cursor = std::io::Cursor::new(&*data);
+                let frames = match format {
+                    ImageFormat::Gif => image::codecs::gif::GifDecoder::new(cursor)
+                        .and_then(|decoder| Self::collect_frames(format, decoder.into_frames())),
+                    ImageFormat::Png => {
+                        let decoder = match image::codecs::png::PngDecoder::new(cursor) {
+                            Ok(d) => d,
+                            Err(err) => return Err(DecodeError::image(data, format, err)),
+                        };
+                        if !decoder.is_apng().unwrap_or(false) {
+                            return Self::decode_single(data, format, options);
+                        }
+                        decoder
+                            .apng()
+                            .and_then(|d| Self::collect_frames(format, d.into_frames()))
+                    }
+                    ImageFormat::WebP => image::codecs::webp::WebPDecoder::new(cursor)
+                        .and_then(|decoder| Self::collect_frames(format, decoder.into_frames())),
+                    _ => return Self::decode_single(data, format, options),
+                };
+                match frames {
+                    Ok(frames) if frames.is_empty() => {
+                        log::error!("decoded image has 0 frames, using placeholder");
+                        Ok(Self::placeholder())
+                    }
+                    Ok(frames) if !Self::check_premultiply(&frames, options) => {
+                        Err(DecodeError::new(
+                            data,
+                            Some(format),
+                            DecodeErrorReason::PremultipliedAnimation,
+                        ))
+                    }
+                    Ok(frames) => Ok(Self::decode_frames(frames, options)),
+                    // A gif that we cannot parse as an animation may
+                    // still have a usable first frame
+                    Err(err) if format == ImageFormat::Gif => {
+                        match Self::try_decode_single(&data, options) {
+                            Ok(decoded) => {
+                                log::debug!(
+                                    "Unable to parse animated gif: {err:#}, \
+                                     decoded it as a single frame"
+                                );
+                                Ok(decoded)
+                            }
+                            Err(_) => Err(DecodeError::image(data, format, err)),
+                        }
+                    }
+                    Err(err) => Err(DecodeError::image(data, format, err)),
+                }
+            }
+            data => Ok(data),
+        }
+    }
+
+    #[cfg(not(feature = "use_image"))]
+    fn decode_timed(self, _options: &DecodeOptions) -> (Self, Option<Duration>) {
+        (self, None)
+    }
+
+    /// Decodes only the frame at `index` of an `EncodedFile`, returning
+    /// its duration and RGBA pixels, which are the same as those of the
+    /// corresponding frame produced by `decode_with_options`.
+    /// The frames of an animation are decoded from the start up to and
+    /// including `index`, retaining only the requested frame, so this is
+    /// suitable for extracting a single frame of a large animation.
+    /// A still image has a single frame with a zero duration.
+    /// Returns an error if there is no frame at `index`, if the data
+    /// cannot be decoded, or for the other variants.
+    #[cfg(feature = "use_image")]
+    pub fn decode_frame(
+        &self,
+        index: usize,
+        options: &DecodeOptions,
+    ) -> Result<(Duration, Vec<u8>), InternalError> {
+        let data = match self {
+            Self::EncodedFile(data) => data,
+            _ => {
+                return Err("decode_frame is only supported for EncodedFile images"
+                    .to_string()
+                    .into())
+            }
+        };
+
+        let frames = match animation_frames(data) {
+            Some(frames) => frames?,
+            None => {
+                if index != 0 {
+                    return Err(format!("frame {index} is out of range for a still image").into());
+                }
+                return match Self::try_decode_single(data, options)? {
+                    Self::Rgba8 { data, .. } => Ok((Duration::ZERO, data)),
+                    _ => Err("decoded image has no frames".to_string().into()),
+                };
+            }
+        };
+
+        let mut count = 0;
+        for frame in frames {
+            let frame = frame?;
+            if count == index {
+                let duration: Duration = frame.delay().into();
+                let mut data = options.constrain(frame.into_buffer()).into_vec();
+                if options.premultiply_alpha {
+                    premultiply_pixels(&mut data);
+                }
+                return Ok((duration.max(options.min_frame_delay), data));
+            }
+            count += 1;
+        }
+        Err(format!("frame {index} is out of range for an animation with {count} frames").into())
+    }
+
+    /// Converts the frames of an animation, which must be a single frame
+    /// if `options.premultiply_alpha` is set; see `check_premultiply`.
+    #[cfg(feature = "use_image")]
+    fn decode_frames(img_frames: Vec<image::Frame>, options: &DecodeOptions) -> Self {
+        if options.premultiply_alpha {
+            if let [frame] = &img_frames[..] {
+                let image = options.constrain(frame.buffer().clone());
+                let (width, height) = image.dimensions();
+                return Self::new_single_frame(width, height, image.into_vec()).premultiply();
+            }
+        }
+        let mut width = 0;
+        let mut height = 0;
+        let mut frames = vec![];
+        let mut durations = vec![];
+        let mut hashes = vec![];
+        let mut frame_dimensions = vec![];
+        for frame in img_frames.into_iter() {
+            let duration: Duration = frame.delay().into();
+            durations.push(duration.max(options.min_frame_delay));
+            let image = options.constrain(frame.into_buffer());
+            let (w, h) = image.dimensions();
+            width = width.max(w);
+            height = height.max(h);
+            frame_dimensions.push((w, h));
+            let data = image.into_vec();
+            hashes.push(Self::hash_bytes(&data));
+            frames.push(data);
+        }
+        Self::AnimRgba8 {
+            width,
+            height,
+            frames,
+            durations,
+            hashes,
+            frame_dimensions,
+        }
+    }
+
+    /// Returns false if `options.premultiply_alpha` is set but `frames`
+    /// has more than one frame, which `decode_frames` cannot premultiply
+    #[cfg(feature = "use_image")]
+    fn check_premultiply(frames: &[image::Frame], options: &DecodeOptions) -> bool {
+        !options.premultiply_alpha || frames.len() <= 1
+    }
+
+    #[cfg(feature = "use_image")]
+    fn decode_single(
+        data: Vec<u8>,
+        format: image::ImageFormat,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError> {
+        match Self::try_decode_single(&data, options) {
+            Ok(decoded) => Ok(decoded),
+            Err(err) => Err(DecodeError::image(data, format, err)),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    fn try_decode_single(data: &[u8], options: &DecodeOptions) -> image::ImageResult<Self> {
+        let decoder = image::io::Reader::new(std::io::Cursor::new(data))
+            .with_guessed_format()?
+            .into_decoder()?;
+        let (image, icc_profile) = decode_with_icc_profile(decoder)?;
+        let image = match exif_orientation(data) {
+            Some(orientation) if options.apply_orientation => apply_orientation(image, orientation),
+            _ => image,
+        };
+        Ok(Self::from_dynamic_image(image, options).with_icc_profile(icc_profile))
+    }
+
+    /// Attempts to decode `data`, which `image::guess_format` did not
+    /// recognize, as each of the formats that it can miss: `.cur` cursors
+    /// are read by the ICO decoder but have a different signature, AVIF is
+    /// only recognized for some sizes of its `ftyp` box, and TGA has no
+    /// signature at all.  TGA is tried last, because its decoder accepts
+    /// almost anything.
+    /// If a format recognizes the data but it exceeds `max_pixels`, the
+    /// remaining formats are still tried, and that is reported only if
+    /// none of them can decode it.  Otherwise, returns `err`.
+    #[cfg(feature = "use_image")]
+    fn decode_unsniffable(
+        data: Vec<u8>,
+        err: image::ImageError,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError> {
+        const UNSNIFFABLE_FORMATS: &[image::ImageFormat] = &[
+            image::ImageFormat::Ico,
+            image::ImageFormat::Avif,
+            image::ImageFormat::Tga,
+        ];
+
+        let mut too_large = None;
+        for &format in UNSNIFFABLE_FORMATS {
+            if !format.reading_enabled() {
+                continue;
+            }
+            let reader = image::io::Reader::with_format(std::io::Cursor::new(&*data), format);
+            let (width, height) = match reader.into_dimensions() {
+                Ok(dims) => dims,
+                Err(_) => continue,
+            };
+            if let Some(max_pixels) = options.max_pixels {
+                if width as u64 * height as u64 > max_pixels {
+                    too_large.get_or_insert((
+                        format,
+                        DecodeErrorReason::TooLarge {
+                            width,
+                            height,
+                            max_pixels,
+                        },
+                    ));
+                    continue;
+                }
+            }
+            if let Ok(image) = image::load_from_memory_with_format(&data, format) {
+                return Ok(Self::from_dynamic_image(image, options));
+            }
+        }
+
+        match too_large {
+            Some((format, reason)) => Err(DecodeError::new(data, Some(format), reason)),
+            None => Err(DecodeError::new(data, None, DecodeErrorReason::Image(err))),
+        }
+    }
+
+    /// Collects the frames of an animation.
+    /// If the data is truncated or corrupt part way through, the frames
+    /// that were successfully decoded up to that point are returned
+    /// rather than discarding the entire animation.
+    #[cfg(feature = "use_image")]
+    fn collect_frames(
+        format: image::ImageFormat,
+        frames: image::Frames<'_>,
+    ) -> image::ImageResult<Vec<image::Frame>> {
+        let mut result = vec![];
+        for frame in frames {
+            match frame {
+                Ok(frame) => result.push(frame),
+                Err(err) if !result.is_empty() => {
+                    log::warn!(
+                        "{format:?} image data is truncated or corrupt after {} frames, \
+                         showing only those frames: {err:#}",
+                        result.len()
+                    );
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(result)
+    }
+
+    #[cfg(feature = "use_image")]
+    fn from_dynamic_image(image: image::DynamicImage, options: &DecodeOptions) -> Self {
+        let image = options.constrain(dynamic_image_to_rgba8(image));
+        let (width, height) = image.dimensions();
+        let decoded = Self::new_single_frame(width, height, image.into_vec());
+        if options.premultiply_alpha {
+            decoded.premultiply()
+        } else {
+            decoded
+        }
+    }
+
+    /// Returns the ICC color profile that was embedded in the encoded
+    /// file that an `Rgba8` image was decoded from, if any.
+    /// Images of the other variants have no profile.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        match self {
+            Self::Rgba8 { icc_profile, .. } => icc_profile.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns this image with its ICC color profile replaced by
+    /// `icc_profile`.  The profile doesn't affect the hash of the image.
+    /// The variants other than `Rgba8` are returned unchanged.
+    pub fn with_icc_profile(self, icc_profile: Option<Vec<u8>>) -> Self {
+        match self {
+            Self::Rgba8 {
+                data,
+                width,
+                height,
+                hash,
+                premultiplied,
+                ..
+            } => Self::Rgba8 {
+                data,
+                width,
+                height,
+                hash,
+                premultiplied,
+                icc_profile,
+            },
+            other => other,
+        }
+    }
+
+    /// Decode an image directly from `reader`, without first reading
+    /// the complete encoded file into memory.
+    /// Unlike `decode_with_options`, unrecognized or undecodable data
+    /// results in an error, as the encoded bytes are not retained.
+    #[cfg(feature = "use_image")]
+    pub fn decode_reader<R: BufRead + Seek>(
+        mut reader: R,
+        options: &DecodeOptions,
+    ) -> Result<Self, InternalError> {
+        use image::{AnimationDecoder, ImageFormat};
+
+        // Peek at the leading bytes for the benefit of the format
+        // sniffing and metadata extraction that operate on slices.
+        // 64KiB is sufficient to hold a JPEG APP1 segment.
+        let mut prefix = vec![];
+        (&mut reader).take(64 * 1024).read_to_end(&mut prefix)?;
+        reader.rewind()?;
+
+        #[cfg(feature = "jxl")]
+        if Self::is_jxl(&prefix) {
+            let image = Self::render_jxl(reader)?;
+            return Ok(Self::from_dynamic_image(image, options));
+        }
+
+        if let Some(max_pixels) = options.max_pixels {
+            let (width, height) = image::io::Reader::new(&mut reader)
+                .with_guessed_format()?
+                .into_dimensions()?;
+            if width as u64 * height as u64 > max_pixels {
+                return Err(format!(
+                    "{width}x{height} image exceeds the limit of {max_pixels} pixels"
+                )
+                .into());
+            }
+            reader.rewind()?;
+        }
+
+        let reader = image::io::Reader::new(reader).with_guessed_format()?;
+        let frames = match reader.format() {
+            Some(format @ ImageFormat::Gif) => Self::collect_frames(
+                format,
+                image::codecs::gif::GifDecoder::new(reader.into_inner())?.into_frames(),
+            )?,
+            Some(format @ ImageFormat::WebP) => Self::collect_frames(
+                format,
+                image::codecs::webp::WebPDecoder::new(reader.into_inner())?.into_frames(),
+            )?,
+            Some(format @ ImageFormat::Png) => {
+                let decoder = image::codecs::png::PngDecoder::new(reader.into_inner())?;
+                if decoder.is_apng()? {
+                    Self::collect_frames(format, decoder.apng()?.into_frames())?
+                } else {
+                    let (image, icc_profile) = decode_with_icc_profile(decoder)?;
+                    return Ok(
+                        Self::from_dynamic_image(image, options).with_icc_profile(icc_profile)
+                    );
+                }
+            }
+            _ => {
+                let (image, icc_profile) = decode_with_icc_profile(reader.into_decoder()?)?;
+                let image = match exif_orientation(&prefix) {
+                    Some(orientation) if options.apply_orientation => {
+                        apply_orientation(image, orientation)
+                    }
+                    _ => image,
+                };
+                return Ok(Self::from_dynamic_image(image, options).with_icc_profile(icc_profile));
+            }
+        };
+
+        if frames.is_empty() {
+            log::error!("decoded image has 0 frames, using placeholder");
+            Ok(Self::placeholder())
+        } else if !Self::check_premultiply(&frames, options) {
+            Err(DecodeErrorReason::PremultipliedAnimation.to_string().into())
+        } else {
+            Ok(Self::decode_frames(frames, options))
+        }
+    }
+}
+
+/// Returns the index of the frame that is showing once `elapsed` time
+/// has passed since the start of an animation with the specified
+/// frame `durations`, wrapping around when the animation loops
+fn frame_index_at(durations: &[Duration], elapsed: Duration) -> usize {
+    let total: Duration = durations.iter().sum();
+    if total.is_zero() {
+        return 0;
+    }
+    let target = elapsed.as_nanos() % total.as_nanos();
+    let mut end = 0u128;
+    durations
+        .iter()
+        .position(|d| {
+            end += d.as_nanos();
+            end > target
+        })
+        .unwrap_or(0)
+}
+
+/// Converts a decoded image to 8 bits per channel RGBA.
+/// Images with 16 bits per channel, such as those exported by design
+/// tools, and floating point images are explicitly rescaled with
+/// rounding rather than relying on the defaults of `to_rgba8`.
+/// CMYK and YCCK JPEGs are converted to RGB by the JPEG decoder itself,
+/// taking into account the inverted Adobe representation.
+#[cfg(feature = "use_image")]
+fn dynamic_image_to_rgba8(image: image::DynamicImage) -> image::RgbaImage {
+    use image::DynamicImage;
+    match image {
+        DynamicImage::ImageRgba8(image) => image,
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => {
+            let image = image.into_rgba16();
+            let (width, height) = image.dimensions();
+            let data = image
+                .into_raw()
+                .into_iter()
+                .map(|v| ((v as u32 * 255 + 32767) / 65535) as u8)
+                .collect();
+            image::RgbaImage::from_raw(width, height, data)
+                .expect("rgba16 and rgba8 have the same number of channels")
+        }
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            let image = image.into_rgba32f();
+            let (width, height) = image.dimensions();
+            let data = image
+                .into_raw()
+                .into_iter()
+                .map(|v| (v.clamp(0., 1.) * 255.).round() as u8)
+                .collect();
+            image::RgbaImage::from_raw(width, height, data)
+                .expect("rgba32f and rgba8 have the same number of channels")
+        }
+        image => image.into_rgba8(),
+    }
+}
+
+/// Composites the straight-alpha pixel `src` over `dest` in place
+#[cfg(feature = "use_image")]
+fn composite_over(dest: &mut [u8], src: &[u8]) {
+    let src_a = src[3] as u32;
+    let dest_a = dest[3] as u32 * (255 - src_a) / 255;
+    let out_a = src_a + dest_a;
+    if out_a == 0 {
+        dest.copy_from_slice(&[0; 4]);
+        return;
+    }
+    for (d, s) in dest[..3].iter_mut().zip(&src[..3]) {
+        *d = ((*s as u32 * src_a + *d as u32 * dest_a + out_a / 2) / out_a) as u8;
+    }
+    dest[3] = out_a as u8;
+}
+
+/// Returns an iterator over the frames of `data` if it is an animated
+/// GIF, APNG or WebP file, or `None` if it is some other kind of image
+#[cfg(feature = "use_image")]
+fn animation_frames(data: &[u8]) -> Option<image::ImageResult<image::Frames<'_>>> {
+    use image::{AnimationDecoder, ImageFormat};
+
+    let cursor = std::io::Cursor::new(data);
+    match image::guess_format(data).ok()? {
+        ImageFormat::Gif => {
+            Some(image::codecs::gif::GifDecoder::new(cursor).map(|d| d.into_frames()))
+        }
+        ImageFormat::WebP => {
+            Some(image::codecs::webp::WebPDecoder::new(cursor).map(|d| d.into_frames()))
+        }
+        ImageFormat::Png => {
+            let decoder = match image::codecs::png::PngDecoder::new(cursor) {
+                Ok(decoder) => decoder,
+                Err(err) => return Some(Err(err)),
+            };
+            if !decoder.is_apng().unwrap_or(false) {
+                return None;
+            }
+            Some(decoder.apng().map(|d| d.into_frames()))
+        }
+        _ => None,
+    }
+}
+
+/// The dimensions and frame durations of an animation, as recorded in
+/// the headers of an animated GIF, APNG or WebP file
+#[cfg(feature = "use_image")]
+#[derive(Debug, PartialEq, Eq)]
+struct AnimationHeader {
+    width: u32,
+    height: u32,
+    durations: Vec<Duration>,
+}
+
+#[cfg(feature = "use_image")]
+impl AnimationHeader {
+    /// The number of bytes occupied by the decoded RGBA frames
+    fn decoded_len(&self) -> usize {
+        (self.width as usize)
+            .saturating_mul(self.height as usize)
+            .saturating_mul(4)
+            .saturating_mul(self.durations.len())
+    }
+}
+
+/// Reads the `AnimationHeader` of `data` by walking the blocks or chunks
+/// of the file, without decoding any of its frames.
+/// Returns `None` if `data` is not an animated GIF, APNG or WebP file,
+/// or if it is truncated or malformed.
+/// The durations are the raw values from the file; the caller is
+/// responsible for applying a minimum frame delay.
+#[cfg(feature = "use_image")]
+fn animation_header(data: &[u8]) -> Option<AnimationHeader> {
+    fn u16_le(data: &[u8], pos: usize) -> Option<u16> {
+        let b = data.get(pos..pos + 2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u16_be(data: &[u8], pos: usize) -> Option<u16> {
+        let b = data.get(pos..pos + 2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn u24_le(data: &[u8], pos: usize) -> Option<u32> {
+        let b = data.get(pos..pos + 3)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    }
+    fn u32_le(data: &[u8], pos: usize) -> Option<u32> {
+        let b = data.get(pos..pos + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn u32_be(data: &[u8], pos: usize) -> Option<u32> {
+        let b = data.get(pos..pos + 4)?;
+        Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn gif(data: &[u8]) -> Option<AnimationHeader> {
+        /// Returns the position following a sequence of data sub-blocks
+        fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+            loop {
+                let len = *data.get(pos)? as usize;
+                pos += 1 + len;
+                if len == 0 {
+                    return Some(pos);
+                }
+            }
+        }
+        /// The size of the color table indicated by the flags of the
+        /// logical screen or image descriptor
+        fn color_table_len(flags: u8) -> usize {
+            if flags & 0x80 != 0 {
+                3 << ((flags & 0x07) + 1)
+            } else {
+                0
+            }
+        }
+
+        let width = u16_le(data, 6)? as u32;
+        let height = u16_le(data, 8)? as u32;
+        let mut pos = 13 + color_table_len(*data.get(10)?);
+        let mut delay = 0;
+        let mut durations = vec![];
+        loop {
+            match *data.get(pos)? {
+                // Extension; the graphic control extension holds the
+                // delay, in hundredths of a second, of the next image
+                0x21 => {
+                    if *data.get(pos + 1)? == 0xf9 {
+                        delay = u16_le(data, pos + 4)?;
+                    }
+                    pos = skip_sub_blocks(data, pos + 2)?;
+                }
+                // Image descriptor, followed by the LZW minimum code
+                // size and the image data
+                0x2c => {
+                    pos += 11 + color_table_len(*data.get(pos + 9)?);
+                    pos = skip_sub_blocks(data, pos)?;
+                    durations.push(Duration::from_millis(delay as u64 * 10));
+                    delay = 0;
+                }
+                // Trailer
+                0x3b => break,
+                _ => return None,
+            }
+        }
+        Some(AnimationHeader {
+            width,
+            height,
+            durations,
+        })
+    }
+
+    fn apng(data: &[u8]) -> Option<AnimationHeader> {
+        let mut pos = 8;
+        let mut size = None;
+        let mut animated = false;
+        let mut durations = vec![];
+        loop {
+            let len = u32_be(data, pos)? as usize;
+            let chunk = data.get(pos + 8..(pos + 8).checked_add(len)?)?;
+            match data.get(pos + 4..pos + 8)? {
+                b"IHDR" => size = Some((u32_be(chunk, 0)?, u32_be(chunk, 4)?)),
+                b"acTL" => animated = true,
+                // Frame control; the delay is a fraction of a second,
+                // where a zero denominator means hundredths
+                b"fcTL" => {
+                    let numerator = u16_be(chunk, 20)? as u64;
+                    let denominator = match u16_be(chunk, 22)? {
+                        0 => 100,
+                        n => n as u64,
+                    };
+                    durations.push(Duration::from_nanos(
+                        numerator * 1_000_000_000 / denominator,
+                    ));
+                }
+                b"IEND" => break,
+                _ => {}
+            }
+            // Skip the chunk type, data and CRC
+            pos += 12 + len;
+        }
+        let (width, height) = size?;
+        if !animated {
+            return None;
+        }
+        Some(AnimationHeader {
+            width,
+            height,
+            durations,
+        })
+    }
+
+    fn webp(data: &[u8]) -> Option<AnimationHeader> {
+        let end = data
+            .len()
+            .min((u32_le(data, 4)? as usize).saturating_add(8));
+        let mut pos = 12;
+        let mut size = None;
+        let mut durations = vec![];
+        while pos + 8 <= end {
+            let len = u32_le(data, pos + 4)? as usize;
+            let chunk = data.get(pos + 8..(pos + 8).checked_add(len)?)?;
+            match data.get(pos..pos + 4)? {
+                // The canvas size is stored minus one
+                b"VP8X" => size = Some((u24_le(chunk, 4)? + 1, u24_le(chunk, 7)? + 1)),
+                b"ANMF" => durations.push(Duration::from_millis(u24_le(chunk, 12)? as u64)),
+                _ => {}
+            }
+            // Chunks are padded to an even length
+            pos += 8 + len + (len & 1);
+        }
+        let (width, height) = size?;
+        if durations.is_empty() {
+            return None;
+        }
+        Some(AnimationHeader {
+            width,
+            height,
+            durations,
+        })
+    }
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        gif(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        apng(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(&b"WEBP"[..]) {
+        webp(data)
+    } else {
+        None
+    }
+}
+
+/// A port of `rgb2hsv` from the shaders
+fn rgb2hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    const K: [f32; 4] = [0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0];
+    let p = if g >= b {
+        [g, b, K[0], K[1]]
+    } else {
+        [b, g, K[3], K[2]]
+    };
+    let q = if r >= p[0] {
+        [r, p[1], p[2], p[0]]
+    } else {
+        [p[0], p[1], p[3], r]
+    };
+
+    let d = q[0] - q[3].min(q[1]);
+    let e = 1.0e-10;
+    [
+        (q[2] + (q[3] - q[1]) / (6.0 * d + e)).abs(),
+        d / (q[0] + e),
+        q[0],
+    ]
+}
+
+/// A port of `hsv2rgb` from the shaders
+fn hsv2rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let channel = |k: f32| {
+        let x = h + k;
+        let p = ((x - x.floor()) * 6.0 - 3.0).abs();
+        v * (1.0 + ((p - 1.0).clamp(0.0, 1.0) - 1.0) * s)
+    };
+    [channel(1.0), channel(2.0 / 3.0), channel(1.0 / 3.0)]
+}
+
+/// A port of `apply_hsv` from the shaders, operating on an sRGBA pixel
+fn apply_hsv([r, g, b, a]: [u8; 4], transform: [f32; 3]) -> [u8; 4] {
+    let linear = LinearRgba::with_srgba(r, g, b, a);
+    let mut hsv = rgb2hsv([linear.0, linear.1, linear.2]);
+    for (c, t) in hsv.iter_mut().zip(transform) {
+        *c *= t;
+    }
+    let [r, g, b] = hsv2rgb(hsv);
+    let (r, g, b, _) = LinearRgba::with_components(r, g, b, linear.3)
+        .srgba_pixel()
+        .as_rgba();
+    [r, g, b, a]
+}
+
+/// The palette entry used for fully transparent pixels
+const TRANSPARENT: [u8; 4] = [0; 4];
+
+/// Decodes the image from `decoder`, along with its embedded ICC color
+/// profile.  A profile that cannot be read is ignored rather than
+/// failing the decode, as the pixels are still usable without it.
+#[cfg(feature = "use_image")]
+fn decode_with_icc_profile(
+    mut decoder: impl image::ImageDecoder,
+) -> image::ImageResult<(image::DynamicImage, Option<Vec<u8>>)> {
+    let icc_profile = match decoder.icc_profile() {
+        Ok(profile) => profile,
+        Err(err) => {
+            log::debug!("ignoring unreadable ICC profile: {err:#}");
+            None
+        }
+    };
+    let image = image::DynamicImage::from_decoder(decoder)?;
+    Ok((image, icc_profile))
+}
+
+#[cfg(feature = "use_image")]
+fn dimensions_for_data(data: &[u8]) -> image::ImageResult<(u32, u32)> {
+    let reader = image::io::Reader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+    let (width, height) = reader.into_dimensions()?;
+
+    Ok((width, height))
+}
+
+/// Extracts the EXIF orientation tag from a JPEG file, if present.
+/// Returns a value in the range 1..=8, as defined by the EXIF spec.
+#[cfg(feature = "use_image")]
+fn exif_orientation(data: &[u8]) -> Option<u16> {
+    // Walk the JPEG segments looking for the APP1 Exif segment
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut pos = 2;
+    let exif = loop {
+        if data.get(pos)? != &0xff {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        // Start of scan; there are no more metadata segments
+        if marker == 0xda {
+            return None;
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            break &segment[6..];
+        }
+        pos += 2 + len;
+    };
+
+    // The Exif payload is a TIFF structure
+    let big_endian = match exif.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = [
+            *exif.get(offset)?,
+            *exif.get(offset + 1)?,
+            *exif.get(offset + 2)?,
+            *exif.get(offset + 3)?,
+        ];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let num_entries = u16_at(ifd)? as usize;
+    for idx in 0..num_entries {
+        let entry = ifd + 2 + idx * 12;
+        const ORIENTATION_TAG: u16 = 0x0112;
+        if u16_at(entry)? == ORIENTATION_TAG {
+            let orientation = u16_at(entry + 8)?;
+            return if (1..=8).contains(&orientation) {
+                Some(orientation)
+            } else {
+                None
+            };
+        }
+    }
+    None
+}
+
+/// Transforms image according to the EXIF orientation value,
+/// so that it appears upright
+#[cfg(feature = "use_image")]
+fn apply_orientation(image: image::DynamicImage, orientation: u16) -> image::DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Multiplies the color channels of the RGBA pixels in `data` by alpha
+fn premultiply_pixels(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        for c in &mut pixel[0..3] {
+            *c = ((*c as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Tracks the sum of the in-memory footprint of all live ImageData
+/// instances; see `total_image_memory`.
+static IMAGE_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the total in-memory footprint, in bytes, of all of the
+/// ImageData instances that are currently alive.
+/// Each ImageData is accounted for based on its `len()` at the time
+/// that it was created, plus the frames that it currently caches if it
+/// was created by `with_raw_data_lazy`, and the copies that it currently
+/// retains for `ImageData::scaled`.
+pub fn total_image_memory() -> usize {
+    IMAGE_MEMORY.load(Ordering::Relaxed)
+}
+
+/// The number of encoded images that have been decoded; see
+/// `image_decode_stats`.
+static DECODE_COUNT: AtomicU64 = AtomicU64::new(0);
+/// The sum of the time spent decoding those images, in nanoseconds
+static DECODE_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Cumulative image decoding statistics; see `image_decode_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// The number of times that encoded image data has been decoded,
+    /// including attempts that failed
+    pub count: u64,
+    /// The total time spent in those decodes
+    pub total_time: Duration,
+}
+
+#[cfg(feature = "use_image")]
+fn record_decode(elapsed: Duration) {
+    DECODE_COUNT.fetch_add(1, Ordering::Relaxed);
+    DECODE_NANOS.fetch_add(
+        elapsed.as_nanos().min(u64::MAX as u128) as u64,
+        Ordering::Relaxed,
+    );
+}
+
+/// Returns the number of images decoded by this process so far,
+/// and the total time spent decoding them.
+/// The two values are read independently, so a decode that completes
+/// concurrently with this call may be reflected in only one of them.
+pub fn image_decode_stats() -> DecodeStats {
+    DecodeStats {
+        count: DECODE_COUNT.load(Ordering::Relaxed),
+        total_time: Duration::from_nanos(DECODE_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+type ImageDropFn = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// The hooks registered via `on_image_drop`, keyed by registration id
+static IMAGE_DROP_HOOKS: Mutex<Vec<(usize, ImageDropFn)>> = Mutex::new(Vec::new());
+/// The number of entries in IMAGE_DROP_HOOKS, allowing Drop to skip
+/// taking the lock in the common case where there are no hooks
+static IMAGE_DROP_HOOK_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_IMAGE_DROP_HOOK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers `hook` to be called with the `id` of every ImageData
+/// instance as it is dropped, so that caches that hold resources
+/// derived from the image, such as GPU textures, can release them
+/// promptly.
+/// Several live ImageData instances can share the same `hash` if they
+/// have identical content, so a cache keyed by the hash should keep
+/// track of the ids that it has seen for each hash, and only release
+/// the resources once the last of them has been dropped.
+///
+/// The hook is called after the registry lock has been released, so it
+/// is safe for it to drop other ImageData instances or to register and
+/// unregister hooks.  It may be called from any thread.
+///
+/// The hook remains registered until the returned `ImageDropHook`
+/// is dropped.
+pub fn on_image_drop<F: Fn(u64) + Send + Sync + 'static>(hook: F) -> ImageDropHook {
+    let id = NEXT_IMAGE_DROP_HOOK_ID.fetch_add(1, Ordering::Relaxed);
+    let mut hooks = IMAGE_DROP_HOOKS.lock().unwrap();
+    hooks.push((id, Arc::new(hook)));
+    IMAGE_DROP_HOOK_COUNT.store(hooks.len(), Ordering::Release);
+    ImageDropHook { id }
+}
+
+/// Unregisters the associated `on_image_drop` hook when dropped
+#[must_use = "the hook is unregistered when this is dropped"]
+pub struct ImageDropHook {
+    id: usize,
+}
+
+impl Drop for ImageDropHook {
+    fn drop(&mut self) {
+        let mut hooks = IMAGE_DROP_HOOKS.lock().unwrap();
+        hooks.retain(|(id, _)| *id != self.id);
+        IMAGE_DROP_HOOK_COUNT.store(hooks.len(), Ordering::Release);
+    }
+}
+
+fn notify_image_dropped(id: u64) {
+    if IMAGE_DROP_HOOK_COUNT.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let hooks: Vec<ImageDropFn> = IMAGE_DROP_HOOKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, hook)| Arc::clone(hook))
+        .collect();
+    for hook in hooks {
+        hook(id);
+    }
+}
+
+static NEXT_IMAGE_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_image_id() -> u64 {
+    NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg_attr(
+    feature = "use_serde",
+    derive(Deserialize),
+    serde(from = "SerializedImageData")
+)]
+pub struct ImageData {
+    data: Mutex<ImageDataType>,
+    hash: [u8; 32],
+    /// Uniquely identifies this instance within the process
+    id: u64,
+    /// The id chosen by `with_data_and_id`; see `content_id`
+    content_id: Option<u64>,
+    /// The number of bytes that this instance contributed to
+    /// IMAGE_MEMORY, so that Drop removes exactly that amount,
+    /// even if the data has been mutated in the meantime.
+    accounted_len: usize,
+    /// Resampled copies of a still image; see `ImageData::scaled`
+    #[cfg(feature = "use_image")]
+    scaled: Mutex<HashMap<(u32, u32), ScaledEntry>>,
+    /// Whether an animated image should advance through its frames
+    playing: AtomicBool,
+    /// The frame most recently returned by current_frame
+    shown_frame: AtomicUsize,
+    /// Set for a large animation whose frames are decoded on demand.
+    /// Its encoded data is held here rather than in `data`.
+    #[cfg(feature = "use_image")]
+    lazy: Option<LazyAnimation>,
+    /// The URI or path from which the image was loaded, if known.
+    /// This is informational only and is not part of the hash.
+    source: Option<Arc<str>>,
+    /// How long it took to decode the data, if it was decoded
+    /// when this instance was created
+    decode_duration: Option<Duration>,
+}
+
+/// The serialized fields of an ImageData.  Deserialization goes through
+/// this so that the resulting instance is accounted for in IMAGE_MEMORY
+/// in the same way as one that was created directly.
+#[cfg(feature = "use_serde")]
+#[derive(Deserialize)]
+struct SerializedImageData {
+    data: ImageDataType,
+    hash: [u8; 32],
+    #[serde(default)]
+    source: Option<Arc<str>>,
+    #[serde(default)]
+    content_id: Option<u64>,
+}
+
+#[cfg(feature = "use_serde")]
+impl From<SerializedImageData> for ImageData {
+    fn from(image: SerializedImageData) -> Self {
+        let mut result = Self::with_data_and_hash(image.data, image.hash);
+        result.source = image.source;
+        result.content_id = image.content_id;
+        result
+    }
+}
+
+/// Borrows the fields of an ImageData in the same layout as
+/// `SerializedImageData`
+#[cfg(feature = "use_serde")]
+#[derive(Serialize)]
+struct SerializedImageDataRef<'a> {
+    data: &'a ImageDataType,
+    hash: &'a [u8; 32],
+    source: &'a Option<Arc<str>>,
+    content_id: &'a Option<u64>,
+}
+
+#[cfg(feature = "use_serde")]
+impl Serialize for ImageData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[cfg(feature = "use_image")]
+        if let Some(lazy) = &self.lazy {
+            return SerializedImageDataRef {
+                data: &ImageDataType::EncodedFile(lazy.encoded.to_vec()),
+                hash: &self.hash,
+                source: &self.source,
+                content_id: &self.content_id,
+            }
+            .serialize(serializer);
+        }
+        SerializedImageDataRef {
+            data: &self.data(),
+            hash: &self.hash,
+            source: &self.source,
+            content_id: &self.content_id,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// The state of an animation that is held in its encoded form, with
+/// only a bounded number of its frames decoded at any one time;
+/// see `ImageData::with_raw_data_lazy`.
+#[cfg(feature = "use_image")]
+struct LazyAnimation {
+    width: u32,
+    height: u32,
+    durations: Vec<Duration>,
+    /// The encoded file, shared with the thread that decodes the frames
+    encoded: Arc<[u8]>,
+    /// Applied to each of the decoded frames
+    options: DecodeOptions,
+    /// Shared with the thread that decodes the frames
+    frames: Arc<Mutex<FrameLru>>,
+    /// Set while a thread is decoding frames into `frames`
+    decoding: Arc<AtomicBool>,
+}
+
+/// A least-recently-used cache of decoded frames, bounded by the
+/// number of bytes occupied by those frames.
+/// Those bytes are accounted for in IMAGE_MEMORY.
+#[cfg(feature = "use_image")]
+struct FrameLru {
+    budget: usize,
+    bytes: usize,
+    frames: HashMap<usize, Arc<[u8]>>,
+    /// Frame indices, least recently used first
+    order: VecDeque<usize>,
+    /// The index of the first frame that could not be decoded.
+    /// Neither it nor any of the following frames are attempted again.
+    failed_from: Option<usize>,
+}
+
+#[cfg(feature = "use_image")]
+impl FrameLru {
+    fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            bytes: 0,
+            frames: HashMap::new(),
+            order: VecDeque::new(),
+            failed_from: None,
+        }
+    }
+
+    fn get(&mut self, idx: usize) -> Option<Arc<[u8]>> {
+        let data = Arc::clone(self.frames.get(&idx)?);
+        self.touch(idx);
+        Some(data)
+    }
+
+    fn touch(&mut self, idx: usize) {
+        if let Some(pos) = self.order.iter().position(|&i| i == idx) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(idx);
+    }
+
+    fn has_failed(&self, idx: usize) -> bool {
+        matches!(self.failed_from, Some(failed) if idx >= failed)
+    }
+
+    fn set_failed_from(&mut self, idx: usize) {
+        self.failed_from = Some(self.failed_from.map_or(idx, |failed| failed.min(idx)));
+    }
+
+    /// Adds a frame, evicting the least recently used frames until the
+    /// cache is within its budget.  The frame that was just added is
+    /// always retained, even if it alone exceeds the budget.
+    fn insert(&mut self, idx: usize, data: Arc<[u8]>) {
+        self.bytes += data.len();
+        IMAGE_MEMORY.fetch_add(data.len(), Ordering::Relaxed);
+        if let Some(prior) = self.frames.insert(idx, data) {
+            self.remove_bytes(prior.len());
+        }
+        self.touch(idx);
+        while self.bytes > self.budget && self.order.len() > 1 {
+            if let Some(victim) = self.order.pop_front() {
+                if let Some(evicted) = self.frames.remove(&victim) {
+                    self.remove_bytes(evicted.len());
+                }
+            }
+        }
+    }
+
+    fn remove_bytes(&mut self, len: usize) {
+        self.bytes -= len;
+        IMAGE_MEMORY.fetch_sub(len, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "use_image")]
+impl Drop for FrameLru {
+    fn drop(&mut self) {
+        IMAGE_MEMORY.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Decodes the frame at `idx` of the animation in `encoded` into `cache`,
+/// scaling it down as required by `options`.
+/// The frames of an animation are typically encoded relative to the
+/// preceding frames, so we must decode from the start.  To avoid doing
+/// that again for each frame of sequential playback, we also retain as
+/// many of the following frames as fit within the budget.
+/// If `idx` cannot be decoded, that is recorded in the cache so that
+/// it isn't attempted again.
+/// The cache is only locked while inserting each frame.
+#[cfg(feature = "use_image")]
+fn decode_lazy_frames(
+    encoded: &[u8],
+    idx: usize,
+    options: &DecodeOptions,
+    cache: &Mutex<FrameLru>,
+) {
+    let frames = match animation_frames(encoded) {
+        Some(Ok(frames)) => frames,
+        Some(Err(err)) => {
+            log::warn!("Unable to decode animation frame {idx}: {err:#}");
+            cache.lock().unwrap().set_failed_from(0);
+            return;
+        }
+        None => {
+            cache.lock().unwrap().set_failed_from(0);
+            return;
+        }
+    };
+    let budget = cache.lock().unwrap().budget;
+    let mut decoded_bytes = 0;
+    let mut num_frames = 0;
+    for (i, frame) in frames.enumerate() {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::warn!("Unable to decode animation frame {i}: {err:#}");
+                break;
+            }
+        };
+        num_frames = i + 1;
+        if i < idx {
+            continue;
+        }
+        let data: Arc<[u8]> = options.constrain(frame.into_buffer()).into_raw().into();
+        decoded_bytes += data.len();
+        if i > idx && decoded_bytes > budget {
+            break;
+        }
+        cache.lock().unwrap().insert(i, data);
+    }
+    if num_frames <= idx {
+        cache.lock().unwrap().set_failed_from(num_frames);
+    }
+}
+
+/// A resampled copy of an image, and the frame in which it was last
+/// used; see `ImageData::scaled`
+#[cfg(feature = "use_image")]
+struct ScaledEntry {
+    image: Arc<ImageDataType>,
+    last_used: u64,
+}
+
+struct HexSlice<'a>(&'a [u8]);
+impl<'a> std::fmt::Display for HexSlice<'a> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(fmt, "{byte:x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ImageData {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ImageData")
+            .field("data", &self.data)
+            .field("hash", &format_args!("{}", HexSlice(&self.hash)))
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl Drop for ImageData {
+    fn drop(&mut self) {
+        IMAGE_MEMORY.fetch_sub(self.accounted_len, Ordering::Relaxed);
+        #[cfg(feature = "use_image")]
+        for entry in self.scaled.get_mut().unwrap().values() {
+            IMAGE_MEMORY.fetch_sub(entry.image.len(), Ordering::Relaxed);
+        }
+        notify_image_dropped(self.id);
+    }
+}
+
+/// The maximum number of threads used by `ImageData::preload_all`
+#[cfg(feature = "use_image")]
+const MAX_PRELOAD_THREADS: usize = 4;
+
+#[cfg(feature = "use_image")]
+struct PreloadState {
+    results: Vec<Option<crate::Result<Arc<ImageData>>>>,
+    remaining: usize,
+    waker: Option<std::task::Waker>,
+    /// The images loaded so far, keyed by the hash of their encoded
+    /// data, so that files with the same content share an instance
+    interned: HashMap<[u8; 32], Arc<ImageData>>,
+}
+
+/// A future that resolves to the results of `ImageData::preload_all`
+#[cfg(feature = "use_image")]
+#[must_use = "futures do nothing unless polled"]
+pub struct PreloadFuture {
+    state: Arc<Mutex<PreloadState>>,
+}
+
+#[cfg(feature = "use_image")]
+impl std::future::Future for PreloadFuture {
+    type Output = Vec<crate::Result<Arc<ImageData>>>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if state.remaining > 0 {
+            state.waker.replace(cx.waker().clone());
+            return std::task::Poll::Pending;
+        }
+        std::task::Poll::Ready(
+            state
+                .results
+                .drain(..)
+                .map(|result| result.expect("every entry is loaded before remaining is 0"))
+                .collect(),
+        )
+    }
+}
+
+impl Eq for ImageData {}
+impl PartialEq for ImageData {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.hash == rhs.hash
+    }
+}
+
+impl ImageData {
+    /// Create a new ImageData struct with the provided raw data.
+    pub fn with_raw_data(data: Vec<u8>) -> Self {
+        let hash = ImageDataType::hash_encoded_file(&data);
+        let (decoded, decode_duration) =
+            ImageDataType::EncodedFile(data).decode_timed(&DecodeOptions::default());
+        let mut image = Self::with_data_and_hash(decoded, hash);
+        image.decode_duration = decode_duration;
+        image
+    }
+
+    fn with_data_and_hash(data: ImageDataType, hash: [u8; 32]) -> Self {
+        let accounted_len = data.len();
+        IMAGE_MEMORY.fetch_add(accounted_len, Ordering::Relaxed);
+        Self {
+            data: Mutex::new(data),
+            hash,
+            id: next_image_id(),
+            content_id: None,
+            accounted_len,
+            #[cfg(feature = "use_image")]
+            scaled: Mutex::new(HashMap::new()),
+            playing: AtomicBool::new(true),
+            shown_frame: AtomicUsize::new(0),
+            #[cfg(feature = "use_image")]
+            lazy: None,
+            source: None,
+            decode_duration: None,
+        }
+    }
+
+    pub fn with_data(data: ImageDataType) -> Self {
+        let hash = data.compute_hash();
+        Self::with_data_and_hash(data, hash)
+    }
+
+    /// Create a new ImageData struct whose `content_id` is `id`, rather
+    /// than being derived from its content.  This is intended for callers
+    /// that assign their own reproducible ids, such as serialized sessions
+    /// and tests; the id is preserved when the image is serialized.
+    /// `id`, which is passed to `on_image_drop` hooks, remains unique
+    /// to the instance.
+    pub fn with_data_and_id(data: ImageDataType, id: u64) -> Self {
+        let mut image = Self::with_data(data);
+        image.content_id = Some(id);
+        image
+    }
+
+    /// Create a new ImageData struct from the contents of the file
+    /// at `path`, recording `path` as its source.
+    pub fn with_raw_data_from_path<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        Ok(Self::with_raw_data(data).with_source(path.to_string_lossy()))
+    }
+
+    /// Reads and decodes the files at `paths` on a bounded pool of
+    /// background threads, resolving to a result for each path in the
+    /// same order as `paths`.  A file that cannot be read or decoded
+    /// produces an error for that entry without affecting the others.
+    /// Files with identical content are decoded once and resolve to
+    /// the same instance.
+    #[cfg(feature = "use_image")]
+    pub fn preload_all(paths: Vec<PathBuf>, options: DecodeOptions) -> PreloadFuture {
+        let num_paths = paths.len();
+        let state = Arc::new(Mutex::new(PreloadState {
+            results: (0..num_paths).map(|_| None).collect(),
+            remaining: num_paths,
+            waker: None,
+            interned: HashMap::new(),
+        }));
+
+        let paths = Arc::new(paths);
+        let next = Arc::new(AtomicUsize::new(0));
+        let num_threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_PRELOAD_THREADS)
+            .min(num_paths);
+        for i in 0..num_threads {
+            let paths = Arc::clone(&paths);
+            let next = Arc::clone(&next);
+            let worker_state = Arc::clone(&state);
+            let options = options.clone();
+            let spawned = std::thread::Builder::new()
+                .name("image-preload".to_string())
+                .spawn(move || Self::preload_worker(&paths, &next, &options, &worker_state));
+            if let Err(err) = spawned {
+                // The threads that were spawned will load all of the
+                // entries between them; if there are none, fail each
+                // entry rather than blocking the caller to load them.
+                if i == 0 {
+                    let mut state = state.lock().unwrap();
+                    for result in state.results.iter_mut() {
+                        result.replace(Err(format!(
+                            "Unable to spawn thread to preload images: {err:#}"
+                        )
+                        .into()));
+                    }
+                    state.remaining = 0;
+                }
+                break;
+            }
+        }
+
+        PreloadFuture { state }
+    }
+
+    /// Loads the entries of `paths` that haven't yet been claimed
+    /// by another worker, until there are none left
+    #[cfg(feature = "use_image")]
+    fn preload_worker(
+        paths: &[PathBuf],
+        next: &AtomicUsize,
+        options: &DecodeOptions,
+        state: &Mutex<PreloadState>,
+    ) {
+        loop {
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            let path = match paths.get(idx) {
+                Some(path) => path,
+                None => break,
+            };
+            let result = Self::preload(path, options, state);
+            let waker = {
+                let mut state = state.lock().unwrap();
+                state.results[idx] = Some(result);
+                state.remaining -= 1;
+                if state.remaining == 0 {
+                    state.waker.take()
+                } else {
+                    None
+                }
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    fn preload(
+        path: &Path,
+        options: &DecodeOptions,
+        state: &Mutex<PreloadState>,
+    ) -> crate::Result<Arc<Self>> {
+        let data = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let hash = ImageDataType::hash_encoded_file(&data);
+        if let Some(image) = state.lock().unwrap().interned.get(&hash) {
+            return Ok(Arc::clone(image));
+        }
+
+        let (decoded, decode_duration) = ImageDataType::EncodedFile(data).try_decode_timed(options);
+        let decoded = decoded.map_err(|err| format!("{}: {err}", path.display()))?;
+        let mut image = Self::with_data_and_hash(decoded, hash).with_source(path.to_string_lossy());
+        image.decode_duration = decode_duration;
+
+        // Another worker may have loaded the same content meanwhile
+        let mut state = state.lock().unwrap();
+        Ok(Arc::clone(
+            state
+                .interned
+                .entry(hash)
+                .or_insert_with(|| Arc::new(image)),
+        ))
+    }
+
+    /// Records the URI or path from which this image was loaded.
+    /// The source does not influence the hash, so identical content
+    /// loaded from different locations still compares equal.
+    pub fn with_source<S: Into<Arc<str>>>(mut self, source: S) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Returns the URI or path from which this image was loaded, if known
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Returns how long it took to decode this image when it was
+    /// created from encoded data, or None if it was not decoded
+    /// at that time
+    pub fn decode_duration(&self) -> Option<Duration> {
+        self.decode_duration
+    }
+
+    /// Create a new ImageData struct with the provided raw data,
+    /// without decoding all of the frames of a large animation up-front.
+    /// If `data` is an animation whose decoded frames would occupy more
+    /// than `budget` bytes, it is retained in its encoded form; see
+    /// `try_lazy_animation`.
+    /// Otherwise, this is equivalent to `with_raw_data`.
+    #[cfg(feature = "use_image")]
+    pub fn with_raw_data_lazy(data: Vec<u8>, budget: usize) -> Self {
+        Self::try_lazy_animation(data, budget).unwrap_or_else(Self::with_raw_data)
+    }
+
+    /// If `data` is an animated GIF, APNG or WebP file whose decoded
+    /// frames would occupy more than `budget` bytes, returns an instance
+    /// that retains it in its encoded form.  Its frames are decoded on
+    /// demand, on a background thread, by `frame_data`, which keeps only
+    /// as many of the most recently used frames as fit within `budget`.
+    /// The size of the animation is determined from the dimensions and
+    /// the number of frames that are recorded in the headers of the file,
+    /// so this doesn't need to decode any frames.
+    /// Otherwise, `data` is returned unchanged.
+    #[cfg(feature = "use_image")]
+    pub fn try_lazy_animation(data: Vec<u8>, budget: usize) -> Result<Self, Vec<u8>> {
+        Self::try_lazy_animation_with_options(data, budget, &DecodeOptions::default())
+    }
+
+    /// Like `try_lazy_animation`, but the frames are decoded using
+    /// `options`: they are scaled down to fit within `max_dimensions`,
+    /// and their durations are clamped to `min_frame_delay`.
+    /// `data` is returned unchanged if the animation exceeds `max_pixels`,
+    /// or if `options.premultiply_alpha` is set.
+    #[cfg(feature = "use_image")]
+    pub fn try_lazy_animation_with_options(
+        data: Vec<u8>,
+        budget: usize,
+        options: &DecodeOptions,
+    ) -> Result<Self, Vec<u8>> {
+        let mut header = match animation_header(&data) {
+            Some(header) if header.durations.len() > 1 && !options.premultiply_alpha => header,
+            _ => return Err(data),
+        };
+        if let Some(max_pixels) = options.max_pixels {
+            if header.width as u64 * header.height as u64 > max_pixels {
+                return Err(data);
+            }
+        }
+        if let Some((width, height)) = options.fit_dimensions(header.width, header.height) {
+            header.width = width;
+            header.height = height;
+        }
+        if header.decoded_len() <= budget {
+            return Err(data);
+        }
+        let hash = ImageDataType::hash_encoded_file(&data);
+        let encoded: Arc<[u8]> = data.into();
+        // The encoded data is held by the LazyAnimation rather than in
+        // `data`, so account for it here
+        let mut image = Self::with_data_and_hash(ImageDataType::EncodedFile(vec![]), hash);
+        image.accounted_len = encoded.len();
+        IMAGE_MEMORY.fetch_add(image.accounted_len, Ordering::Relaxed);
+        image.lazy = Some(LazyAnimation {
+            width: header.width,
+            height: header.height,
+            durations: header
+                .durations
+                .into_iter()
+                .map(|duration| duration.max(options.min_frame_delay))
+                .collect(),
+            encoded,
+            options: options.clone(),
+            frames: Arc::new(Mutex::new(FrameLru::new(budget))),
+            decoding: Arc::new(AtomicBool::new(false)),
+        });
+        Ok(image)
+    }
+
+    /// For an animation created by `with_raw_data_lazy` or
+    /// `try_lazy_animation`, returns the dimensions of its frames
+    /// and the duration of each of them.  Returns None for other images.
+    pub fn lazy_frame_info(&self) -> Option<((u32, u32), &[Duration])> {
+        #[cfg(feature = "use_image")]
+        if let Some(lazy) = &self.lazy {
+            return Some(((lazy.width, lazy.height), &lazy.durations));
+        }
+        None
+    }
+
+    /// Decode an image from `reader`, avoiding holding both the encoded
+    /// and decoded representations in memory at the same time.
+    /// This is preferable to reading a large file into memory and
+    /// passing it to `with_raw_data`.
+    #[cfg(feature = "use_image")]
+    pub fn from_reader<R: BufRead + Seek>(reader: R) -> crate::Result<Self> {
+        let start = Instant::now();
+        let data = ImageDataType::decode_reader(reader, &DecodeOptions::default());
+        let elapsed = start.elapsed();
+        record_decode(elapsed);
+        let mut image = Self::with_data(data?);
+        image.decode_duration = Some(elapsed);
+        Ok(image)
+    }
+
+    /// Returns the in-memory footprint.  For an animation created by
+    /// `with_raw_data_lazy`, this is the size of the encoded data plus
+    /// that of the frames that are currently cached.
+    pub fn len(&self) -> usize {
+        #[cfg(feature = "use_image")]
+        if let Some(lazy) = &self.lazy {
+            return lazy.encoded.len() + lazy.frames.lock().unwrap().bytes;
+        }
+        self.data().len()
+    }
+
+    /// Returns a copy of this image resampled to `width` x `height`;
+    /// see `ImageDataType::resize`.
+    /// The copy is retained, so that drawing the image at the same size
+    /// again doesn't resample it again, until it is discarded by
+    /// `evict_scaled`.  `frame` identifies the frame that is being drawn
+    /// and is recorded as the time at which the copy was last used.
+    /// Returns `None` if the image cannot be resized.
+    /// The image data must not be locked by the caller.
+    #[cfg(feature = "use_image")]
+    pub fn scaled(&self, width: u32, height: u32, frame: u64) -> Option<Arc<ImageDataType>> {
+        let mut scaled = self.scaled.lock().unwrap();
+        if let Some(entry) = scaled.get_mut(&(width, height)) {
+            entry.last_used = entry.last_used.max(frame);
+            return Some(Arc::clone(&entry.image));
+        }
+
+        let image = Arc::new(self.data().resize(width, height)?);
+        IMAGE_MEMORY.fetch_add(image.len(), Ordering::Relaxed);
+        scaled.insert(
+            (width, height),
+            ScaledEntry {
+                image: Arc::clone(&image),
+                last_used: frame,
+            },
+        );
+        Some(image)
+    }
+
+    /// Discards the copies retained by `scaled` that were last used
+    /// in a frame prior to `frame`.
+    /// Returns true if any copies are still retained.
+    #[cfg(feature = "use_image")]
+    pub fn evict_scaled(&self, frame: u64) -> bool {
+        let mut scaled = self.scaled.lock().unwrap();
+        scaled.retain(|_, entry| {
+            let keep = entry.last_used >= frame;
+            if !keep {
+                IMAGE_MEMORY.fetch_sub(entry.image.len(), Ordering::Relaxed);
+            }
+            keep
+        });
+        !scaled.is_empty()
+    }
+
+    pub fn data(&self) -> MutexGuard<ImageDataType> {
+        self.data.lock().unwrap()
+    }
+
+    /// Pauses or resumes playback of an animated image.
+    /// This can be used through a shared `Arc<ImageData>`, so that
+    /// all of the places that display the image are affected.
+    pub fn set_playing(&self, playing: bool) {
+        self.playing.store(playing, Ordering::Relaxed);
+    }
+
+    /// Returns true unless playback has been paused by `set_playing`
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::Relaxed)
+    }
+
+    /// Returns the index of the frame to show once `elapsed` time has
+    /// passed since the start of the animation; see
+    /// `ImageDataType::frame_at`.
+    /// While playback is paused, the frame that was most recently
+    /// returned is returned again, so that the animation holds still.
+    /// For an animation created by `with_raw_data_lazy`, decoding of the
+    /// frame is started if necessary, so that `frame_data` can return it
+    /// promptly.
+    pub fn current_frame(&self, elapsed: Duration) -> usize {
+        if !self.is_playing() {
+            return self.shown_frame.load(Ordering::Relaxed);
+        }
+        #[cfg(feature = "use_image")]
+        let lazy_frame = self.lazy.as_ref().map(|lazy| {
+            let frame = frame_index_at(&lazy.durations, elapsed);
+            self.decode_lazy_frame(lazy, frame);
+            frame
+        });
+        #[cfg(not(feature = "use_image"))]
+        let lazy_frame = None;
+        let frame = lazy_frame.unwrap_or_else(|| self.data().frame_at(elapsed));
+        self.shown_frame.store(frame, Ordering::Relaxed);
+        frame
+    }
+
+    /// Returns whether the frame at `idx` has been decoded, without
+    /// copying it; see `ImageDataType::frame_dimensions`.
+    /// For an animation created by `with_raw_data_lazy`, this is true
+    /// once the frame is in the cache, and decoding is started if it
+    /// is not; see `frame_data`.
+    pub fn has_frame(&self, idx: usize) -> bool {
+        #[cfg(feature = "use_image")]
+        if let Some(lazy) = &self.lazy {
+            return self.decode_lazy_frame(lazy, idx).is_some();
+        }
+        self.data().frame_dimensions(idx).is_some()
+    }
+
+    /// For an animation created by `with_raw_data_lazy`, returns the
+    /// RGBA pixels of the frame at `idx`.  The frames are decoded on a
+    /// background thread and retained in a bounded cache: `None` is
+    /// returned, and decoding is started, if the frame is not yet in the
+    /// cache, so this never blocks on decoding.
+    /// Returns `None` for other images, whose frames can be borrowed
+    /// from `data` instead.
+    pub fn frame_data(&self, idx: usize) -> Option<Arc<[u8]>> {
+        #[cfg(feature = "use_image")]
+        if let Some(lazy) = &self.lazy {
+            return self.decode_lazy_frame(lazy, idx);
+        }
+        #[cfg(not(feature = "use_image"))]
+        let _ = idx;
+        None
+    }
+
+    /// Returns the frame at `idx` from the cache of a lazy animation,
+    /// or starts decoding it on a background thread if it is missing,
+    /// has not previously failed to decode, and no decode is already
+    /// in progress.
+    #[cfg(feature = "use_image")]
+    fn decode_lazy_frame(&self, lazy: &LazyAnimation, idx: usize) -> Option<Arc<[u8]>> {
+        if idx >= lazy.durations.len() {
+            return None;
+        }
+        {
+            let mut frames = lazy.frames.lock().unwrap();
+            if let Some(data) = frames.get(idx) {
+                return Some(data);
+            }
+            if frames.has_failed(idx) {
+                return None;
+            }
+        }
+        if lazy.decoding.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        let encoded = Arc::clone(&lazy.encoded);
+        let options = lazy.options.clone();
+        let frames = Arc::clone(&lazy.frames);
+        let decoding = Arc::clone(&lazy.decoding);
+        let spawned = std::thread::Builder::new()
+            .name("lazy-animation".to_string())
+            .spawn(move || {
+                decode_lazy_frames(&encoded, idx, &options, &frames);
+                decoding.store(false, Ordering::Release);
+            });
+        if let Err(err) = spawned {
+            log::error!("Unable to spawn thread to decode animation frames: {err:#}");
+            lazy.decoding.store(false, Ordering::Release);
+        }
+        None
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Returns an identifier that is unique to this instance for the
+    /// lifetime of the process, unlike `hash` and `content_id`, which
+    /// are shared by instances with identical content.
+    /// This is the value passed to `on_image_drop` hooks.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns a compact identifier derived from the content hash, or
+    /// the id that was passed to `with_data_and_id`.
+    /// Identical images have the same id, both within a session and
+    /// across sessions, and it is preserved by serialization, which
+    /// makes it suitable for serialized state that needs to be
+    /// reproducible.  Distinct images are not strictly guaranteed to
+    /// have distinct ids; use `hash` where that matters.
+    pub fn content_id(&self) -> u64 {
+        if let Some(id) = self.content_id {
+            return id;
+        }
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&self.hash[0..8]);
+        u64::from_le_bytes(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_distinguishes_dimensions() {
+        let data = vec![0xffu8; 4 * 4 * 4];
+        let a = ImageDataType::new_single_frame(4, 4, data.clone());
+        let b = ImageDataType::new_single_frame(2, 8, data.clone());
+        assert_ne!(a.compute_hash(), b.compute_hash());
+
+        let c = ImageDataType::new_single_frame(4, 4, data);
+        assert_eq!(a.compute_hash(), c.compute_hash());
+    }
+
+    #[test]
+    fn hash_distinguishes_variants() {
+        let data = vec![0xffu8; 4 * 4 * 4];
+        let still = ImageDataType::new_single_frame(4, 4, data.clone());
+        let encoded = ImageDataType::EncodedFile(data.clone());
+        let anim = ImageDataType::AnimRgba8 {
+            width: 4,
+            height: 4,
+            durations: vec![],
+            hashes: vec![ImageDataType::hash_bytes(&data)],
+            frames: vec![data],
+            frame_dimensions: vec![(4, 4)],
+        };
+        assert_ne!(still.compute_hash(), encoded.compute_hash());
+        assert_ne!(still.compute_hash(), anim.compute_hash());
+        assert_ne!(encoded.compute_hash(), anim.compute_hash());
+
+        let premultiplied = ImageDataType::Rgba8 {
+            width: 4,
+            height: 4,
+            data: vec![0xffu8; 4 * 4 * 4],
+            hash: [0; 32],
+            premultiplied: true,
+            icc_profile: None,
+        };
+        assert_ne!(still.compute_hash(), premultiplied.compute_hash());
+    }
+
+    #[test]
+    fn raw_data_hash_matches_encoded_file() {
+        let data = b"not really an image".to_vec();
+        assert_eq!(
+            ImageData::with_raw_data(data.clone()).hash(),
+            ImageData::with_data(ImageDataType::EncodedFile(data)).hash()
+        );
+    }
+
+    #[test]
+    fn frames_iterator() {
+        let frame = |v: u8| vec![v; 2 * 2 * 4];
+        let durations = vec![
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            Duration::from_millis(30),
+        ];
+        let frames = vec![frame(1), frame(2), frame(3)];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 2,
+            height: 2,
+            durations: durations.clone(),
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frames: frames.clone(),
+            frame_dimensions: vec![(2, 2); 3],
+        };
+        let collected: Vec<_> = anim.frames().collect();
+        assert_eq!(collected.len(), frames.len());
+        for (idx, (duration, data)) in collected.into_iter().enumerate() {
+            assert_eq!(duration, durations[idx]);
+            assert_eq!(anim.frame_dimensions(idx), Some((2, 2)));
+            assert_eq!(data, frames[idx].as_slice());
+        }
+
+        let still = ImageDataType::new_single_frame(2, 2, frame(4));
+        let collected: Vec<_> = still.frames().collect();
+        assert_eq!(collected, vec![(Duration::ZERO, frame(4).as_slice())]);
+
+        let encoded = ImageDataType::EncodedFile(frame(5));
+        assert_eq!(encoded.frames().count(), 0);
+
+        let indexed = ImageDataType::Indexed8 {
+            palette: vec![[1, 2, 3, 0xff]],
+            indices: vec![0; 2 * 2],
+            width: 2,
+            height: 2,
+        };
+        assert_eq!(indexed.frames().count(), 0);
+        let converted = indexed.to_rgba8();
+        let collected: Vec<_> = converted.frames().collect();
+        assert_eq!(
+            collected,
+            vec![(Duration::ZERO, [1, 2, 3, 0xff].repeat(4).as_slice())]
+        );
+    }
+
+    #[test]
+    fn content_id_is_deterministic() {
+        let a = ImageData::with_data(ImageDataType::placeholder());
+        let b = ImageData::with_data(ImageDataType::placeholder());
+        assert_eq!(a.content_id(), b.content_id());
+
+        let c = ImageData::with_data(ImageDataType::new_single_frame(
+            1,
+            1,
+            vec![0xff, 0xff, 0xff, 0xff],
+        ));
+        assert_ne!(a.content_id(), c.content_id());
+    }
+
+    #[test]
+    fn assigned_content_id() {
+        let a = ImageData::with_data_and_id(ImageDataType::placeholder(), 42);
+        let b = ImageData::with_data_and_id(ImageDataType::placeholder(), 42);
+        assert_eq!(a.content_id(), 42);
+        assert_eq!(b.content_id(), 42);
+        // The instance ids, which are passed to the drop hooks, remain unique
+        assert_ne!(a.id(), b.id());
+    }
+
+    #[test]
+    fn average_color_checkerboard() {
+        let dark = [0x40, 0x20, 0x00, 0xff];
+        let light = [0xc0, 0xe0, 0xff, 0xff];
+        let mut data = vec![];
+        for y in 0..4 {
+            for x in 0..4 {
+                data.extend_from_slice(if (x + y) % 2 == 0 { &dark } else { &light });
+            }
+        }
+        let image = ImageDataType::new_single_frame(4, 4, data);
+        assert_eq!(image.average_color(), Some([0x80, 0x80, 0x80, 0xff]));
+
+        // Transparent pixels don't contribute to the color
+        let mut data = vec![];
+        for idx in 0..4 {
+            data.extend_from_slice(if idx % 2 == 0 { &dark } else { &[0, 0, 0, 0] });
+        }
+        let image = ImageDataType::new_single_frame(2, 2, data);
+        assert_eq!(image.average_color(), Some([0x40, 0x20, 0x00, 0x80]));
+
+        assert_eq!(ImageDataType::EncodedFile(vec![]).average_color(), None);
+    }
+
+    #[test]
+    fn adjust_hsb_matches_shader() {
+        let pixel = |color: [u8; 4], transform: (f32, f32, f32)| {
+            ImageDataType::new_single_frame(1, 1, color.to_vec())
+                .adjust_hsb(transform.0, transform.1, transform.2)
+                .pixel_at(0, 0)
+                .unwrap()
+        };
+        let green = [0, 0xff, 0, 0xff];
+        let blue = [0, 0, 0xff, 0xff];
+        let gray = [0x80, 0x80, 0x80, 0xff];
+
+        assert_eq!(pixel(gray, (1.0, 1.0, 1.0)), gray);
+        // Green has a hue of 1/3; doubling it gives the 2/3 of blue
+        assert_eq!(pixel(green, (2.0, 1.0, 1.0)), blue);
+        assert_eq!(pixel(blue, (0.5, 1.0, 1.0)), green);
+        // Desaturating a fully bright color gives white
+        assert_eq!(pixel(blue, (1.0, 0.0, 1.0)), [0xff, 0xff, 0xff, 0xff]);
+        // Halving the brightness halves the linear value; the alpha
+        // channel is preserved
+        let dimmed = pixel([0xff, 0, 0, 0x80], (1.0, 1.0, 0.5));
+        let expected = LinearRgba::with_components(0.5, 0.0, 0.0, 1.0)
+            .srgba_pixel()
+            .as_rgba();
+        assert_eq!(dimmed, [expected.0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn adjust_hsb_premultiplied() {
+        let straight = ImageDataType::new_single_frame(1, 1, vec![0, 0xff, 0, 0x80]);
+        let expected = straight.clone().adjust_hsb(2.0, 1.0, 1.0).premultiply();
+        let adjusted = straight.premultiply().adjust_hsb(2.0, 1.0, 1.0);
+        assert!(
+            adjusted == expected,
+            "got {:?}, expected {:?}",
+            adjusted.pixel_at(0, 0),
+            expected.pixel_at(0, 0)
+        );
+    }
+
+    #[test]
+    fn to_grayscale_uses_luma() {
+        let image = ImageDataType::new_single_frame(2, 1, vec![0xff, 0, 0, 0xff, 0, 0xff, 0, 0x40]);
+        let gray = image.to_grayscale();
+        let red_luma = (0.299f32 * 255.).round() as u8;
+        let green_luma = (0.587f32 * 255.).round() as u8;
+        assert_eq!(
+            gray.pixel_at(0, 0),
+            Some([red_luma, red_luma, red_luma, 0xff])
+        );
+        assert_eq!(
+            gray.pixel_at(1, 0),
+            Some([green_luma, green_luma, green_luma, 0x40])
+        );
+    }
+
+    #[test]
+    fn update_region_in_bounds() {
+        let mut image = ImageDataType::new_single_frame(4, 3, vec![0u8; 4 * 3 * 4]);
+        let original_hash = image.compute_hash();
+        let red = [0xff, 0, 0, 0xff];
+        let patch: Vec<u8> = red.repeat(2 * 2);
+
+        let dirty = image.update_region(1, 1, 2, 2, &patch).unwrap();
+        assert_eq!(
+            dirty,
+            DirtyRect {
+                x: 1,
+                y: 1,
+                width: 2,
+                height: 2
+            }
+        );
+        assert_ne!(image.compute_hash(), original_hash);
+        for y in 0..3 {
+            for x in 0..4 {
+                let inside = (1..3).contains(&x) && (1..3).contains(&y);
+                let expected = if inside { red } else { [0; 4] };
+                assert_eq!(image.pixel_at(x, y), Some(expected), "pixel ({x},{y})");
+            }
+        }
+        match &image {
+            ImageDataType::Rgba8 { hash, .. } => {
+                assert_eq!(*hash, image.compute_hash());
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_region_rejects_out_of_bounds() {
+        let mut image = ImageDataType::new_single_frame(4, 3, vec![0u8; 4 * 3 * 4]);
+        let before = image.clone();
+
+        assert!(image.update_region(3, 0, 2, 1, &[0u8; 2 * 4]).is_err());
+        assert!(image.update_region(0, 2, 1, 2, &[0u8; 2 * 4]).is_err());
+        assert!(image
+            .update_region(u32::MAX, 0, 2, 1, &[0u8; 2 * 4])
+            .is_err());
+        // In bounds, but the data is the wrong length
+        assert!(image.update_region(0, 0, 2, 2, &[0u8; 3 * 4]).is_err());
+        assert!(image == before);
+
+        let mut encoded = ImageDataType::EncodedFile(vec![]);
+        assert!(encoded.update_region(0, 0, 0, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn crop_still_and_animation() {
+        // Each pixel holds its own coordinates, so that we can verify
+        // which pixels were extracted
+        let coords = |width: u32, height: u32, frame: u8| -> Vec<u8> {
+            (0..height)
+                .flat_map(|y| (0..width).flat_map(move |x| [x as u8, y as u8, frame, 0xff]))
+                .collect()
+        };
+
+        let image = ImageDataType::new_single_frame(4, 3, coords(4, 3, 0));
+        let cropped = image.crop(1, 1, 2, 2).unwrap();
+        assert_eq!(cropped.frame_dimensions(0), Some((2, 2)));
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(
+                    cropped.pixel_at(x, y),
+                    Some([x as u8 + 1, y as u8 + 1, 0, 0xff]),
+                    "pixel ({x},{y})"
+                );
+            }
+        }
+        match &cropped {
+            ImageDataType::Rgba8 { hash, .. } => {
+                assert_eq!(*hash, cropped.compute_hash());
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        // The second frame is smaller than the animation, so the part
+        // of the crop that lies outside of it is transparent
+        let frames = vec![coords(4, 3, 1), coords(2, 2, 2)];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 4,
+            height: 3,
+            durations: vec![Duration::from_millis(10); 2],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frames,
+            frame_dimensions: vec![(4, 3), (2, 2)],
+        };
+        match anim.crop(1, 1, 2, 2).unwrap() {
+            ImageDataType::AnimRgba8 {
+                width,
+                height,
+                durations,
+                frames,
+                hashes,
+                frame_dimensions,
+            } => {
+                assert_eq!((width, height), (2, 2));
+                assert_eq!(durations.len(), 2);
+                assert_eq!(frame_dimensions, vec![(2, 2), (2, 2)]);
+                assert_eq!(
+                    frames[0],
+                    vec![1, 1, 1, 0xff, 2, 1, 1, 0xff, 1, 2, 1, 0xff, 2, 2, 1, 0xff]
+                );
+                assert_eq!(
+                    frames[1],
+                    vec![1, 1, 2, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+                );
+                for (frame, hash) in frames.iter().zip(hashes) {
+                    assert_eq!(hash, ImageDataType::hash_bytes(frame));
+                }
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn crop_rejects_out_of_bounds() {
+        let image = ImageDataType::new_single_frame(4, 3, vec![0u8; 4 * 3 * 4]);
+        assert!(image.clone().crop(3, 0, 2, 1).is_err());
+        assert!(image.clone().crop(0, 2, 1, 2).is_err());
+        assert!(image.clone().crop(u32::MAX, 0, 2, 1).is_err());
+        assert!(image.clone().crop(0, 0, 0, 1).is_err());
+        assert!(image.crop(0, 0, 4, 3).is_ok());
+
+        assert!(ImageDataType::EncodedFile(vec![1, 2, 3])
+            .crop(0, 0, 1, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn color_key_white() {
+        // A white 3x3 image with a red pixel in the middle, and a
+        // near-white, already transparent, pixel in the top right
+        let mut data = vec![0xffu8; 3 * 3 * 4];
+        data[4 * 4..4 * 4 + 4].copy_from_slice(&[0xff, 0, 0, 0xff]);
+        data[2 * 4..2 * 4 + 4].copy_from_slice(&[0xfe, 0xfe, 0xfe, 0]);
+        data[6 * 4..6 * 4 + 4].copy_from_slice(&[0xfa, 0xfd, 0xfe, 0xff]);
+        let image = ImageDataType::new_single_frame(3, 3, data);
+        let original_hash = image.compute_hash();
+
+        let keyed = image.apply_color_key([0xff, 0xff, 0xff], 2);
+        assert_ne!(keyed.compute_hash(), original_hash);
+        match keyed {
+            ImageDataType::Rgba8 { data, hash, .. } => {
+                assert_eq!(hash, ImageDataType::hash_rgba8(3, 3, false, &data));
+                let alpha = |idx: usize| data[idx * 4 + 3];
+                // Corner pixels are keyed out
+                assert_eq!(alpha(0), 0);
+                assert_eq!(alpha(8), 0);
+                // Already transparent stays transparent
+                assert_eq!(alpha(2), 0);
+                // Outside of the tolerance in the red channel
+                assert_eq!(alpha(6), 0xff);
+                // The red pixel is left alone
+                assert_eq!(&data[4 * 4..4 * 4 + 4], &[0xff, 0, 0, 0xff]);
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn color_key_premultiplied() {
+        // Half transparent white, which is stored as gray when
+        // premultiplied, and an opaque gray pixel
+        let image = ImageDataType::new_single_frame(
+            2,
+            1,
+            vec![0xff, 0xff, 0xff, 0x80, 0x80, 0x80, 0x80, 0xff],
+        )
+        .premultiply();
+        match image.apply_color_key([0xff, 0xff, 0xff], 2) {
+            ImageDataType::Rgba8 {
+                data,
+                premultiplied: true,
+                ..
+            } => {
+                assert_eq!(&data[0..4], &[0, 0, 0, 0]);
+                assert_eq!(&data[4..8], &[0x80, 0x80, 0x80, 0xff]);
+            }
+            other => panic!("expected premultiplied Rgba8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rows_and_pixel_at() {
+        // 3x2 image where each pixel is [x, y, x + y, 0xff]
+        let mut data = vec![];
+        for y in 0..2u8 {
+            for x in 0..3u8 {
+                data.extend_from_slice(&[x, y, x + y, 0xff]);
+            }
+        }
+        let image = ImageDataType::new_single_frame(3, 2, data.clone());
+
+        let rows: Vec<&[u8]> = image.rows().unwrap().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], &data[0..12]);
+        assert_eq!(rows[1], &data[12..24]);
+
+        assert_eq!(image.pixel_at(0, 0), Some([0, 0, 0, 0xff]));
+        assert_eq!(image.pixel_at(2, 1), Some([2, 1, 3, 0xff]));
+        assert_eq!(image.pixel_at(1, 1), Some([1, 1, 2, 0xff]));
+        assert_eq!(image.pixel_at(3, 0), None);
+        assert_eq!(image.pixel_at(0, 2), None);
+
+        let encoded = ImageDataType::EncodedFile(data);
+        assert!(encoded.rows().is_none());
+        assert_eq!(encoded.pixel_at(0, 0), None);
+    }
+
+    #[test]
+    fn frame_count_and_is_animated() {
+        let frame = |v: u8| vec![v; 2 * 2 * 4];
+
+        let still = ImageDataType::new_single_frame(2, 2, frame(1));
+        assert_eq!(still.frame_count(), 1);
+        assert!(!still.is_animated());
+
+        let frames = vec![frame(1), frame(2)];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 2,
+            height: 2,
+            durations: vec![Duration::from_millis(10); 2],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(2, 2); 2],
+            frames,
+        };
+        assert_eq!(anim.frame_count(), 2);
+        assert!(anim.is_animated());
+
+        let single = ImageDataType::AnimRgba8 {
+            width: 2,
+            height: 2,
+            durations: vec![Duration::from_millis(10)],
+            hashes: vec![ImageDataType::hash_bytes(&frame(1))],
+            frame_dimensions: vec![(2, 2)],
+            frames: vec![frame(1)],
+        };
+        assert_eq!(single.frame_count(), 1);
+        assert!(!single.is_animated());
+
+        let encoded = ImageDataType::EncodedFile(frame(3));
+        assert_eq!(encoded.frame_count(), 0);
+        assert!(!encoded.is_animated());
+
+        // The frames of an animation are counted from its headers
+        #[cfg(feature = "use_image")]
+        {
+            let encoded = ImageDataType::EncodedFile(lazy_test_gif());
+            assert_eq!(encoded.frame_count(), 10);
+            assert!(encoded.is_animated());
+            let image = ImageData::with_data(encoded);
+            assert!(!image.has_frame(0));
+        }
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn roundtrip_non_uniform_animation() {
+        let frames = vec![vec![1u8; 2 * 2 * 4], vec![2u8; 3 * 4]];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 3,
+            height: 2,
+            durations: vec![Duration::from_millis(10); 2],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frames,
+            frame_dimensions: vec![(2, 2), (3, 1)],
+        };
+        let data = varbincode::serialize(&anim).unwrap();
+        let decoded: ImageDataType = varbincode::deserialize(data.as_slice()).unwrap();
+        assert!(decoded == anim);
+        assert_eq!(decoded.frame_dimensions(0), Some((2, 2)));
+        assert_eq!(decoded.frame_dimensions(1), Some((3, 1)));
+        assert_eq!(decoded.frame_dimensions(2), None);
+        assert_eq!(decoded.compute_hash(), anim.compute_hash());
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn roundtrip_image_cell_render_hint() {
+        let data = Arc::new(ImageData::with_data(ImageDataType::placeholder()));
+        let hint = RenderHint {
+            filter: ImageFilter::Nearest,
+            mipmaps: true,
+        };
+        let cell = ImageCell::new(
+            TextureCoordinate::new_f32(0.0, 0.0),
+            TextureCoordinate::new_f32(1.0, 1.0),
+            data,
+        )
+        .with_render_hint(Some(hint));
+        assert_eq!(cell.render_hint(), Some(hint));
+
+        let encoded = varbincode::serialize(&cell).unwrap();
+        let decoded: ImageCell = varbincode::deserialize(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, cell);
+        assert_eq!(decoded.render_hint(), Some(hint));
+
+        let mut plain = decoded.clone();
+        plain.set_render_hint(None);
+        assert!(!plain.same_content(&decoded));
+    }
+
+    #[test]
+    fn image_cell_try_new_rejects_nan() {
+        let data = Arc::new(ImageData::with_data(ImageDataType::placeholder()));
+        assert_eq!(
+            ImageCell::try_new((f32::NAN, 0.0), (1.0, 1.0), Arc::clone(&data)).unwrap_err(),
+            ImageCellError::NaN
+        );
+        assert_eq!(
+            ImageCell::try_new((0.0, 0.0), (1.0, f32::NAN), data).unwrap_err(),
+            ImageCellError::NaN
+        );
+    }
+
+    #[test]
+    fn image_cell_try_new_rejects_out_of_range() {
+        let data = Arc::new(ImageData::with_data(ImageDataType::placeholder()));
+        assert_eq!(
+            ImageCell::try_new((-0.5, 0.0), (1.0, 1.0), Arc::clone(&data)).unwrap_err(),
+            ImageCellError::OutOfRange(-0.5)
+        );
+        assert_eq!(
+            ImageCell::try_new((0.0, 0.0), (1.0, 1.5), Arc::clone(&data)).unwrap_err(),
+            ImageCellError::OutOfRange(1.5)
+        );
+        assert!(ImageCell::try_new((0.0, 0.25), (0.5, 1.0), data).is_ok());
+    }
+
+    #[test]
+    fn image_cell_new_keeps_coordinates() {
+        let data = Arc::new(ImageData::with_data(ImageDataType::placeholder()));
+        let cell = ImageCell::new(
+            TextureCoordinate::new_f32(-1.0, 0.5),
+            TextureCoordinate::new_f32(2.0, 1.0),
+            data,
+        );
+        assert_eq!(cell.top_left(), TextureCoordinate::new_f32(-1.0, 0.5));
+        assert_eq!(cell.bottom_right(), TextureCoordinate::new_f32(2.0, 1.0));
+    }
+
+    #[test]
+    fn poster_frame() {
+        let frames: Vec<Vec<u8>> = (0..8u8).map(|v| vec![v; 2 * 2 * 4]).collect();
+        let anim = ImageDataType::AnimRgba8 {
+            width: 2,
+            height: 2,
+            durations: vec![Duration::from_millis(100); frames.len()],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(2, 2); frames.len()],
+            frames: frames.clone(),
+        };
+        match anim.poster_frame().unwrap() {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                data,
+                hash,
+                ..
+            } => {
+                assert_eq!((width, height), (2, 2));
+                assert_eq!(data, frames[2]);
+                assert_eq!(hash, ImageDataType::hash_rgba8(2, 2, false, &frames[2]));
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        let still = ImageDataType::placeholder();
+        assert!(still.poster_frame().unwrap() == still);
+
+        assert!(ImageDataType::EncodedFile(vec![]).poster_frame().is_none());
+    }
+
+    #[test]
+    fn composite_over_checker() {
+        // A 4x1 image: transparent, opaque red, transparent, half
+        // transparent white
+        let data = vec![
+            0, 0, 0, 0, //
+            0xff, 0, 0, 0xff, //
+            0x12, 0x34, 0x56, 0, //
+            0xff, 0xff, 0xff, 0x80,
+        ];
+        let image = ImageDataType::new_single_frame(4, 1, data);
+        match image.composite_over_checker(2) {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                hash,
+                premultiplied,
+                ..
+            } => {
+                assert_eq!((width, height), (4, 1));
+                assert!(!premultiplied);
+                assert_eq!(hash, ImageDataType::hash_rgba8(4, 1, false, &data));
+                // The first tile is light, the second is dark
+                assert_eq!(&data[0..4], &[0xcc, 0xcc, 0xcc, 0xff]);
+                assert_eq!(&data[4..8], &[0xff, 0, 0, 0xff]);
+                assert_eq!(&data[8..12], &[0x99, 0x99, 0x99, 0xff]);
+                // Half of the way from the dark tile to white
+                assert_eq!(&data[12..16], &[0xcc, 0xcc, 0xcc, 0xff]);
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        let encoded = ImageDataType::EncodedFile(vec![1, 2, 3]);
+        assert!(encoded.composite_over_checker(8) == encoded);
+    }
+
+    #[test]
+    fn paused_animation_holds_frame() {
+        let frames: Vec<Vec<u8>> = (0..4u8).map(|v| vec![v; 2 * 2 * 4]).collect();
+        let image = ImageData::with_data(ImageDataType::AnimRgba8 {
+            width: 2,
+            height: 2,
+            durations: vec![Duration::from_millis(100); frames.len()],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(2, 2); frames.len()],
+            frames,
+        });
+        let ms = Duration::from_millis;
+
+        assert!(image.is_playing());
+        assert_eq!(image.current_frame(ms(50)), 0);
+        assert_eq!(image.current_frame(ms(150)), 1);
+
+        image.set_playing(false);
+        assert!(!image.is_playing());
+        assert_eq!(image.current_frame(ms(250)), 1);
+        assert_eq!(image.current_frame(ms(350)), 1);
+
+        image.set_playing(true);
+        assert_eq!(image.current_frame(ms(250)), 2);
+        assert_eq!(image.current_frame(ms(450)), 0);
+    }
+
+    #[cfg(feature = "use_image")]
+    fn zero_delay_gif() -> Vec<u8> {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let mut data = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for color in [[0xff, 0, 0, 0xff], [0, 0xff, 0, 0xff]] {
+                let buffer = RgbaImage::from_pixel(2, 2, Rgba(color));
+                let frame = Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(0, 1));
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        data
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_unsniffable_format() {
+        use image::codecs::tga::TgaEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+
+        let pixels: Vec<u8> = [[0xff, 0, 0, 0xff], [0, 0xff, 0, 0x80]].concat();
+        let mut data = vec![];
+        TgaEncoder::new(&mut data)
+            .write_image(&pixels, 2, 1, ExtendedColorType::Rgba8)
+            .unwrap();
+        assert!(image::guess_format(&data).is_err());
+
+        match ImageDataType::EncodedFile(data.clone()).decode() {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => {
+                assert_eq!((width, height), (2, 1));
+                assert_eq!(data, pixels);
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        let limited = ImageDataType::EncodedFile(data).decode_with_options(&DecodeOptions {
+            max_pixels: Some(1),
+            ..Default::default()
+        });
+        assert!(matches!(limited, ImageDataType::EncodedFile(_)));
+
+        let garbage = vec![0x42u8; 16];
+        assert!(
+            ImageDataType::EncodedFile(garbage.clone()).decode()
+                == ImageDataType::EncodedFile(garbage)
+        );
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_cursor_file() {
+        use image::codecs::ico::IcoEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+
+        let pixels: Vec<u8> = [[0xff, 0, 0, 0xff]; 4].concat();
+        let mut data = vec![];
+        IcoEncoder::new(&mut data)
+            .write_image(&pixels, 2, 2, ExtendedColorType::Rgba8)
+            .unwrap();
+        // A .cur file has the same layout as a .ico, but type 2
+        data[2] = 2;
+        assert!(image::guess_format(&data).is_err());
+
+        match ImageDataType::EncodedFile(data.clone()).decode() {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => {
+                assert_eq!((width, height), (2, 2));
+                assert_eq!(data, pixels);
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        match ImageDataType::EncodedFile(data).try_decode_with_options(&DecodeOptions {
+            max_pixels: Some(3),
+            ..Default::default()
+        }) {
+            Err(err) => assert!(
+                matches!(
+                    err.reason,
+                    DecodeErrorReason::TooLarge {
+                        width: 2,
+                        height: 2,
+                        ..
+                    }
+                ),
+                "{:?}",
+                err.reason
+            ),
+            Ok(decoded) => panic!("expected TooLarge, got {:?}", decoded),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn lazy_animation_stays_within_budget() {
+        let data = lazy_test_gif();
+        let expected = match ImageDataType::EncodedFile(data.clone()).decode() {
+            ImageDataType::AnimRgba8 { frames, .. } => frames,
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        };
+        let frame_len = 4 * 4 * 4;
+
+        // Small enough to decode eagerly
+        let eager = ImageData::with_raw_data_lazy(data.clone(), 10 * frame_len);
+        assert!(eager.lazy.is_none());
+        assert_eq!(eager.data().frame_count(), 10);
+
+        let budget = 3 * frame_len;
+        let image = ImageData::with_raw_data_lazy(data, budget);
+        assert!(matches!(&*image.data(), ImageDataType::EncodedFile(_)));
+        assert_eq!(
+            image.lazy_frame_info(),
+            Some(((4, 4), &[Duration::from_millis(100); 10][..]))
+        );
+        let cached_bytes = || image.lazy.as_ref().unwrap().frames.lock().unwrap().bytes;
+        let encoded_len = image.lazy.as_ref().unwrap().encoded.len();
+        // Frames are decoded in the background, so poll for them
+        let wait_for_frame = |idx: usize| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            loop {
+                if let Some(data) = image.frame_data(idx) {
+                    return data;
+                }
+                assert!(Instant::now() < deadline, "frame {} was not decoded", idx);
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        // Play through the animation twice
+        for _ in 0..2 {
+            for (idx, frame) in expected.iter().enumerate() {
+                let elapsed = Duration::from_millis(100 * idx as u64 + 50);
+                assert_eq!(image.current_frame(elapsed), idx);
+                assert_eq!(&*wait_for_frame(idx), frame.as_slice());
+                assert!(image.has_frame(idx));
+                assert!(cached_bytes() <= budget);
+                assert_eq!(image.len(), encoded_len + cached_bytes());
+            }
+        }
+        // Random access also works
+        assert_eq!(&*wait_for_frame(1), expected[1].as_slice());
+        assert!(cached_bytes() <= budget);
+        assert!(image.frame_data(10).is_none());
+        assert!(!image.has_frame(10));
+    }
+
+    /// A 4x4 GIF with 10 frames of 100ms each
+    #[cfg(feature = "use_image")]
+    fn lazy_test_gif() -> Vec<u8> {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let mut data = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for i in 0..10u8 {
+                let buffer = RgbaImage::from_pixel(4, 4, Rgba([i * 20, 0xff - i * 20, 0, 0xff]));
+                let frame = Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1));
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        data
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn lazy_animation_uses_decode_options() {
+        let options = DecodeOptions {
+            max_dimensions: Some((2, 2)),
+            min_frame_delay: Duration::from_millis(150),
+            ..Default::default()
+        };
+        // The scaled down frames would fit within this budget
+        assert!(ImageData::try_lazy_animation_with_options(
+            lazy_test_gif(),
+            2 * 2 * 4 * 10,
+            &options
+        )
+        .is_err());
+        let limited = DecodeOptions {
+            max_pixels: Some(15),
+            ..Default::default()
+        };
+        assert!(ImageData::try_lazy_animation_with_options(lazy_test_gif(), 0, &limited).is_err());
+
+        let image =
+            ImageData::try_lazy_animation_with_options(lazy_test_gif(), 2 * 2 * 4, &options)
+                .unwrap();
+        assert_eq!(
+            image.lazy_frame_info(),
+            Some(((2, 2), &[Duration::from_millis(150); 10][..]))
+        );
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let data = loop {
+            if let Some(data) = image.frame_data(3) {
+                break data;
+            }
+            assert!(Instant::now() < deadline, "frame was not decoded");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(data.len(), 2 * 2 * 4);
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn lazy_animation_remembers_failures() {
+        let mut image = ImageData::try_lazy_animation(lazy_test_gif(), 0).unwrap();
+        image.lazy.as_mut().unwrap().encoded = Arc::from(&[0x42u8; 16][..]);
+        let lazy = image.lazy.as_ref().unwrap();
+
+        assert!(image.frame_data(2).is_none());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while lazy.decoding.load(Ordering::Acquire) {
+            assert!(Instant::now() < deadline, "decoding did not finish");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(lazy.frames.lock().unwrap().failed_from, Some(0));
+
+        // No further attempt is made to decode the frames
+        assert!(image.frame_data(2).is_none());
+        assert!(!image.has_frame(5));
+        assert!(!lazy.decoding.load(Ordering::Acquire));
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn animation_headers() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let mut gif = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            for delay in [30, 70] {
+                let buffer = RgbaImage::from_pixel(5, 3, Rgba([0xff, 0, 0, 0xff]));
+                let frame = Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(delay, 1));
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        assert_eq!(
+            animation_header(&gif),
+            Some(AnimationHeader {
+                width: 5,
+                height: 3,
+                durations: vec![Duration::from_millis(30), Duration::from_millis(70)],
+            })
+        );
+
+        let durations = vec![Duration::from_millis(100), Duration::from_millis(250)];
+        let frames = vec![[0x10, 0x20, 0x30, 0xff].repeat(4 * 2); 2];
+        let apng = ImageDataType::AnimRgba8 {
+            width: 4,
+            height: 2,
+            durations: durations.clone(),
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(4, 2); 2],
+            frames,
+        }
+        .encode_apng()
+        .unwrap();
+        assert_eq!(
+            animation_header(&apng),
+            Some(AnimationHeader {
+                width: 4,
+                height: 2,
+                durations,
+            })
+        );
+
+        // A still PNG is not an animation
+        let png = ImageDataType::new_single_frame(1, 1, vec![0; 4])
+            .encode_apng()
+            .unwrap();
+        assert_eq!(animation_header(&png), None);
+
+        // An animated WebP container; the frame payloads are not examined
+        fn chunk(fourcc: &[u8], payload: &[u8]) -> Vec<u8> {
+            let mut chunk = fourcc.to_vec();
+            chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            chunk.extend_from_slice(payload);
+            if payload.len() % 2 == 1 {
+                chunk.push(0);
+            }
+            chunk
+        }
+        let anmf = |duration: u32| {
+            let mut payload = vec![0; 12];
+            payload.extend_from_slice(&duration.to_le_bytes()[..3]);
+            payload.extend_from_slice(&[0, 0xaa, 0xbb, 0xcc]);
+            chunk(b"ANMF", &payload)
+        };
+        let mut body = b"WEBP".to_vec();
+        body.extend(chunk(b"VP8X", &[0x02, 0, 0, 0, 199, 0, 0, 99, 0, 0]));
+        body.extend(chunk(b"ANIM", &[0; 6]));
+        body.extend(anmf(40));
+        body.extend(anmf(1000));
+        let webp = chunk(b"RIFF", &body);
+        assert_eq!(
+            animation_header(&webp),
+            Some(AnimationHeader {
+                width: 200,
+                height: 100,
+                durations: vec![Duration::from_millis(40), Duration::from_millis(1000)],
+            })
+        );
+
+        // Truncated data is rejected rather than misreported
+        assert_eq!(animation_header(&gif[..gif.len() - 4]), None);
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_clamps_frame_delay() {
+        let decoded = ImageDataType::EncodedFile(zero_delay_gif()).decode();
+        match decoded {
+            ImageDataType::AnimRgba8 { durations, .. } => {
+                assert_eq!(durations, vec![DecodeOptions::default().min_frame_delay; 2]);
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+
+        let raw =
+            ImageDataType::EncodedFile(zero_delay_gif()).decode_with_options(&DecodeOptions {
+                min_frame_delay: Duration::ZERO,
+                ..Default::default()
+            });
+        match raw {
+            ImageDataType::AnimRgba8 { durations, .. } => {
+                assert_eq!(durations, vec![Duration::ZERO; 2]);
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_single_frame() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let colors = [
+            [0xff, 0, 0, 0xff],
+            [0, 0xff, 0, 0xff],
+            [0, 0, 0xff, 0xff],
+            [0xff, 0xff, 0, 0xff],
+        ];
+        let mut data = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for (idx, color) in colors.iter().enumerate() {
+                let buffer = RgbaImage::from_pixel(4, 4, Rgba(*color));
+                let delay = Delay::from_numer_denom_ms(100 * (idx as u32 + 1), 1);
+                encoder
+                    .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+
+        let options = DecodeOptions::default();
+        let encoded = ImageDataType::EncodedFile(data);
+        let (duration, pixels) = encoded.decode_frame(2, &options).unwrap();
+        match encoded.clone().decode_with_options(&options) {
+            ImageDataType::AnimRgba8 {
+                frames, durations, ..
+            } => {
+                assert_eq!(duration, durations[2]);
+                assert_eq!(duration, Duration::from_millis(300));
+                assert!(pixels == frames[2]);
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+
+        assert!(encoded.decode_frame(4, &options).is_err());
+
+        let still = ImageDataType::EncodedFile(encode_png(2, 2));
+        let (duration, pixels) = still.decode_frame(0, &options).unwrap();
+        assert_eq!(duration, Duration::ZERO);
+        assert_eq!(pixels, [0, 0, 0xff, 0xff].repeat(4));
+        assert!(still.decode_frame(1, &options).is_err());
+
+        assert!(ImageDataType::new_single_frame(1, 1, vec![0; 4])
+            .decode_frame(0, &options)
+            .is_err());
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_truncated_gif() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let encode = |colors: &[[u8; 4]]| {
+            let mut data = vec![];
+            {
+                let mut encoder = GifEncoder::new(&mut data);
+                for color in colors {
+                    let buffer = RgbaImage::from_pixel(4, 4, Rgba(*color));
+                    let delay = Delay::from_numer_denom_ms(100, 1);
+                    encoder
+                        .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+                        .unwrap();
+                }
+            }
+            data
+        };
+        let colors = [[0xff, 0, 0, 0xff], [0, 0xff, 0, 0xff], [0, 0, 0xff, 0xff]];
+        let two = encode(&colors[..2]);
+        let three = encode(&colors);
+
+        // Everything up to the trailer of the two frame file is
+        // shared with the three frame file; cut the latter off part
+        // way through its third frame
+        let two_frames_len = two.len() - 1;
+        assert_eq!(two[..two_frames_len], three[..two_frames_len]);
+        let truncated = three[..two_frames_len + (three.len() - two_frames_len) / 2].to_vec();
+
+        let decoded = ImageDataType::EncodedFile(truncated.clone()).decode();
+        assert!(
+            decoded.is_animated(),
+            "expected AnimRgba8, got {:?}",
+            decoded
+        );
+        assert_eq!(decoded.frame_count(), 2);
+
+        let decoded = ImageDataType::decode_reader(
+            std::io::Cursor::new(truncated),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded.frame_count(), 2);
+    }
+
+    #[test]
+    fn image_drop_hook() {
+        let dropped = Arc::new(Mutex::new(vec![]));
+        let hook = {
+            let dropped = Arc::clone(&dropped);
+            on_image_drop(move |id| dropped.lock().unwrap().push(id))
+        };
+        let new_image = || {
+            ImageData::with_data(ImageDataType::new_single_frame(
+                1,
+                1,
+                vec![0x12, 0x34, 0x56, 0x78],
+            ))
+        };
+        let drops = |id: u64| dropped.lock().unwrap().iter().filter(|&&d| d == id).count();
+
+        // Instances with identical content share a hash but not an id
+        let image = new_image();
+        let twin = new_image();
+        assert_eq!(image.hash(), twin.hash());
+        assert_ne!(image.id(), twin.id());
+
+        let (id, twin_id) = (image.id(), twin.id());
+        drop(image);
+        assert_eq!(drops(id), 1);
+        assert_eq!(drops(twin_id), 0);
+        drop(twin);
+        assert_eq!(drops(twin_id), 1);
+
+        // No longer called once the hook has been unregistered
+        drop(hook);
+        let image = new_image();
+        let id = image.id();
+        drop(image);
+        assert_eq!(drops(id), 0);
+    }
+
+    #[test]
+    fn image_memory_accounting() {
+        // IMAGE_MEMORY is shared with the other tests, which may be
+        // running concurrently, so this checks the amount that each
+        // instance accounts for rather than the total
+        let still =
+            ImageData::with_data(ImageDataType::new_single_frame(4, 4, vec![0u8; 4 * 4 * 4]));
+        assert_eq!(still.accounted_len, 4 * 4 * 4);
+        let encoded = ImageData::with_data(ImageDataType::EncodedFile(vec![0u8; 100]));
+        assert_eq!(encoded.accounted_len, 100);
+
+        // Mutating the data doesn't change the amount that is
+        // removed from the total when the image is dropped
+        *encoded.data() = ImageDataType::EncodedFile(vec![0u8; 10]);
+        assert_eq!(encoded.accounted_len, 100);
+        assert_eq!(encoded.len(), 10);
+    }
+
+    #[test]
+    fn source_does_not_affect_hash() {
+        use std::io::Write;
+        let data = vec![1u8, 2, 3, 4];
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&data).unwrap();
+
+        let from_path = ImageData::with_raw_data_from_path(file.path()).unwrap();
+        assert_eq!(
+            from_path.source(),
+            Some(file.path().to_string_lossy().as_ref())
+        );
+
+        let from_uri = ImageData::with_raw_data(data.clone()).with_source("https://example.com/a");
+        assert_eq!(from_uri.source(), Some("https://example.com/a"));
+
+        let anonymous = ImageData::with_raw_data(data);
+        assert_eq!(anonymous.source(), None);
+        assert_eq!(from_path.hash(), anonymous.hash());
+        assert_eq!(from_uri.hash(), anonymous.hash());
+        assert!(from_path == from_uri);
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn roundtrip_image_data_source() {
+        let image = ImageData::with_data(ImageDataType::placeholder()).with_source("bg/1.png");
+        let data = varbincode::serialize(&image).unwrap();
+        let decoded: ImageData = varbincode::deserialize(data.as_slice()).unwrap();
+        assert_eq!(decoded.source(), Some("bg/1.png"));
+        assert_eq!(decoded.hash(), image.hash());
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn deserialized_image_data_is_accounted() {
+        let image =
+            ImageData::with_data(ImageDataType::new_single_frame(4, 4, vec![0u8; 4 * 4 * 4]));
+        let data = varbincode::serialize(&image).unwrap();
+        let decoded: ImageData = varbincode::deserialize(data.as_slice()).unwrap();
+        assert_eq!(decoded.accounted_len, 4 * 4 * 4);
+        assert_ne!(decoded.id(), image.id());
+        assert_eq!(decoded.content_id(), image.content_id());
+
+        let image = ImageData::with_data_and_id(ImageDataType::placeholder(), 42);
+        let data = varbincode::serialize(&image).unwrap();
+        let decoded: ImageData = varbincode::deserialize(data.as_slice()).unwrap();
+        assert_eq!(decoded.content_id(), 42);
+        assert_ne!(decoded.id(), image.id());
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn scaled_copies() {
+        let data = ImageData::with_data(ImageDataType::new_single_frame(
+            4,
+            4,
+            vec![0x80u8; 4 * 4 * 4],
+        ));
+
+        let half = data.scaled(2, 2, 1).unwrap();
+        match &*half {
+            ImageDataType::Rgba8 { width, height, .. } => assert_eq!((*width, *height), (2, 2)),
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+        assert!(Arc::ptr_eq(&half, &data.scaled(2, 2, 2).unwrap()));
+        let quarter = data.scaled(1, 1, 2).unwrap();
+        assert!(!Arc::ptr_eq(&half, &quarter));
+
+        // Both were used in frame 2, so they are retained
+        assert!(data.evict_scaled(2));
+        assert!(Arc::ptr_eq(&half, &data.scaled(2, 2, 3).unwrap()));
+        assert!(Arc::ptr_eq(&quarter, &data.scaled(1, 1, 2).unwrap()));
+
+        // Only the half size copy was used in frame 3
+        data.evict_scaled(3);
+        assert!(Arc::ptr_eq(&half, &data.scaled(2, 2, 3).unwrap()));
+        assert!(!Arc::ptr_eq(&quarter, &data.scaled(1, 1, 4).unwrap()));
+
+        data.evict_scaled(5);
+        assert!(!Arc::ptr_eq(&half, &data.scaled(2, 2, 5).unwrap()));
+        assert!(!data.evict_scaled(6));
+
+        let encoded = ImageData::with_data(ImageDataType::EncodedFile(vec![]));
+        assert!(encoded.scaled(2, 2, 1).is_none());
+    }
+
+    #[cfg(feature = "use_image")]
+    fn encode_png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0xff, 0xff]));
+        let mut data = vec![];
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        data
+    }
+
+    #[cfg(feature = "use_image")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::task::Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(result) => return result,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn preload_all_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.png");
+        let large = dir.path().join("large.png");
+        let missing = dir.path().join("missing.png");
+        std::fs::write(&small, encode_png(2, 2)).unwrap();
+        std::fs::write(&large, encode_png(8, 4)).unwrap();
+
+        let copy = dir.path().join("copy.png");
+        std::fs::write(&copy, encode_png(2, 2)).unwrap();
+
+        let results = block_on(ImageData::preload_all(
+            vec![small, missing, large, copy],
+            DecodeOptions::default(),
+        ));
+        assert_eq!(results.len(), 4);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.data().frame_dimensions(0), Some((2, 2)));
+        assert!(matches!(&*first.data(), ImageDataType::Rgba8 { .. }));
+        let err = results[1].as_ref().unwrap_err().to_string();
+        assert!(err.contains("missing.png"), "{}", err);
+        let last = results[2].as_ref().unwrap();
+        assert_eq!(last.data().frame_dimensions(0), Some((8, 4)));
+        assert!(matches!(&*last.data(), ImageDataType::Rgba8 { .. }));
+        // Identical content is interned
+        assert!(Arc::ptr_eq(first, results[3].as_ref().unwrap()));
+
+        assert!(block_on(ImageData::preload_all(vec![], DecodeOptions::default())).is_empty());
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_preserves_icc_profile() {
+        let profile: Vec<u8> = (0..=255u8).cycle().take(600).collect();
+
+        // The iCCP chunk holds a zlib stream; use a single stored
+        // (uncompressed) deflate block to avoid needing an encoder
+        let mut chunk = b"test profile\0\0".to_vec();
+        chunk.extend_from_slice(&[0x78, 0x01, 0x01]);
+        chunk.extend_from_slice(&(profile.len() as u16).to_le_bytes());
+        chunk.extend_from_slice(&(!(profile.len() as u16)).to_le_bytes());
+        chunk.extend_from_slice(&profile);
+        let (a, b) = profile.iter().fold((1u32, 0u32), |(a, b), &byte| {
+            let a = (a + byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        chunk.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+        let pixels = vec![0x10, 0x20, 0x30, 0x80, 0x40, 0x50, 0x60, 0xff];
+        let mut data = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_chunk(png::chunk::iCCP, &chunk).unwrap();
+            writer.write_image_data(&pixels).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let decoded = ImageDataType::EncodedFile(data.clone())
+            .try_decode()
+            .unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&profile[..]));
+        // The profile is not part of the identity of the image
+        assert_eq!(
+            decoded.compute_hash(),
+            ImageDataType::new_single_frame(2, 1, pixels).compute_hash()
+        );
+
+        let round_tripped = decoded.premultiply().to_straight_alpha();
+        assert_eq!(round_tripped.icc_profile(), Some(&profile[..]));
+
+        let streamed =
+            ImageDataType::decode_reader(std::io::Cursor::new(data), &DecodeOptions::default())
+                .unwrap();
+        assert_eq!(streamed.icc_profile(), Some(&profile[..]));
+
+        let plain = ImageDataType::EncodedFile(encode_png(2, 2)).decode();
+        assert_eq!(plain.icc_profile(), None);
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_16bit_grayscale_png() {
+        let image = image::ImageBuffer::<image::Luma<u16>, _>::from_fn(2, 1, |x, _| {
+            image::Luma([if x == 0 { 0x1234 } else { 0xffff }])
+        });
+        let mut png = vec![];
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let decoded = ImageDataType::EncodedFile(png).try_decode().unwrap();
+        match decoded {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => {
+                assert_eq!((width, height), (2, 1));
+                // 0x1234 / 257 = 18.13
+                assert_eq!(data, vec![18, 18, 18, 0xff, 0xff, 0xff, 0xff, 0xff]);
+            }
+            _ => panic!("expected Rgba8, got {:?}", decoded),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_cmyk_jpeg() {
+        // An 8x8 solid red image, stored as inverted CMYK with an
+        // Adobe APP14 marker, as written by Photoshop
+        let jpeg = include_bytes!("../../data/cmyk-red.jpg").to_vec();
+        let decoded = ImageDataType::EncodedFile(jpeg).try_decode().unwrap();
+        match decoded {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => {
+                assert_eq!((width, height), (8, 8));
+                for px in data.chunks_exact(4) {
+                    assert!(
+                        px[0] > 0xe0 && px[1] < 0x20 && px[2] < 0x20 && px[3] == 0xff,
+                        "expected red, got {:?}",
+                        px
+                    );
+                }
+            }
+            _ => panic!("expected Rgba8, got {:?}", decoded),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn try_decode_reports_corrupt_png() {
+        let png = encode_png(64, 64);
+        let ok = ImageDataType::EncodedFile(png.clone())
+            .try_decode()
+            .unwrap();
+        assert_eq!(ok.frame_dimensions(0), Some((64, 64)));
+
+        // Cut the data off part way through the compressed pixels,
+        // leaving the signature and header intact
+        let truncated = png[..png.len() / 2].to_vec();
+        let err = ImageDataType::EncodedFile(truncated.clone())
+            .try_decode()
+            .unwrap_err();
+        assert_eq!(err.format, Some(image::ImageFormat::Png));
+        assert!(matches!(err.reason, DecodeErrorReason::Image(_)));
+        assert!(err.to_string().contains("Png"), "{}", err);
+        assert_eq!(err.into_data(), truncated);
+
+        // decode falls back to preserving the encoded data
+        let decoded = ImageDataType::EncodedFile(truncated.clone()).decode();
+        assert!(decoded == ImageDataType::EncodedFile(truncated));
+
+        let err = ImageDataType::EncodedFile(png)
+            .try_decode_with_options(&DecodeOptions {
+                max_pixels: Some(64),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(err.format, Some(image::ImageFormat::Png));
+        assert!(matches!(
+            err.reason,
+            DecodeErrorReason::TooLarge {
+                width: 64,
+                height: 64,
+                max_pixels: 64
+            }
+        ));
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_stats() {
+        let before = image_decode_stats();
+        let image = ImageData::with_raw_data(encode_png(16, 16));
+        let after = image_decode_stats();
+
+        let duration = image.decode_duration().expect("png was decoded");
+        assert!(duration > Duration::ZERO);
+        // Other tests may be decoding concurrently, so only
+        // lower bounds can be asserted here
+        assert!(after.count > before.count);
+        assert!(after.total_time >= before.total_time + duration);
+
+        let rgba = ImageData::with_data(image.data().clone());
+        assert_eq!(rgba.decode_duration(), None);
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn from_reader_matches_in_memory_decode() {
+        let png = encode_png(8, 4);
+        let streamed = ImageData::from_reader(std::io::Cursor::new(&png)).unwrap();
+        let in_memory = ImageData::with_raw_data(png);
+        assert!(*streamed.data() == *in_memory.data());
+
+        let gif = zero_delay_gif();
+        let streamed = ImageData::from_reader(std::io::Cursor::new(&gif)).unwrap();
+        let in_memory = ImageData::with_raw_data(gif);
+        assert!(*streamed.data() == *in_memory.data());
+
+        assert!(ImageData::from_reader(std::io::Cursor::new(b"not an image")).is_err());
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn decode_options_compose() {
+        let options = DecodeOptions {
+            max_pixels: Some(100 * 100),
+            max_dimensions: Some((10, 10)),
+            ..Default::default()
+        };
+
+        // Within the pixel budget, and scaled down to fit
+        match ImageDataType::EncodedFile(encode_png(40, 20)).decode_with_options(&options) {
+            ImageDataType::Rgba8 { width, height, .. } => {
+                assert_eq!((width, height), (10, 5));
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        // Exceeds the pixel budget, so the max_dimensions are moot
+        match ImageDataType::EncodedFile(encode_png(200, 100)).decode_with_options(&options) {
+            ImageDataType::EncodedFile(_) => {}
+            other => panic!("expected EncodedFile, got {:?}", other),
+        }
+
+        // Animations are constrained as well as still images
+        let options = DecodeOptions {
+            max_dimensions: Some((1, 1)),
+            min_frame_delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+        match ImageDataType::EncodedFile(zero_delay_gif()).decode_with_options(&options) {
+            ImageDataType::AnimRgba8 {
+                width,
+                height,
+                durations,
+                ..
+            } => {
+                assert_eq!((width, height), (1, 1));
+                assert_eq!(durations, vec![Duration::from_millis(50); 2]);
+            }
+            other => panic!("expected AnimRgba8, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn exif_orientation_from_jpeg() {
+        // A minimal JPEG prefix holding just a little-endian
+        // Exif segment that sets the orientation to 6
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe1];
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(b"II");
+        exif.extend_from_slice(&42u16.to_le_bytes());
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&6u16.to_le_bytes());
+        exif.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&[0xff, 0xda]);
+
+        assert_eq!(exif_orientation(&data), Some(6));
+        assert_eq!(exif_orientation(&[0xff, 0xd8, 0xff, 0xda]), None);
+        assert_eq!(exif_orientation(b"not a jpeg"), None);
+    }
+
+    #[test]
+    fn premultiply() {
+        let image = ImageDataType::new_single_frame(1, 1, vec![200, 100, 50, 128]);
+        let premultiplied = image.premultiply();
+        match &premultiplied {
+            ImageDataType::Rgba8 {
+                data,
+                premultiplied: true,
+                hash,
+                ..
+            } => {
+                assert_eq!(data, &vec![100, 50, 25, 128]);
+                assert_eq!(*hash, premultiplied.compute_hash());
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        // Doing it again must not multiply by alpha a second time
+        assert!(premultiplied.clone().premultiply() == premultiplied);
+
+        #[cfg(feature = "use_image")]
+        match premultiplied.resize(2, 2) {
+            Some(
+                ref resized @ ImageDataType::Rgba8 {
+                    premultiplied: true,
+                    hash,
+                    ..
+                },
+            ) => assert_eq!(hash, resized.compute_hash()),
+            other => panic!("expected premultiplied Rgba8, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn premultiply_alpha_animation() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let options = DecodeOptions {
+            premultiply_alpha: true,
+            ..Default::default()
+        };
+        match ImageDataType::EncodedFile(lazy_test_gif()).try_decode_with_options(&options) {
+            Err(err) => assert!(matches!(
+                err.reason,
+                DecodeErrorReason::PremultipliedAnimation
+            )),
+            Ok(other) => panic!("expected an error, got {:?}", other),
+        }
+        assert!(ImageData::try_lazy_animation_with_options(lazy_test_gif(), 0, &options).is_err());
+
+        // A single frame animation is decoded as a premultiplied still image
+        let mut data = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            let mut buffer = RgbaImage::from_pixel(2, 1, Rgba([0xff, 0, 0, 0xff]));
+            buffer.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+            let frame = Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1));
+            encoder.encode_frame(frame).unwrap();
+        }
+        match ImageDataType::EncodedFile(data).decode_with_options(&options) {
+            ImageDataType::Rgba8 { premultiplied, .. } => assert!(premultiplied),
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        // Frames that are extracted one at a time are premultiplied too
+        let frames = vec![vec![200, 100, 50, 128], vec![200, 100, 50, 0xff]];
+        let anim = ImageDataType::AnimRgba8 {
+            width: 1,
+            height: 1,
+            durations: vec![Duration::from_millis(100); 2],
+            hashes: frames
+                .iter()
+                .map(|f| ImageDataType::hash_bytes(f))
+                .collect(),
+            frame_dimensions: vec![(1, 1); 2],
+            frames,
+        };
+        let encoded = ImageDataType::EncodedFile(anim.encode_apng().unwrap());
+        let (_, frame) = encoded.decode_frame(0, &options).unwrap();
+        assert_eq!(frame, vec![100, 50, 25, 128]);
+    }
+
+    #[test]
+    fn straight_alpha_roundtrip() {
+        let mut data = vec![];
+        for alpha in [255u8, 200, 128, 64, 16, 1, 0].iter() {
+            for value in [0u8, 1, 77, 128, 254, 255].iter() {
+                data.extend_from_slice(&[*value, 255 - *value, *value / 2, *alpha]);
+            }
+        }
+        let width = (data.len() / 4) as u32;
+        let image = ImageDataType::new_single_frame(width, 1, data.clone());
+
+        // Converting to the current representation is a no-op
+        assert!(image.clone().to_straight_alpha() == image);
+        let premultiplied = image.to_premultiplied_alpha();
+        assert!(premultiplied.clone().to_premultiplied_alpha() == premultiplied);
+
+        let straight = premultiplied.to_straight_alpha();
+        match &straight {
+            ImageDataType::Rgba8 {
+                data: result,
+                premultiplied,
+                hash,
+                ..
+            } => {
+                assert!(!*premultiplied);
+                assert_eq!(*hash, straight.compute_hash());
+                for (got, want) in result.chunks_exact(4).zip(data.chunks_exact(4)) {
+                    let alpha = want[3];
+                    assert_eq!(got[3], alpha);
+                    for channel in 0..3 {
+                        if alpha == 0 {
+                            assert_eq!(got[channel], 0);
+                        } else {
+                            // Premultiplying loses precision that can't be
+                            // recovered, in proportion to the transparency
+                            let tolerance = 255 / (2 * alpha as u16) + 1;
+                            assert!(
+                                (got[channel] as u16).abs_diff(want[channel] as u16) <= tolerance,
+                                "{:?} vs {:?}",
+                                got,
+                                want
+                            );
+                        }
+                    }
+                }
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn image_layer_boundaries() {
+        let cases = [
+            (i32::MIN, ImageLayer::UnderBackground),
+            (i32::MIN / 2 - 1, ImageLayer::UnderBackground),
+            (i32::MIN / 2, ImageLayer::UnderText),
+            (-1, ImageLayer::UnderText),
+            (0, ImageLayer::OverText),
+            (i32::MAX, ImageLayer::OverText),
+        ];
+        for (z_index, layer) in cases.iter() {
+            assert_eq!(ImageLayer::for_z_index(*z_index), *layer, "{z_index}");
+            assert!(layer.z_range().contains(z_index));
+        }
+
+        // The ranges are contiguous and cover every z_index
+        assert_eq!(*ImageLayer::UnderBackground.z_range().start(), i32::MIN);
+        assert_eq!(
+            *ImageLayer::UnderBackground.z_range().end() + 1,
+            *ImageLayer::UnderText.z_range().start()
+        );
+        assert_eq!(
+            *ImageLayer::UnderText.z_range().end() + 1,
+            *ImageLayer::OverText.z_range().start()
+        );
+        assert_eq!(*ImageLayer::OverText.z_range().end(), i32::MAX);
+
+        let cell = ImageCell::with_z_index(
+            TextureCoordinate::new_f32(0.0, 0.0),
+            TextureCoordinate::new_f32(1.0, 1.0),
+            Arc::new(ImageData::with_data(ImageDataType::placeholder())),
+            i32::MIN / 2,
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+        );
+        assert_eq!(cell.layer(), ImageLayer::UnderText);
+    }
+
+    #[test]
+    fn image_cell_same_content_ignores_ids() {
+        let top_left = TextureCoordinate::new_f32(0.0, 0.0);
+        let bottom_right = TextureCoordinate::new_f32(0.5, 0.5);
+        let a = ImageCell::with_z_index(
+            top_left,
+            bottom_right,
+            Arc::new(ImageData::with_data(ImageDataType::placeholder())),
+            1,
+            2,
+            2,
+            0,
+            0,
+            Some(1),
+            Some(10),
+        );
+        let b = ImageCell::with_z_index(
+            top_left,
+            bottom_right,
+            Arc::new(ImageData::with_data(ImageDataType::placeholder())),
+            1,
+            2,
+            2,
+            0,
+            0,
+            Some(2),
+            None,
+        );
+        assert!(a.same_content(&b));
+        assert_ne!(a, b);
+
+        let c = ImageCell::with_z_index(
+            top_left,
+            bottom_right,
+            Arc::clone(a.image_data()),
+            -1,
+            2,
+            2,
+            0,
+            0,
+            Some(1),
+            Some(10),
+        );
+        assert!(!a.same_content(&c));
+    }
+}
@@ -0,0 +1,424 @@
+use crate::image::{ImageDataType, TRANSPARENT};
+use std::collections::HashMap;
+
+impl ImageDataType {
+    /// Reduces a non-premultiplied `Rgba8` image to an `Indexed8` image
+    /// with a palette of at most `max_colors` colors; `max_colors` is
+    /// clamped to the range 1..=256.
+    /// Fully transparent pixels are all treated as the same color,
+    /// `[0, 0, 0, 0]`, regardless of their color channels.
+    /// If the image has no more than `max_colors` distinct colors then
+    /// the conversion is lossless.  Otherwise the palette is chosen by
+    /// median cut, with one entry reserved for transparency if the image
+    /// has any transparent pixels, and each pixel is mapped to the
+    /// nearest palette entry, comparing colors by their premultiplied
+    /// RGBA values so that translucent edges keep their alpha.
+    /// When `dither` is true, the error from mapping each pixel is
+    /// diffused over its neighbors using Floyd-Steinberg dithering,
+    /// which avoids visible banding in gradients; dithering is skipped
+    /// when the conversion is lossless.
+    /// The other variants, and premultiplied images, are returned
+    /// unchanged.
+    pub fn quantize(self, max_colors: usize, dither: bool) -> Self {
+        match self {
+            Self::Rgba8 {
+                data,
+                width,
+                height,
+                premultiplied: false,
+                ..
+            } => {
+                let max_colors = max_colors.clamp(1, 256);
+                let pixel = |p: &[u8]| -> [u8; 4] {
+                    if p[3] == 0 {
+                        TRANSPARENT
+                    } else {
+                        [p[0], p[1], p[2], p[3]]
+                    }
+                };
+
+                let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+                for p in data.chunks_exact(4) {
+                    *counts.entry(pixel(p)).or_insert(0) += 1;
+                }
+                let lossless = counts.len() <= max_colors;
+                let palette = if lossless {
+                    let mut palette: Vec<[u8; 4]> = counts.into_keys().collect();
+                    palette.sort();
+                    palette
+                } else if max_colors > 1 && counts.remove(&TRANSPARENT).is_some() {
+                    let mut palette = median_cut(counts.into_iter().collect(), max_colors - 1);
+                    palette.push(TRANSPARENT);
+                    palette
+                } else {
+                    median_cut(counts.into_iter().collect(), max_colors)
+                };
+
+                let indices = if dither && !lossless {
+                    dither_to_palette(&data, width as usize, &palette)
+                } else {
+                    let mut nearest: HashMap<[u8; 4], u8> = HashMap::new();
+                    data.chunks_exact(4)
+                        .map(|p| {
+                            let color = pixel(p);
+                            *nearest
+                                .entry(color)
+                                .or_insert_with(|| nearest_palette_index(&palette, color))
+                        })
+                        .collect()
+                };
+
+                Self::Indexed8 {
+                    palette,
+                    indices,
+                    width,
+                    height,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+/// Chooses a palette of up to `max_colors` colors that represents
+/// `colors`, a list of distinct colors and the number of pixels that
+/// use each of them, by recursively splitting the set of colors at the
+/// median of its widest channel and averaging each resulting box.
+/// The color channels are averaged weighted by alpha, so that nearly
+/// transparent colors don't skew the color of a box.
+fn median_cut(colors: Vec<([u8; 4], usize)>, max_colors: usize) -> Vec<[u8; 4]> {
+    /// Returns the channel with the widest range of values in `colors`,
+    /// along with that range
+    fn widest_channel(colors: &[([u8; 4], usize)]) -> (usize, u8) {
+        (0..4)
+            .map(|channel| {
+                let (min, max) = colors
+                    .iter()
+                    .fold((u8::MAX, u8::MIN), |(min, max), (c, _)| {
+                        (min.min(c[channel]), max.max(c[channel]))
+                    });
+                (channel, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(idx, colors)| (idx, widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range);
+        let (idx, channel) = match widest {
+            Some((idx, (channel, range))) if range > 0 => (idx, channel),
+            _ => break,
+        };
+
+        let mut lower = boxes.swap_remove(idx);
+        lower.sort_unstable_by_key(|(c, _)| c[channel]);
+        let total: usize = lower.iter().map(|(_, count)| count).sum();
+        let mut seen = 0;
+        let median = lower
+            .iter()
+            .position(|(_, count)| {
+                seen += count;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        let upper = lower.split_off((median + 1).min(lower.len() - 1));
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|colors| {
+            let total: usize = colors.iter().map(|(_, count)| count).sum();
+            let alpha: usize = colors.iter().map(|(c, count)| c[3] as usize * count).sum();
+            let mut color = [0u8; 4];
+            for (channel, value) in color.iter_mut().take(3).enumerate() {
+                let sum: usize = colors
+                    .iter()
+                    .map(|(c, count)| c[channel] as usize * c[3] as usize * count)
+                    .sum();
+                *value = ((sum + alpha / 2) / alpha.max(1)) as u8;
+            }
+            color[3] = ((alpha + total / 2) / total.max(1)) as u8;
+            color
+        })
+        .collect()
+}
+
+/// Returns the index of the entry in `palette` that is closest to `color`.
+/// Colors are compared by their premultiplied RGBA values, so that the
+/// color channels of a mostly transparent color matter less than its
+/// alpha.
+fn nearest_palette_index(palette: &[[u8; 4]], color: [u8; 4]) -> u8 {
+    fn premultiplied([r, g, b, a]: [u8; 4]) -> [i32; 4] {
+        let a = a as i32;
+        [
+            (r as i32 * a + 127) / 255,
+            (g as i32 * a + 127) / 255,
+            (b as i32 * a + 127) / 255,
+            a,
+        ]
+    }
+
+    let color = premultiplied(color);
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, &entry)| {
+            premultiplied(entry)
+                .iter()
+                .zip(color.iter())
+                .map(|(&a, &b)| {
+                    let d = a - b;
+                    d * d
+                })
+                .sum::<i32>()
+        })
+        .map(|(idx, _)| idx as u8)
+        .unwrap_or(0)
+}
+
+/// Maps each pixel of `data`, a non-premultiplied RGBA image that is
+/// `width` pixels wide, to the nearest entry in `palette`, diffusing the
+/// difference between each pixel and its palette entry over the pixels
+/// to its right and below it, in the proportions used by Floyd-Steinberg.
+fn dither_to_palette(data: &[u8], width: usize, palette: &[[u8; 4]]) -> Vec<u8> {
+    if width == 0 {
+        return vec![];
+    }
+
+    // The accumulated error, in sixteenths, for the current and next rows.
+    // There is an extra column at each end so that the error that would
+    // be diffused beyond the edges of the image can simply be discarded.
+    let mut current = vec![[0i32; 4]; width + 2];
+    let mut next = vec![[0i32; 4]; width + 2];
+    let mut nearest: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(data.len() / 4);
+
+    for row in data.chunks_exact(width * 4) {
+        for (x, p) in row.chunks_exact(4).enumerate() {
+            // Transparent pixels map straight to the transparent entry;
+            // diffusing error into or out of them would speckle the
+            // edges of opaque regions
+            if p[3] == 0 {
+                let idx = *nearest
+                    .entry(TRANSPARENT)
+                    .or_insert_with(|| nearest_palette_index(palette, TRANSPARENT));
+                indices.push(idx);
+                continue;
+            }
+
+            let mut color = [0u8; 4];
+            for (channel, value) in color.iter_mut().enumerate() {
+                let adjusted = p[channel] as i32 + ((current[x + 1][channel] + 8) >> 4);
+                *value = adjusted.clamp(0, 255) as u8;
+            }
+            let idx = *nearest
+                .entry(color)
+                .or_insert_with(|| nearest_palette_index(palette, color));
+            indices.push(idx);
+
+            let chosen = palette[idx as usize];
+            for channel in 0..4 {
+                let error = color[channel] as i32 - chosen[channel] as i32;
+                current[x + 2][channel] += error * 7;
+                next[x][channel] += error * 3;
+                next[x + 1][channel] += error * 5;
+                next[x + 2][channel] += error;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|error| *error = [0; 4]);
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quantize_few_colors_is_lossless() {
+        let colors = [[0xff, 0, 0, 0xff], [0, 0xff, 0, 0x80], [0, 0, 0, 0]];
+        let data: Vec<u8> = (0..4 * 3).flat_map(|idx| colors[idx % 3]).collect();
+        let image = ImageDataType::new_single_frame(4, 3, data.clone());
+
+        let indexed = image.clone().quantize(256, false);
+        match &indexed {
+            ImageDataType::Indexed8 {
+                palette,
+                indices,
+                width,
+                height,
+            } => {
+                assert_eq!(palette.len(), 3);
+                assert_eq!(indices.len(), 4 * 3);
+                assert_eq!((*width, *height), (4, 3));
+            }
+            other => panic!("expected Indexed8, got {:?}", other),
+        }
+        assert_eq!(indexed.len(), 3 * 4 + 4 * 3);
+        assert_eq!(indexed.frame_count(), 1);
+        assert_eq!(indexed.pixel_at(1, 0), Some(colors[1]));
+        assert_ne!(indexed.compute_hash(), image.compute_hash());
+        assert!(indexed.to_rgba8() == image);
+    }
+
+    #[test]
+    fn quantize_preserves_translucent_edges() {
+        // An opaque red logo with slightly noisy color, a wide half
+        // transparent anti-aliased edge and a transparent background
+        // whose color channels are garbage
+        let (width, height) = (16u32, 16u32);
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = match x {
+                    0..=5 => [(x * 37 + y * 11) as u8, (y * 53) as u8, (x * 97) as u8, 0],
+                    6..=10 => [0xff, 0, 0, 0x80],
+                    _ => [0xff - (x % 3) as u8, (y % 4) as u8, 0, 0xff],
+                };
+                data.extend_from_slice(&pixel);
+            }
+        }
+        let image = ImageDataType::new_single_frame(width, height, data.clone());
+
+        for dither in [false, true] {
+            let indexed = image.clone().quantize(3, dither);
+            match &indexed {
+                ImageDataType::Indexed8 { palette, .. } => {
+                    assert_eq!(palette.len(), 3);
+                    assert!(palette.contains(&[0, 0, 0, 0]));
+                }
+                other => panic!("expected Indexed8, got {:?}", other),
+            }
+
+            let expanded = indexed.to_rgba8();
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = expanded.pixel_at(x, y).unwrap();
+                    let offset = ((y * width + x) * 4) as usize;
+                    let want = data[offset + 3];
+                    if want == 0 {
+                        assert_eq!(pixel, [0, 0, 0, 0], "pixel ({x},{y}) dither={dither}");
+                    } else {
+                        assert_eq!(pixel[3], want, "pixel ({x},{y}) dither={dither}");
+                        assert!(pixel[0] >= 0xf0, "pixel ({},{}) dither={}", x, y, dither);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_gradient_within_tolerance() {
+        // A 64x64 gradient has far more than 16 distinct colors
+        let (width, height) = (64u32, 64u32);
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, 0x80, 0xff]);
+            }
+        }
+        let image = ImageDataType::new_single_frame(width, height, data.clone());
+
+        let indexed = image.quantize(16, false);
+        match &indexed {
+            ImageDataType::Indexed8 { palette, .. } => assert!(palette.len() <= 16),
+            other => panic!("expected Indexed8, got {:?}", other),
+        }
+        assert!(indexed.len() < data.len());
+
+        let expanded = indexed.to_rgba8();
+        assert_eq!(expanded.frame_dimensions(0), Some((width, height)));
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = expanded.pixel_at(x, y).unwrap();
+                let offset = ((y * width + x) * 4) as usize;
+                for (channel, (&got, &want)) in
+                    pixel.iter().zip(&data[offset..offset + 4]).enumerate()
+                {
+                    assert!(
+                        got.abs_diff(want) <= 64,
+                        "pixel ({},{}) channel {}: {} vs {}",
+                        x,
+                        y,
+                        channel,
+                        got,
+                        want
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dithering_reduces_banding() {
+        // A horizontal gray ramp, quantized to just 4 levels
+        let (width, height) = (256usize, 16usize);
+        let mut data = vec![];
+        for _ in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&[x as u8, x as u8, x as u8, 0xff]);
+            }
+        }
+        let image = ImageDataType::new_single_frame(width as u32, height as u32, data);
+
+        // The eye perceives the average color of a small area rather than
+        // individual pixels, so measure the error of each 4x4 block
+        let max_block_error = |indexed: ImageDataType| -> i32 {
+            let (palette, indices) = match indexed {
+                ImageDataType::Indexed8 {
+                    palette, indices, ..
+                } => (palette, indices),
+                other => panic!("expected Indexed8, got {:?}", other),
+            };
+            // Ignore the ends of the ramp that lie outside of the palette,
+            // where no amount of dithering can help
+            let lowest = palette.iter().map(|c| c[0] as i32).min().unwrap();
+            let highest = palette.iter().map(|c| c[0] as i32).max().unwrap();
+
+            let mut max_error = 0;
+            for by in (0..height).step_by(4) {
+                for bx in (0..width).step_by(4) {
+                    let mut got = 0;
+                    let mut want = 0;
+                    for y in by..by + 4 {
+                        for x in bx..bx + 4 {
+                            got += palette[indices[y * width + x] as usize][0] as i32;
+                            want += x as i32;
+                        }
+                    }
+                    if want / 16 >= lowest && (want + 15) / 16 <= highest {
+                        max_error = max_error.max((got - want).abs() / 16);
+                    }
+                }
+            }
+            max_error
+        };
+
+        let banded = max_block_error(image.clone().quantize(4, false));
+        let dithered = max_block_error(image.quantize(4, true));
+        assert!(
+            dithered * 2 < banded,
+            "dithered error {} vs non-dithered {}",
+            dithered,
+            banded
+        );
+    }
+
+    #[test]
+    fn dithering_flat_colors_is_lossless() {
+        let colors = [[0xff, 0, 0, 0xff], [0, 0, 0xff, 0xff]];
+        let data: Vec<u8> = (0..8 * 8).flat_map(|idx| colors[(idx / 8) % 2]).collect();
+        let image = ImageDataType::new_single_frame(8, 8, data);
+        assert!(image.clone().quantize(2, true).to_rgba8() == image);
+    }
+}
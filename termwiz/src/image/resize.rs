@@ -0,0 +1,419 @@
+#[cfg(feature = "use_image")]
+use crate::image::composite_over;
+use crate::image::ImageDataType;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+/// How `ImageDataType::fit_to` fits an image into dimensions that
+/// may have a different aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FitMode {
+    /// Scale the image, preserving its aspect ratio, so that it
+    /// covers the target; the excess is cropped equally from both sides
+    Cover,
+    /// Scale the image, preserving its aspect ratio, so that it fits
+    /// within the target; the rest is padded with transparent pixels
+    Contain,
+    /// Scale the image to exactly the target, ignoring its aspect ratio
+    Stretch,
+}
+
+/// The filter used when resampling image data to a different size
+/// on the CPU, for example by `ImageDataType::resize_with_filter`
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResampleFilter {
+    /// Use the nearest pixel; fast, but aliases badly when downscaling
+    Nearest,
+    /// Bilinear interpolation
+    Bilinear,
+    /// Bicubic (Catmull-Rom) interpolation; the default when upscaling
+    Bicubic,
+    /// Gaussian filter; softer than bicubic
+    Gaussian,
+    /// Lanczos with a window of 3; the default when downscaling
+    Lanczos3,
+}
+
+impl ResampleFilter {
+    /// Returns the filter that gives the best quality when resampling
+    /// an image from `src` to `dest` dimensions: `Lanczos3` when either
+    /// dimension shrinks and `Bicubic` otherwise
+    pub fn for_scale(src: (u32, u32), dest: (u32, u32)) -> Self {
+        if dest.0 < src.0 || dest.1 < src.1 {
+            Self::Lanczos3
+        } else {
+            Self::Bicubic
+        }
+    }
+
+    /// Returns the equivalent filter from the `image` crate
+    #[cfg(feature = "use_image")]
+    pub fn filter_type(self) -> image::imageops::FilterType {
+        use image::imageops::FilterType;
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Bilinear => FilterType::Triangle,
+            Self::Bicubic => FilterType::CatmullRom,
+            Self::Gaussian => FilterType::Gaussian,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl ImageDataType {
+    /// Returns a copy of a still `Rgba8` image resampled to the
+    /// specified dimensions, using the filter selected by
+    /// `ResampleFilter::for_scale`.
+    /// Returns `None` for the other variants.
+    #[cfg(feature = "use_image")]
+    pub fn resize(&self, width: u32, height: u32) -> Option<Self> {
+        let filter = match self {
+            Self::Rgba8 {
+                width: src_width,
+                height: src_height,
+                ..
+            } => ResampleFilter::for_scale((*src_width, *src_height), (width, height)),
+            _ => return None,
+        };
+        self.resize_with_filter(width, height, filter)
+    }
+
+    /// Returns a copy of a still `Rgba8` image resampled to the
+    /// specified dimensions using `filter`.
+    /// Returns `None` for the other variants.
+    #[cfg(feature = "use_image")]
+    pub fn resize_with_filter(
+        &self,
+        width: u32,
+        height: u32,
+        filter: ResampleFilter,
+    ) -> Option<Self> {
+        match self {
+            Self::Rgba8 {
+                data,
+                width: src_width,
+                height: src_height,
+                premultiplied,
+                ..
+            } => {
+                let image = image::RgbaImage::from_raw(*src_width, *src_height, data.clone())?;
+                let image = image::imageops::resize(&image, width, height, filter.filter_type());
+                let data = image.into_vec();
+                // Resampling premultiplied pixels keeps them premultiplied
+                let hash = Self::hash_rgba8(width, height, *premultiplied, &data);
+                Some(Self::Rgba8 {
+                    data,
+                    width,
+                    height,
+                    hash,
+                    premultiplied: *premultiplied,
+                    icc_profile: None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Produces a `size` x `size` `Rgba8` thumbnail of the image.
+    /// The image is scaled, preserving its aspect ratio, so that its
+    /// longest dimension fits `size`, then composited over the center
+    /// of a square filled with the straight-alpha `background` color.
+    /// An animation is represented by its poster frame.
+    /// Encoded data is decoded first; if that fails, the thumbnail
+    /// holds just the background.
+    #[cfg(feature = "use_image")]
+    pub fn thumbnail(self, size: u32, background: [u8; 4]) -> Self {
+        let image = match self {
+            Self::EncodedFile(_) => self.decode(),
+            image => image,
+        };
+        let image = image.poster_frame().map(Self::to_straight_alpha);
+
+        let scaled = image.and_then(|image| {
+            let (width, height) = image.frame_dimensions(0)?;
+            if width == 0 || height == 0 || size == 0 {
+                return None;
+            }
+            let scale = size as f64 / width.max(height) as f64;
+            let fit_width = ((width as f64 * scale).round() as u32).max(1).min(size);
+            let fit_height = ((height as f64 * scale).round() as u32).max(1).min(size);
+            if (fit_width, fit_height) == (width, height) {
+                Some(image)
+            } else {
+                image.resize(fit_width, fit_height)
+            }
+        });
+
+        Self::letterbox(scaled, size, size, background)
+    }
+
+    /// Composites a still `Rgba8` `image`, which must be no larger than
+    /// `width` x `height`, over the center of a `width` x `height`
+    /// image filled with the straight-alpha `background` color.
+    /// If `image` is `None`, the result holds just the background.
+    #[cfg(feature = "use_image")]
+    fn letterbox(image: Option<Self>, width: u32, height: u32, background: [u8; 4]) -> Self {
+        let mut data = background.repeat(width as usize * height as usize);
+
+        if let Some(Self::Rgba8 {
+            data: pixels,
+            width: image_width,
+            height: image_height,
+            ..
+        }) = image
+        {
+            let left = ((width - image_width) / 2) as usize;
+            let top = ((height - image_height) / 2) as usize;
+            for (y, row) in pixels.chunks_exact(image_width as usize * 4).enumerate() {
+                let start = ((top + y) * width as usize + left) * 4;
+                for (dest, src) in data[start..start + row.len()]
+                    .chunks_exact_mut(4)
+                    .zip(row.chunks_exact(4))
+                {
+                    composite_over(dest, src);
+                }
+            }
+        }
+
+        Self::new_single_frame(width, height, data)
+    }
+
+    /// Produces a `width` x `height` `Rgba8` image from the image,
+    /// scaling it as specified by `mode`; see `FitMode`.
+    /// This allows, for example, a static background image to be
+    /// scaled and cropped once rather than each time it is drawn.
+    /// An animation is represented by its poster frame.
+    /// Encoded data is decoded first; if that fails, or either of the
+    /// dimensions is zero, the result is fully transparent.
+    #[cfg(feature = "use_image")]
+    pub fn fit_to(self, width: u32, height: u32, mode: FitMode) -> Self {
+        let image = match self {
+            Self::EncodedFile(_) => self.decode(),
+            image => image,
+        };
+        let image = match image.poster_frame().map(Self::to_straight_alpha) {
+            Some(image) => image,
+            None => return Self::letterbox(None, width, height, [0; 4]),
+        };
+        let (image_width, image_height) = match image.frame_dimensions(0) {
+            Some((w, h)) if w > 0 && h > 0 && width > 0 && height > 0 => (w, h),
+            _ => return Self::letterbox(None, width, height, [0; 4]),
+        };
+
+        let resize = |image: Self, w: u32, h: u32| {
+            if image.frame_dimensions(0) == Some((w, h)) {
+                Some(image)
+            } else {
+                image.resize(w, h)
+            }
+        };
+
+        match mode {
+            FitMode::Stretch => resize(image, width, height)
+                .unwrap_or_else(|| Self::letterbox(None, width, height, [0; 4])),
+            FitMode::Contain => {
+                let scale =
+                    (width as f64 / image_width as f64).min(height as f64 / image_height as f64);
+                let fit_width = ((image_width as f64 * scale).round() as u32).clamp(1, width);
+                let fit_height = ((image_height as f64 * scale).round() as u32).clamp(1, height);
+                let scaled = resize(image, fit_width, fit_height);
+                Self::letterbox(scaled, width, height, [0; 4])
+            }
+            FitMode::Cover => {
+                // Crop the largest centered region with the target
+                // aspect ratio, then scale that to the target
+                let (crop_width, crop_height) =
+                    if image_width as u64 * height as u64 > image_height as u64 * width as u64 {
+                        let w = (image_height as f64 * width as f64 / height as f64).round();
+                        ((w as u32).clamp(1, image_width), image_height)
+                    } else {
+                        let h = (image_width as f64 * height as f64 / width as f64).round();
+                        (image_width, (h as u32).clamp(1, image_height))
+                    };
+                let cropped = image
+                    .crop(
+                        (image_width - crop_width) / 2,
+                        (image_height - crop_height) / 2,
+                        crop_width,
+                        crop_height,
+                    )
+                    .ok()
+                    .and_then(|image| resize(image, width, height));
+                cropped.unwrap_or_else(|| Self::letterbox(None, width, height, [0; 4]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resample_filter_for_scale() {
+        assert_eq!(
+            ResampleFilter::for_scale((100, 100), (50, 50)),
+            ResampleFilter::Lanczos3
+        );
+        assert_eq!(
+            ResampleFilter::for_scale((100, 100), (200, 50)),
+            ResampleFilter::Lanczos3
+        );
+        assert_eq!(
+            ResampleFilter::for_scale((100, 100), (200, 200)),
+            ResampleFilter::Bicubic
+        );
+        assert_eq!(
+            ResampleFilter::for_scale((100, 100), (100, 100)),
+            ResampleFilter::Bicubic
+        );
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn thumbnail_letterboxes() {
+        let red = [0xff, 0, 0, 0xff];
+        let background = [0x10, 0x20, 0x30, 0xff];
+
+        // A very wide image is scaled to fit the width, and padded
+        // above and below rather than cropped
+        let wide = ImageDataType::new_single_frame(
+            100,
+            10,
+            std::iter::repeat(red).take(100 * 10).flatten().collect(),
+        );
+        let thumb = wide.thumbnail(20, background);
+        let data = match &thumb {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => {
+                assert_eq!((*width, *height), (20, 20));
+                data
+            }
+            _ => panic!("expected Rgba8, got {:?}", thumb),
+        };
+        let pixel = |x: usize, y: usize| &data[(y * 20 + x) * 4..(y * 20 + x) * 4 + 4];
+        // Scaled to 20x2, centered at rows 9 and 10
+        for x in 0..20 {
+            for y in 0..20 {
+                let expected = if y == 9 || y == 10 { red } else { background };
+                assert_eq!(pixel(x, y), &expected[..], "at {x},{y}");
+            }
+        }
+
+        // A tall image is padded to the left and right
+        let tall = ImageDataType::new_single_frame(
+            2,
+            20,
+            std::iter::repeat(red).take(2 * 20).flatten().collect(),
+        );
+        let thumb = tall.thumbnail(20, background);
+        match &thumb {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => {
+                assert_eq!((*width, *height), (20, 20));
+                for (idx, px) in data.chunks_exact(4).enumerate() {
+                    let x = idx % 20;
+                    let expected = if x == 9 || x == 10 { red } else { background };
+                    assert_eq!(px, &expected[..], "at index {idx}");
+                }
+            }
+            _ => panic!("expected Rgba8, got {:?}", thumb),
+        }
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn fit_to_modes() {
+        let red = [0xff, 0, 0, 0xff];
+        let green = [0, 0xff, 0, 0xff];
+        let blue = [0, 0, 0xff, 0xff];
+
+        // A 40x10 image with red and blue ends around a green middle
+        let mut data = vec![];
+        for _ in 0..10 {
+            for x in 0..40 {
+                data.extend_from_slice(match x {
+                    0..=9 => &red,
+                    10..=29 => &green,
+                    _ => &blue,
+                });
+            }
+        }
+        let wide = ImageDataType::new_single_frame(40, 10, data);
+
+        for mode in [FitMode::Cover, FitMode::Contain, FitMode::Stretch] {
+            let fitted = wide.clone().fit_to(20, 20, mode);
+            assert_eq!(fitted.frame_dimensions(0), Some((20, 20)), "{mode:?}");
+        }
+
+        // Cover crops the sides of a wide image, keeping the middle
+        let cover = wide.clone().fit_to(10, 10, FitMode::Cover);
+        assert_eq!(cover.frame_dimensions(0), Some((10, 10)));
+        for y in 0..10 {
+            for x in 0..10 {
+                assert_eq!(cover.pixel_at(x, y), Some(green), "at {x},{y}");
+            }
+        }
+
+        // Contain scales it to 20x5 and pads above and below
+        let contain = wide.clone().fit_to(20, 20, FitMode::Contain);
+        assert_eq!(contain.pixel_at(0, 6), Some([0, 0, 0, 0]));
+        assert_eq!(contain.pixel_at(10, 9), Some(green));
+        assert_eq!(contain.pixel_at(0, 12), Some([0, 0, 0, 0]));
+
+        // Stretch keeps all of the content
+        let stretch = wide.fit_to(20, 20, FitMode::Stretch);
+        assert_eq!(stretch.pixel_at(0, 10), Some(red));
+        assert_eq!(stretch.pixel_at(19, 10), Some(blue));
+    }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn lanczos_downscale_reduces_aliasing() {
+        // A single pixel checkerboard is the worst case for aliasing;
+        // an ideal downscale is flat mid-grey
+        let size = 64u32;
+        let mut data = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let v = if (x + y) % 2 == 0 { 0xff } else { 0 };
+                data.extend_from_slice(&[v, v, v, 0xff]);
+            }
+        }
+        let image = ImageDataType::new_single_frame(size, size, data);
+
+        fn variance(image: &ImageDataType) -> f64 {
+            let data = match image {
+                ImageDataType::Rgba8 { data, .. } => data,
+                _ => unreachable!(),
+            };
+            let values: Vec<f64> = data.chunks_exact(4).map(|px| px[0] as f64).collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+        }
+
+        let nearest = image
+            .resize_with_filter(21, 21, ResampleFilter::Nearest)
+            .unwrap();
+        let lanczos = image
+            .resize_with_filter(21, 21, ResampleFilter::Lanczos3)
+            .unwrap();
+        let nearest = variance(&nearest);
+        let lanczos = variance(&lanczos);
+        assert!(
+            lanczos < nearest / 10.,
+            "lanczos variance {} should be much lower than nearest {}",
+            lanczos,
+            nearest
+        );
+    }
+}
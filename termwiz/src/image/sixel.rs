@@ -0,0 +1,322 @@
+use crate::color::RgbColor;
+use crate::error::InternalError;
+use crate::escape::{Sixel, SixelData};
+use crate::image::ImageDataType;
+
+/// Options that influence how `ImageDataType::from_sixel` renders
+/// sixel data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SixelOptions {
+    /// The number of color registers.  Color numbers are reduced
+    /// modulo this value, as they are by DEC terminals.  It is clamped
+    /// to the range 1..=65536.  The default is 1024, which matches xterm.
+    pub max_registers: usize,
+    /// When true, color introducers that define a color in either
+    /// of the device independent color spaces, HLS or RGB percentages,
+    /// update the color register.  Those are the only color spaces that
+    /// sixel defines, so when false every color definition is discarded,
+    /// as it is by a VT340 in monochrome mode: the image can only select
+    /// the 16 default VT340 colors, and any higher register is black.
+    /// The default is true.
+    pub honor_color_space: bool,
+    /// The color of pixels that the image doesn't draw, unless the
+    /// image selects a transparent background.  When None, which is
+    /// the default, those pixels are transparent.
+    pub background: Option<RgbColor>,
+}
+
+impl Default for SixelOptions {
+    fn default() -> Self {
+        Self {
+            max_registers: 1024,
+            honor_color_space: true,
+            background: None,
+        }
+    }
+}
+
+/// The initial colors of the sixel color registers,
+/// matching the VT340 color table:
+/// https://github.com/hackerb9/vt340test/blob/main/colormap/showcolortable.png
+pub const SIXEL_DEFAULT_REGISTERS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [0x33, 0x33, 0xcc],
+    [0xcc, 0x23, 0x23],
+    [0x33, 0xcc, 0x33],
+    [0xcc, 0x33, 0xcc],
+    [0x33, 0xcc, 0xcc],
+    [0xcc, 0xcc, 0xcc],
+    [0x77, 0x77, 0x77],
+    [0x44, 0x44, 0x44],
+    [0x56, 0x56, 0x99],
+    [0x99, 0x44, 0x44],
+    [0x56, 0x99, 0x56],
+    [0x99, 0x56, 0x99],
+    [0x56, 0x99, 0x99],
+    [0x99, 0x99, 0x56],
+    [0xcc, 0xcc, 0xcc],
+];
+
+impl ImageDataType {
+    /// Renders parsed `sixel` data into an `Rgba8` image, with
+    /// `options` controlling the color registers and the background.
+    /// Each image starts with its own set of color registers, holding
+    /// the default VT340 colors.
+    /// Returns an error if the image is empty or if its pixel data
+    /// would exceed the same 100MB limit that the terminal applies
+    /// to images.
+    pub fn from_sixel(sixel: &Sixel, options: &SixelOptions) -> Result<Self, InternalError> {
+        const MAX_IMAGE_SIZE: u64 = 100_000_000;
+
+        let (width, height) = sixel.dimensions();
+        let size = (width as u64)
+            .saturating_mul(height as u64)
+            .saturating_mul(4);
+        if size == 0 {
+            return Err("sixel image has no pixels".to_string().into());
+        }
+        if size > MAX_IMAGE_SIZE {
+            return Err(format!(
+                "sixel image {}x{} would use {} bytes, which exceeds the limit of {}",
+                width, height, size, MAX_IMAGE_SIZE
+            )
+            .into());
+        }
+        let (width, height) = (width as usize, height as usize);
+        let num_registers = options.max_registers.clamp(1, u16::MAX as usize + 1);
+        let mut registers: Vec<[u8; 3]> = (0..num_registers)
+            .map(|idx| SIXEL_DEFAULT_REGISTERS.get(idx).copied().unwrap_or([0; 3]))
+            .collect();
+        let register = |color_number: u16| color_number as usize % num_registers;
+
+        let background = match options.background {
+            Some(color) if !sixel.background_is_transparent => {
+                let (r, g, b) = color.to_tuple_rgb8();
+                [r, g, b, 0xff]
+            }
+            _ => [0; 4],
+        };
+        let mut data = background.repeat(width * height);
+
+        let mut x: usize = 0;
+        let mut y: usize = 0;
+        // This matches the initial color used by the terminal
+        let mut foreground = [0, 0xff, 0];
+
+        let mut emit_sixel = |bits: u8, [r, g, b]: [u8; 3], x: usize, y: usize| {
+            if x >= width {
+                return;
+            }
+            for bitno in 0..6 {
+                if y + bitno >= height {
+                    break;
+                }
+                if bits & (1 << bitno) != 0 {
+                    let offset = ((y + bitno) * width + x) * 4;
+                    data[offset..offset + 4].copy_from_slice(&[r, g, b, 0xff]);
+                }
+            }
+        };
+
+        for d in &sixel.data {
+            match d {
+                SixelData::Data(bits) => {
+                    emit_sixel(*bits, foreground, x, y);
+                    x = x.saturating_add(1);
+                }
+                SixelData::Repeat {
+                    repeat_count,
+                    data: bits,
+                } => {
+                    // Pixels beyond the right edge are clipped, so there
+                    // is no need to visit them
+                    let count = (*repeat_count as usize).min(width.saturating_sub(x));
+                    for _ in 0..count {
+                        emit_sixel(*bits, foreground, x, y);
+                        x += 1;
+                    }
+                    x = x.saturating_add(*repeat_count as usize - count);
+                }
+                SixelData::CarriageReturn => x = 0,
+                SixelData::NewLine => {
+                    x = 0;
+                    y = y.saturating_add(6);
+                }
+                SixelData::DefineColorMapRGB { color_number, rgb } => {
+                    if options.honor_color_space {
+                        let (r, g, b) = rgb.to_tuple_rgb8();
+                        registers[register(*color_number)] = [r, g, b];
+                    }
+                }
+                SixelData::DefineColorMapHSL {
+                    color_number,
+                    hue_angle,
+                    lightness,
+                    saturation,
+                } => {
+                    if options.honor_color_space {
+                        registers[register(*color_number)] =
+                            sixel_hls_to_rgb(*hue_angle, *lightness, *saturation);
+                    }
+                }
+                SixelData::SelectColorMapEntry(color_number) => {
+                    foreground = registers[register(*color_number)];
+                }
+            }
+        }
+
+        Ok(Self::new_single_frame(width as u32, height as u32, data))
+    }
+}
+
+/// Converts a color from the sixel HLS color space to sRGB.
+/// Unlike the usual HSL color wheel, sixel places blue at 0 degrees,
+/// red at 120 degrees and green at 240 degrees; lightness and saturation
+/// are percentages.
+pub fn sixel_hls_to_rgb(hue_angle: u16, lightness: u8, saturation: u8) -> [u8; 3] {
+    let hue = (hue_angle as f64 - 120.).rem_euclid(360.);
+    let lightness = lightness.min(100) as f64 / 100.;
+    let saturation = saturation.min(100) as f64 / 100.;
+
+    let chroma = (1. - (2. * lightness - 1.).abs()) * saturation;
+    let sector = hue / 60.;
+    let x = chroma * (1. - (sector % 2. - 1.).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.),
+        1 => (x, chroma, 0.),
+        2 => (0., chroma, x),
+        3 => (0., x, chroma),
+        4 => (x, 0., chroma),
+        _ => (chroma, 0., x),
+    };
+    let m = lightness - chroma / 2.;
+    let channel = |v: f64| ((v + m) * 255.).round().clamp(0., 255.) as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_sixel(body: &str, transparent_background: bool) -> Sixel {
+        use crate::escape::parser::Parser;
+        use crate::escape::Action;
+        let seq = format!(
+            "\x1bP0;{};0q{body}\x1b\\",
+            if transparent_background { 1 } else { 0 }
+        );
+        match Parser::new()
+            .parse_as_vec(seq.as_bytes())
+            .into_iter()
+            .next()
+        {
+            Some(Action::Sixel(sixel)) => *sixel,
+            other => panic!("expected sixel, got {:?}", other),
+        }
+    }
+
+    fn sixel_columns(image: &ImageDataType, y: u32) -> Vec<[u8; 4]> {
+        let (width, _) = image.frame_dimensions(0).unwrap();
+        (0..width).map(|x| image.pixel_at(x, y).unwrap()).collect()
+    }
+
+    #[test]
+    fn sixel_rgb_percentages() {
+        let sixel = parse_sixel("#1;2;100;0;0#2;2;0;50;100#1~~#2~", true);
+        let image = ImageDataType::from_sixel(&sixel, &SixelOptions::default()).unwrap();
+        assert_eq!(image.frame_dimensions(0), Some((3, 6)));
+        for y in 0..6 {
+            assert_eq!(
+                sixel_columns(&image, y),
+                vec![
+                    [0xff, 0, 0, 0xff],
+                    [0xff, 0, 0, 0xff],
+                    [0, 0x80, 0xff, 0xff]
+                ]
+            );
+        }
+
+        // Out of range components saturate at full intensity
+        let sixel = parse_sixel("#1;2;300;256;101#1~", true);
+        let image = ImageDataType::from_sixel(&sixel, &SixelOptions::default()).unwrap();
+        assert_eq!(image.pixel_at(0, 0), Some([0xff, 0xff, 0xff, 0xff]));
+    }
+
+    #[test]
+    fn sixel_hls() {
+        // Sixel hues place blue at 0, red at 120 and green at 240 degrees
+        let sixel = parse_sixel(
+            "#1;1;120;50;100#2;1;240;50;100#3;1;0;50;100#4;1;0;100;0#1~#2~#3~#4~",
+            true,
+        );
+        let image = ImageDataType::from_sixel(&sixel, &SixelOptions::default()).unwrap();
+        assert_eq!(
+            sixel_columns(&image, 0),
+            vec![
+                [0xff, 0, 0, 0xff],
+                [0, 0xff, 0, 0xff],
+                [0, 0, 0xff, 0xff],
+                [0xff, 0xff, 0xff, 0xff],
+            ]
+        );
+    }
+
+    #[test]
+    fn sixel_options() {
+        // Only the top four pixels of the column are drawn
+        let sixel = parse_sixel("#1;2;100;0;0#1N", false);
+        let background = RgbColor::new_8bpc(0x10, 0x20, 0x30);
+        let options = SixelOptions {
+            background: Some(background),
+            ..Default::default()
+        };
+        let image = ImageDataType::from_sixel(&sixel, &options).unwrap();
+        assert_eq!(image.pixel_at(0, 3), Some([0xff, 0, 0, 0xff]));
+        assert_eq!(image.pixel_at(0, 4), Some([0x10, 0x20, 0x30, 0xff]));
+
+        // The image asks for a transparent background, which wins
+        let sixel = parse_sixel("#1;2;100;0;0#1N", true);
+        let image = ImageDataType::from_sixel(&sixel, &options).unwrap();
+        assert_eq!(image.pixel_at(0, 4), Some([0, 0, 0, 0]));
+
+        // Without honoring the color space, the VT340 colors are used
+        let image = ImageDataType::from_sixel(
+            &sixel,
+            &SixelOptions {
+                honor_color_space: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(image.pixel_at(0, 0), Some([0x33, 0x33, 0xcc, 0xff]));
+
+        // Color numbers wrap around the available registers
+        let sixel = parse_sixel("#17;2;0;100;0#1~", true);
+        let image = ImageDataType::from_sixel(
+            &sixel,
+            &SixelOptions {
+                max_registers: 16,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(image.pixel_at(0, 0), Some([0, 0xff, 0, 0xff]));
+    }
+
+    #[test]
+    fn sixel_size_limit() {
+        let options = SixelOptions::default();
+
+        let sixel = parse_sixel("", true);
+        assert!(ImageDataType::from_sixel(&sixel, &options).is_err());
+
+        // A single repeat can describe an enormous image
+        let sixel = parse_sixel("#1!4000000000~", true);
+        assert!(ImageDataType::from_sixel(&sixel, &options).is_err());
+
+        // Pixels beyond the declared raster size are clipped
+        let sixel = parse_sixel("\"1;1;2;6#1!4000000000~", true);
+        let image = ImageDataType::from_sixel(&sixel, &options).unwrap();
+        assert_eq!(image.frame_dimensions(0), Some((2, 6)));
+    }
+}
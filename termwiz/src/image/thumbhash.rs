@@ -0,0 +1,359 @@
+use crate::image::ImageDataType;
+
+impl ImageDataType {
+    /// Computes a [ThumbHash](https://evanw.github.io/thumbhash/) for the
+    /// image; a compact representation of a blurred version of the image
+    /// that can be shown as a placeholder while the full image loads.
+    /// For an animation, the first frame is used.
+    /// Returns `None` for the encoded variants.
+    pub fn thumbhash(&self) -> Option<Vec<u8>> {
+        let (width, height, data) = match self {
+            Self::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => (*width, *height, data),
+            Self::AnimRgba8 { frames, .. } => {
+                let (width, height) = self.frame_dimensions(0)?;
+                (width, height, frames.first()?)
+            }
+            Self::Indexed8 { .. } => return self.clone().to_rgba8().thumbhash(),
+            Self::EncodedFile(_) | Self::EncodedLease(_) => return None,
+        };
+
+        // The encoding is only defined for images up to 100x100
+        const MAX_DIM: u32 = 100;
+        let image = Self::new_single_frame(width, height, data.clone());
+        let image = if width > MAX_DIM || height > MAX_DIM {
+            let scale = MAX_DIM as f64 / width.max(height) as f64;
+            let w = ((width as f64 * scale).round() as u32).max(1);
+            let h = ((height as f64 * scale).round() as u32).max(1);
+            image.resize(w, h)?
+        } else {
+            image
+        };
+
+        match &image {
+            Self::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => Some(rgba_to_thumb_hash(*width as usize, *height as usize, data)),
+            _ => None,
+        }
+    }
+
+    /// Rehydrates a blurred `Rgba8` preview of `width` x `height` pixels
+    /// from a hash produced by `thumbhash`.
+    /// Returns `None` if the hash is malformed.
+    pub fn from_thumbhash(hash: &[u8], width: u32, height: u32) -> Option<Self> {
+        let (w, h, data) = thumb_hash_to_rgba(hash)?;
+        Self::new_single_frame(w as u32, h as u32, data).resize(width, height)
+    }
+}
+
+/// Encodes `rgba`, which must be no larger than 100x100, as a ThumbHash.
+/// This is a port of the reference implementation at
+/// <https://github.com/evanw/thumbhash>: the image is composited over
+/// its average color, converted to the LPQA color space and the low
+/// frequency DCT coefficients of each channel are quantized to 4 bits.
+fn rgba_to_thumb_hash(w: usize, h: usize, rgba: &[u8]) -> Vec<u8> {
+    use std::f32::consts::PI;
+
+    let pixels = rgba.chunks_exact(4);
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0., 0., 0., 0.);
+    for p in pixels.clone() {
+        let alpha = p[3] as f32 / 255.;
+        avg_r += alpha / 255. * p[0] as f32;
+        avg_g += alpha / 255. * p[1] as f32;
+        avg_b += alpha / 255. * p[2] as f32;
+        avg_a += alpha;
+    }
+    if avg_a > 0. {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    let has_alpha = avg_a < (w * h) as f32;
+    let l_limit = if has_alpha { 5. } else { 7. };
+    let longest = w.max(h) as f32;
+    let lx = ((l_limit * w as f32 / longest).round() as usize).max(1);
+    let ly = ((l_limit * h as f32 / longest).round() as usize).max(1);
+
+    let mut l = Vec::with_capacity(w * h);
+    let mut p = Vec::with_capacity(w * h);
+    let mut q = Vec::with_capacity(w * h);
+    let mut a = Vec::with_capacity(w * h);
+    for px in pixels {
+        let alpha = px[3] as f32 / 255.;
+        let r = avg_r * (1. - alpha) + alpha / 255. * px[0] as f32;
+        let g = avg_g * (1. - alpha) + alpha / 255. * px[1] as f32;
+        let b = avg_b * (1. - alpha) + alpha / 255. * px[2] as f32;
+        l.push((r + g + b) / 3.);
+        p.push((r + g) / 2. - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    // Returns the DC coefficient, the AC coefficients normalized
+    // to 0..=1 and the scale by which they were normalized
+    let encode_channel = |channel: &[f32], nx: usize, ny: usize| {
+        let mut dc = 0.;
+        let mut ac = vec![];
+        let mut scale = 0f32;
+        let mut fx = vec![0.; w];
+        for cy in 0..ny {
+            let mut cx = 0;
+            while cx * ny < nx * (ny - cy) {
+                for (x, f) in fx.iter_mut().enumerate() {
+                    *f = (PI / w as f32 * cx as f32 * (x as f32 + 0.5)).cos();
+                }
+                let mut f = 0.;
+                for y in 0..h {
+                    let fy = (PI / h as f32 * cy as f32 * (y as f32 + 0.5)).cos();
+                    for x in 0..w {
+                        f += channel[x + y * w] * fx[x] * fy;
+                    }
+                }
+                f /= (w * h) as f32;
+                if cx > 0 || cy > 0 {
+                    ac.push(f);
+                    scale = scale.max(f.abs());
+                } else {
+                    dc = f;
+                }
+                cx += 1;
+            }
+        }
+        if scale > 0. {
+            for f in &mut ac {
+                *f = 0.5 + 0.5 / scale * *f;
+            }
+        }
+        (dc, ac, scale)
+    };
+
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, 3, 3);
+    let (a_dc, a_ac, a_scale) = if has_alpha {
+        encode_channel(&a, 5, 5)
+    } else {
+        (1., vec![], 1.)
+    };
+
+    let is_landscape = w > h;
+    let header24 = (63. * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31. * l_scale).round() as u32) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if is_landscape { ly } else { lx }) as u16
+        | ((63. * p_scale).round() as u16) << 3
+        | ((63. * q_scale).round() as u16) << 9
+        | (is_landscape as u16) << 15;
+    let mut hash = vec![
+        header24 as u8,
+        (header24 >> 8) as u8,
+        (header24 >> 16) as u8,
+        header16 as u8,
+        (header16 >> 8) as u8,
+    ];
+    if has_alpha {
+        hash.push((15. * a_dc).round() as u8 | ((15. * a_scale).round() as u8) << 4);
+    }
+
+    // The AC coefficients are packed as nibbles, low nibble first
+    let ac = l_ac.iter().chain(&p_ac).chain(&q_ac).chain(&a_ac);
+    for (i, f) in ac.enumerate() {
+        let nibble = (15. * f).round() as u8;
+        if i % 2 == 0 {
+            hash.push(nibble);
+        } else if let Some(last) = hash.last_mut() {
+            *last |= nibble << 4;
+        }
+    }
+    hash
+}
+
+/// Decodes a ThumbHash produced by `rgba_to_thumb_hash` into the
+/// width, height and RGBA pixels of an image of up to 32x32 pixels
+/// with approximately the aspect ratio of the original image.
+/// Returns `None` if the hash is malformed.
+fn thumb_hash_to_rgba(hash: &[u8]) -> Option<(usize, usize, Vec<u8>)> {
+    use std::f32::consts::PI;
+
+    let header24 =
+        *hash.first()? as u32 | (*hash.get(1)? as u32) << 8 | (*hash.get(2)? as u32) << 16;
+    let header16 = *hash.get(3)? as u16 | (*hash.get(4)? as u16) << 8;
+    let l_dc = (header24 & 63) as f32 / 63.;
+    let p_dc = ((header24 >> 6) & 63) as f32 / 31.5 - 1.;
+    let q_dc = ((header24 >> 12) & 63) as f32 / 31.5 - 1.;
+    let l_scale = ((header24 >> 18) & 31) as f32 / 31.;
+    let has_alpha = header24 >> 23 != 0;
+    let p_scale = ((header16 >> 3) & 63) as f32 / 63.;
+    let q_scale = ((header16 >> 9) & 63) as f32 / 63.;
+    let is_landscape = header16 >> 15 != 0;
+
+    let l_max = if has_alpha { 5 } else { 7 };
+    let l_min = (header16 & 7) as usize;
+    if l_min == 0 {
+        return None;
+    }
+    let (lx, ly) = if is_landscape {
+        (l_max, l_min)
+    } else {
+        (l_min, l_max)
+    };
+    let ratio = lx as f32 / ly as f32;
+    let (lx, ly) = (lx.max(3), ly.max(3));
+
+    let (a_dc, a_scale) = if has_alpha {
+        let a = *hash.get(5)?;
+        ((a & 15) as f32 / 15., (a >> 4) as f32 / 15.)
+    } else {
+        (1., 1.)
+    };
+
+    let ac_start = if has_alpha { 6 } else { 5 };
+    let mut ac_index = 0;
+    let mut decode_channel = |nx: usize, ny: usize, scale: f32| -> Option<Vec<f32>> {
+        let mut ac = vec![];
+        for cy in 0..ny {
+            let mut cx = if cy > 0 { 0 } else { 1 };
+            while cx * ny < nx * (ny - cy) {
+                let data = *hash.get(ac_start + (ac_index >> 1))?;
+                let nibble = (data >> ((ac_index & 1) << 2)) & 15;
+                ac.push((nibble as f32 / 7.5 - 1.) * scale);
+                ac_index += 1;
+                cx += 1;
+            }
+        }
+        Some(ac)
+    };
+    let l_ac = decode_channel(lx, ly, l_scale)?;
+    // The saturation is boosted to compensate for the quantization
+    let p_ac = decode_channel(3, 3, p_scale * 1.25)?;
+    let q_ac = decode_channel(3, 3, q_scale * 1.25)?;
+    let a_ac = if has_alpha {
+        decode_channel(5, 5, a_scale)?
+    } else {
+        vec![]
+    };
+
+    let (w, h) = if ratio > 1. {
+        (32, ((32. / ratio).round() as usize).max(1))
+    } else {
+        (((32. * ratio).round() as usize).max(1), 32)
+    };
+    let mut rgba = Vec::with_capacity(w * h * 4);
+    let mut fx = [0f32; 7];
+    let mut fy = [0f32; 7];
+    for y in 0..h {
+        for x in 0..w {
+            for (cx, f) in fx.iter_mut().enumerate() {
+                *f = (PI / w as f32 * (x as f32 + 0.5) * cx as f32).cos();
+            }
+            for (cy, f) in fy.iter_mut().enumerate() {
+                *f = (PI / h as f32 * (y as f32 + 0.5) * cy as f32).cos();
+            }
+
+            // Sums the AC coefficients of a channel, which are stored
+            // in the same order as they were produced by encode_channel
+            let sum = |ac: &[f32], nx: usize, ny: usize| {
+                let mut total = 0.;
+                let mut j = 0;
+                for (cy, fy) in fy.iter().enumerate().take(ny) {
+                    let mut cx = if cy > 0 { 0 } else { 1 };
+                    while cx * ny < nx * (ny - cy) {
+                        total += ac[j] * fx[cx] * fy * 2.;
+                        j += 1;
+                        cx += 1;
+                    }
+                }
+                total
+            };
+            let l = l_dc + sum(&l_ac, lx, ly);
+            let p = p_dc + sum(&p_ac, 3, 3);
+            let q = q_dc + sum(&q_ac, 3, 3);
+            let a = if has_alpha {
+                a_dc + sum(&a_ac, 5, 5)
+            } else {
+                a_dc
+            };
+
+            let b = l - 2. / 3. * p;
+            let r = (3. * l - b + q) / 2.;
+            let g = r - q;
+            for c in [r, g, b, a] {
+                rgba.push((c.clamp(0., 1.) * 255.) as u8);
+            }
+        }
+    }
+    Some((w, h, rgba))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thumbhash_roundtrip() {
+        let color = [0x20u8, 0x80, 0xc0, 0xff];
+        let data: Vec<u8> = color.iter().copied().cycle().take(120 * 80 * 4).collect();
+        let image = ImageDataType::new_single_frame(120, 80, data);
+        let hash = image.thumbhash().unwrap();
+        assert!(hash.len() <= 25, "{:?}", hash);
+
+        let preview = ImageDataType::from_thumbhash(&hash, 12, 8).unwrap();
+        match &preview {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                data,
+                ..
+            } => {
+                assert_eq!((*width, *height), (12, 8));
+                for px in data.chunks_exact(4) {
+                    for c in 0..4 {
+                        assert!(
+                            (px[c] as i32 - color[c] as i32).abs() < 16,
+                            "{:?} vs {:?}",
+                            px,
+                            color
+                        );
+                    }
+                }
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        // A left to right gradient over a transparent bottom half
+        // should keep its orientation and its transparency
+        let mut data = vec![];
+        for y in 0..40 {
+            for x in 0..60u32 {
+                let alpha = if y < 20 { 0xff } else { 0 };
+                data.extend_from_slice(&[(x * 255 / 59) as u8, 0x40, 0x40, alpha]);
+            }
+        }
+        let hash = ImageDataType::new_single_frame(60, 40, data)
+            .thumbhash()
+            .unwrap();
+        match ImageDataType::from_thumbhash(&hash, 30, 20).unwrap() {
+            ImageDataType::Rgba8 { data, .. } => {
+                let px = |x: usize, y: usize| &data[(y * 30 + x) * 4..(y * 30 + x) * 4 + 4];
+                assert!(px(2, 2)[0] + 64 < px(27, 2)[0], "{:?}", &data[..]);
+                assert!(px(15, 2)[3] > 0xc0, "{:?}", px(15, 2));
+                assert!(px(15, 17)[3] < 0x40, "{:?}", px(15, 17));
+            }
+            other => panic!("expected Rgba8, got {:?}", other),
+        }
+
+        assert!(ImageDataType::from_thumbhash(&hash[..3], 30, 20).is_none());
+        assert!(ImageDataType::EncodedFile(vec![]).thumbhash().is_none());
+    }
+}
@@ -589,12 +589,20 @@ impl TerminfoRenderer {
                             && image.bottom_right == TextureCoordinate::new_f32(1.0, 1.0)
                         {
                             // The whole image is requested, so we can send the
-                            // original image bytes over
+                            // original image bytes over, or encode the decoded
+                            // pixels as a PNG
                             match &*image.image.data() {
                                 ImageDataType::EncodedFile(data) => data.to_vec(),
                                 ImageDataType::EncodedLease(lease) => lease.get_data()?,
-                                ImageDataType::AnimRgba8 { .. } | ImageDataType::Rgba8 { .. } => {
-                                    unimplemented!()
+                                #[cfg(feature = "use_image")]
+                                decoded => decoded.encode_apng()?,
+                                #[cfg(not(feature = "use_image"))]
+                                _ => {
+                                    return Err(anyhow::anyhow!(
+                                        "cannot send decoded image data \
+                                         without the use_image feature"
+                                    )
+                                    .into())
                                 }
                             }
                         } else {
@@ -1293,4 +1301,42 @@ mod test {
             ]
         );
     }
+
+    #[cfg(feature = "use_image")]
+    #[test]
+    fn iterm2_indexed_image() {
+        use crate::image::ImageData;
+        use crate::surface::change::Image;
+        use std::sync::Arc;
+
+        let mut out = FakeTerm::new(xterm_terminfo_with_hints(
+            ProbeHints::default().iterm2_image(Some(true)),
+        ));
+        let data = ImageDataType::Indexed8 {
+            palette: vec![[0xff, 0, 0, 0xff]],
+            indices: vec![0; 4],
+            width: 2,
+            height: 2,
+        };
+        out.render(&[Change::Image(Image {
+            width: 1,
+            height: 1,
+            top_left: TextureCoordinate::new_f32(0.0, 0.0),
+            bottom_right: TextureCoordinate::new_f32(1.0, 1.0),
+            image: Arc::new(ImageData::with_data(data)),
+        })])
+        .unwrap();
+
+        // The OSC is terminated by ST, which the parser reports separately
+        let st = Action::Esc(Esc::Code(EscCode::StringTerminator));
+        match out.parse().as_slice() {
+            [Action::OperatingSystemCommand(osc), last] if *last == st => match &**osc {
+                OperatingSystemCommand::ITermProprietary(ITermProprietary::File(file)) => {
+                    assert!(file.data.starts_with(b"\x89PNG"));
+                }
+                other => panic!("unexpected osc {:?}", other),
+            },
+            other => panic!("unexpected actions {:?}", other),
+        }
+    }
 }
@@ -30,6 +30,8 @@ use std::os::unix::process::CommandExt;
 #[cfg(windows)]
 use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use thiserror::Error;
@@ -59,6 +61,8 @@ pub struct Client {
     client_domain_config: ClientDomainConfig,
     pub is_reconnectable: bool,
     pub is_local: bool,
+    /// Set while the connection has been lost and is being re-established
+    reconnecting: Arc<AtomicBool>,
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -1044,6 +1048,8 @@ impl Client {
         let is_local = reconnectable.is_local();
         let (sender, mut receiver) = unbounded();
         let client_id = ClientId::new();
+        let reconnecting = Arc::new(AtomicBool::new(false));
+        let reconnecting_thread = Arc::clone(&reconnecting);
 
         thread::spawn(move || {
             const BASE_INTERVAL: Duration = Duration::from_secs(1);
@@ -1074,6 +1080,7 @@ impl Client {
 
                     let mut ui = ConnectionUI::new();
                     ui.title("wezterm: Reconnecting...");
+                    reconnecting_thread.store(true, Ordering::Relaxed);
 
                     loop {
                         ui.sleep_with_reason(
@@ -1086,6 +1093,7 @@ impl Client {
                         match reconnectable.connect(initial, &mut ui, no_auto_start) {
                             Ok(_) => {
                                 backoff = BASE_INTERVAL;
+                                reconnecting_thread.store(false, Ordering::Relaxed);
                                 log::error!("Reconnected!");
                                 promise::spawn::spawn_into_main_thread(async move {
                                     ClientDomain::reattach(local_domain_id, ui).await.ok();
@@ -1138,9 +1146,16 @@ impl Client {
             is_local,
             client_id,
             client_domain_config,
+            reconnecting,
         }
     }
 
+    /// Returns true if the connection to the server was lost
+    /// and is in the process of being re-established
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting.load(Ordering::Relaxed)
+    }
+
     pub fn into_client_domain_config(self) -> ClientDomainConfig {
         self.client_domain_config
     }
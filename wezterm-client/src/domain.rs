@@ -14,6 +14,7 @@ use mux::{Mux, MuxNotification};
 use portable_pty::CommandBuilder;
 use promise::spawn::spawn_into_new_thread;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use wezterm_term::TerminalSize;
 
@@ -255,6 +256,8 @@ pub struct ClientDomain {
     label: String,
     inner: Mutex<Option<Arc<ClientInner>>>,
     local_domain_id: DomainId,
+    /// Set while attach is establishing the initial connection
+    connecting: AtomicBool,
 }
 
 async fn update_remote_workspace(
@@ -405,6 +408,7 @@ impl ClientDomain {
             label,
             inner: Mutex::new(None),
             local_domain_id,
+            connecting: AtomicBool::new(false),
         }
     }
 
@@ -412,6 +416,21 @@ impl ClientDomain {
         self.inner.lock().unwrap().as_ref().map(Arc::clone)
     }
 
+    pub fn config(&self) -> &ClientDomainConfig {
+        &self.config
+    }
+
+    /// Returns true if the domain is in the process of connecting
+    /// to the server, either while attaching or after the connection
+    /// was lost and is being re-established.
+    pub fn is_connecting(&self) -> bool {
+        self.connecting.load(Ordering::Relaxed)
+            || self
+                .inner()
+                .map(|inner| inner.client.is_reconnecting())
+                .unwrap_or(false)
+    }
+
     pub fn connect_automatically(&self) -> bool {
         self.config.connect_automatically()
     }
@@ -944,6 +963,7 @@ impl Domain for ClientDomain {
         });
         ui.title("wezterm: Connecting...");
 
+        self.connecting.store(true, Ordering::Relaxed);
         ui.async_run_and_log_error({
             let ui = ui.clone();
             async move {
@@ -979,9 +999,11 @@ impl Domain for ClientDomain {
         })
         .await
         .map_err(|e| {
+            self.connecting.store(false, Ordering::Relaxed);
             ui.output_str(&format!("Error during attach: {:#}\n", e));
             e
         })?;
+        self.connecting.store(false, Ordering::Relaxed);
 
         ui.output_str("Attached!\n");
         drop(activity);
@@ -17,14 +17,14 @@ use lfucache::LfuCache;
 use once_cell::sync::Lazy;
 use ordered_float::NotNan;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Seek;
 use std::rc::Rc;
 use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
-use std::sync::{Arc, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::time::{Duration, Instant};
 use termwiz::color::RgbColor;
-use termwiz::image::{ImageData, ImageDataType};
+use termwiz::image::{on_image_drop, ImageData, ImageDataType, ImageDropHook};
 use termwiz::surface::CursorShape;
 use wezterm_blob_leases::{BlobLease, BlobManager, BoxedReader};
 use wezterm_font::units::*;
@@ -192,7 +192,9 @@ impl<'a> BitmapImage for DecodedImageHandle<'a> {
         match &*self.h {
             ImageDataType::Rgba8 { data, .. } => data.as_ptr(),
             ImageDataType::AnimRgba8 { frames, .. } => frames[self.current_frame].as_ptr(),
-            ImageDataType::EncodedLease(_) | ImageDataType::EncodedFile(_) => unreachable!(),
+            ImageDataType::EncodedLease(_)
+            | ImageDataType::EncodedFile(_)
+            | ImageDataType::Indexed8 { .. } => unreachable!(),
         }
     }
 
@@ -202,9 +204,14 @@ impl<'a> BitmapImage for DecodedImageHandle<'a> {
 
     fn image_dimensions(&self) -> (usize, usize) {
         match &*self.h {
-            ImageDataType::Rgba8 { width, height, .. }
-            | ImageDataType::AnimRgba8 { width, height, .. } => (*width as usize, *height as usize),
-            ImageDataType::EncodedLease(_) | ImageDataType::EncodedFile(_) => unreachable!(),
+            ImageDataType::Rgba8 { width, height, .. } => (*width as usize, *height as usize),
+            ImageDataType::AnimRgba8 { .. } => {
+                let (width, height) = self.h.frame_dimensions(self.current_frame).unwrap();
+                (width as usize, height as usize)
+            }
+            ImageDataType::EncodedLease(_)
+            | ImageDataType::EncodedFile(_)
+            | ImageDataType::Indexed8 { .. } => unreachable!(),
         }
     }
 }
@@ -471,21 +478,32 @@ impl std::fmt::Debug for FrameState {
     }
 }
 
+/// The resampled copy of an image that was scaled down to fit in the
+/// atlas is discarded once this many frames have been painted without
+/// uploading it again
+const SCALED_IMAGE_IDLE_FRAMES: u64 = 60;
+
+/// Shown in place of images that could not be decoded
+static PLACEHOLDER: Lazy<Arc<ImageData>> =
+    Lazy::new(|| Arc::new(ImageData::with_data(ImageDataType::placeholder())));
+
 #[derive(Debug)]
 pub struct DecodedImage {
     frame_start: RefCell<Instant>,
     current_frame: RefCell<usize>,
-    image: Arc<ImageData>,
+    /// Held weakly so that caching the image doesn't prevent it from
+    /// being dropped, and reported to DroppedImages, once it is no
+    /// longer referenced by any terminal
+    image: Weak<ImageData>,
     frames: RefCell<Option<FrameState>>,
 }
 
 impl DecodedImage {
     fn placeholder() -> Self {
-        let image = ImageData::with_data(ImageDataType::placeholder());
         Self {
             frame_start: RefCell::new(Instant::now()),
             current_frame: RefCell::new(0),
-            image: Arc::new(image),
+            image: Arc::downgrade(&PLACEHOLDER),
             frames: RefCell::new(None),
         }
     }
@@ -495,7 +513,7 @@ impl DecodedImage {
             Ok(rx) => Self {
                 frame_start: RefCell::new(Instant::now()),
                 current_frame: RefCell::new(0),
-                image: Arc::clone(image_data),
+                image: Arc::downgrade(image_data),
                 frames: RefCell::new(Some(FrameState::new(rx))),
             },
             Err(err) => {
@@ -506,6 +524,15 @@ impl DecodedImage {
     }
 
     fn load(image_data: &Arc<ImageData>) -> Self {
+        if image_data.lazy_frame_info().is_some() {
+            // Frames are decoded on demand by the ImageData itself
+            return Self {
+                frame_start: RefCell::new(Instant::now()),
+                current_frame: RefCell::new(0),
+                image: Arc::downgrade(image_data),
+                frames: RefCell::new(None),
+            };
+        }
         match &*image_data.data() {
             ImageDataType::EncodedLease(lease) => {
                 Self::start_frame_decoder(lease.clone(), image_data)
@@ -527,7 +554,7 @@ impl DecodedImage {
                 Self {
                     frame_start: RefCell::new(Instant::now()),
                     current_frame: RefCell::new(current_frame),
-                    image: Arc::clone(image_data),
+                    image: Arc::downgrade(image_data),
                     frames: RefCell::new(None),
                 }
             }
@@ -535,13 +562,107 @@ impl DecodedImage {
             _ => Self {
                 frame_start: RefCell::new(Instant::now()),
                 current_frame: RefCell::new(0),
-                image: Arc::clone(image_data),
+                image: Arc::downgrade(image_data),
                 frames: RefCell::new(None),
             },
         }
     }
 }
 
+/// Collects the ids of the tracked ImageData instances that have been
+/// dropped, which may happen on any thread, until the GlyphCache gets
+/// around to releasing the associated resources
+struct DroppedImages {
+    state: Arc<Mutex<DroppedState>>,
+    _hook: ImageDropHook,
+}
+
+#[derive(Default)]
+struct DroppedState {
+    /// The ids passed to DroppedImages::track that are still alive
+    tracked: HashSet<u64>,
+    /// The ids of the tracked instances that have since been dropped
+    dropped: Vec<u64>,
+}
+
+impl DroppedImages {
+    fn new() -> Self {
+        let state = Arc::new(Mutex::new(DroppedState::default()));
+        let hook = {
+            let state = Arc::clone(&state);
+            on_image_drop(move |id| {
+                // Every ImageData in the process is reported here, so
+                // only the ones that we know about are retained
+                let mut state = state.lock().unwrap();
+                if state.tracked.remove(&id) {
+                    state.dropped.push(id);
+                }
+            })
+        };
+        Self { state, _hook: hook }
+    }
+
+    /// Arranges for `id` to be returned by take once it is dropped
+    fn track(&self, id: u64) {
+        self.state.lock().unwrap().tracked.insert(id);
+    }
+
+    fn take(&self) -> Vec<u64> {
+        std::mem::take(&mut self.state.lock().unwrap().dropped)
+    }
+}
+
+/// The sprites for the frames of images, keyed by the hash of the
+/// frame, or some other key that identifies it.  The keys are also
+/// recorded against the hash of the image they belong to, so that all
+/// of them can be released once the image is dropped.
+#[derive(Default)]
+struct FrameCache {
+    sprites: HashMap<[u8; 32], Sprite>,
+    keys: HashMap<[u8; 32], HashSet<[u8; 32]>>,
+}
+
+impl FrameCache {
+    fn get(&self, key: &[u8; 32]) -> Option<&Sprite> {
+        self.sprites.get(key)
+    }
+
+    fn insert(&mut self, image_hash: [u8; 32], key: [u8; 32], sprite: Sprite) {
+        self.keys.entry(image_hash).or_default().insert(key);
+        self.sprites.insert(key, sprite);
+    }
+
+    /// Removes the sprites for all of the frames of the image
+    fn remove_image(&mut self, image_hash: &[u8; 32]) {
+        for key in self.keys.remove(image_hash).unwrap_or_default() {
+            self.sprites.remove(&key);
+        }
+    }
+}
+
+/// How GlyphCache::cached_image_impl should render the current
+/// frame of an image
+#[derive(Debug, Clone, Copy)]
+struct ImageRenderOptions {
+    padding: Option<usize>,
+    min_frame_duration: Duration,
+    allow_image: AllowImage,
+    animations_paused: bool,
+}
+
+impl ImageRenderOptions {
+    fn scale_down(&self) -> Option<usize> {
+        match self.allow_image {
+            AllowImage::Scale(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn is_playing(&self, image: &ImageData) -> bool {
+        image.is_playing() && !self.animations_paused
+    }
+}
+
 /// A number of items here are HashMaps rather than LfuCaches;
 /// eviction is managed by recreating Self when the Atlas is filled
 pub struct GlyphCache {
@@ -549,12 +670,24 @@ pub struct GlyphCache {
     pub atlas: Atlas,
     pub fonts: Rc<FontConfiguration>,
     pub image_cache: LfuCache<[u8; 32], DecodedImage>,
-    frame_cache: HashMap<[u8; 32], Sprite>,
+    frame_cache: FrameCache,
     line_glyphs: HashMap<LineKey, Sprite>,
     pub block_glyphs: HashMap<SizedBlockKey, Sprite>,
     pub cursor_glyphs: HashMap<(Option<CursorShape>, u8), Sprite>,
     pub color: HashMap<(RgbColor, NotNan<f32>), Sprite>,
     min_frame_duration: Duration,
+    /// The hash of each image that has been resolved by cached_image,
+    /// keyed by the id of the ImageData
+    image_ids: HashMap<u64, [u8; 32]>,
+    /// The number of entries in image_ids for each hash
+    image_refs: HashMap<[u8; 32], usize>,
+    dropped_images: DroppedImages,
+    /// The images that retain resampled copies for cached_scaled_image,
+    /// keyed by the id of the ImageData, so that advance_frame can
+    /// discard the copies that are no longer needed
+    scaled_images: HashMap<u64, Weak<ImageData>>,
+    /// The number of the frame that is being painted; see advance_frame
+    pub frame: u64,
 }
 
 impl GlyphCache {
@@ -571,13 +704,18 @@ impl GlyphCache {
                 |config| config.glyph_cache_image_cache_size,
                 &fonts.config(),
             ),
-            frame_cache: HashMap::new(),
+            frame_cache: FrameCache::default(),
             atlas,
             line_glyphs: HashMap::new(),
             block_glyphs: HashMap::new(),
             cursor_glyphs: HashMap::new(),
             color: HashMap::new(),
             min_frame_duration: Duration::from_millis(1000 / fonts.config().max_fps as u64),
+            image_ids: HashMap::new(),
+            image_refs: HashMap::new(),
+            dropped_images: DroppedImages::new(),
+            scaled_images: HashMap::new(),
+            frame: 0,
         })
     }
 }
@@ -600,13 +738,18 @@ impl GlyphCache {
                 |config| config.glyph_cache_image_cache_size,
                 &fonts.config(),
             ),
-            frame_cache: HashMap::new(),
+            frame_cache: FrameCache::default(),
             atlas,
             line_glyphs: HashMap::new(),
             block_glyphs: HashMap::new(),
             cursor_glyphs: HashMap::new(),
             color: HashMap::new(),
             min_frame_duration: Duration::from_millis(1000 / fonts.config().max_fps as u64),
+            image_ids: HashMap::new(),
+            image_refs: HashMap::new(),
+            dropped_images: DroppedImages::new(),
+            scaled_images: HashMap::new(),
+            frame: 0,
         })
     }
 }
@@ -884,32 +1027,86 @@ impl GlyphCache {
     }
 
     fn cached_image_impl(
-        frame_cache: &mut HashMap<[u8; 32], Sprite>,
+        frame_cache: &mut FrameCache,
         atlas: &mut Atlas,
         decoded: &DecodedImage,
-        padding: Option<usize>,
-        min_frame_duration: Duration,
-        allow_image: AllowImage,
+        image: &ImageData,
+        options: ImageRenderOptions,
     ) -> anyhow::Result<(Sprite, Option<Instant>, LoadState)> {
+        // This must not hold the lock on the image data, which is
+        // also taken by the lazy animation when it starts decoding
+        if let Some((size, durations)) = image.lazy_frame_info() {
+            return Self::cached_lazy_image_impl(
+                frame_cache,
+                atlas,
+                decoded,
+                image,
+                size,
+                durations,
+                options,
+            );
+        }
+
+        let padding = options.padding;
+        let min_frame_duration = options.min_frame_duration;
+        let scale_down = options.scale_down();
+        let playing = options.is_playing(image);
+
         let mut handle = DecodedImageHandle {
-            h: decoded.image.data(),
+            h: image.data(),
             current_frame: *decoded.current_frame.borrow(),
         };
 
-        let scale_down = match allow_image {
-            AllowImage::Scale(n) => Some(n),
-            _ => None,
-        };
-
         match &*handle.h {
-            ImageDataType::Rgba8 { hash, .. } => {
+            ImageDataType::Rgba8 {
+                hash,
+                premultiplied,
+                ..
+            } => {
                 if let Some(sprite) = frame_cache.get(hash) {
                     return Ok((sprite.clone(), None, LoadState::Loaded));
                 }
+                let sprite = if *premultiplied {
+                    // The atlas holds straight alpha, so a premultiplied
+                    // image is converted before it is uploaded
+                    let image = match (*handle.h).clone().to_straight_alpha() {
+                        ImageDataType::Rgba8 {
+                            data,
+                            width,
+                            height,
+                            ..
+                        } => Image::from_raw(width as usize, height as usize, data),
+                        _ => unreachable!(),
+                    };
+                    atlas.allocate_with_padding(&image, padding, scale_down)
+                } else {
+                    atlas.allocate_with_padding(&handle, padding, scale_down)
+                }
+                .context("atlas.allocate_with_padding")?;
+                frame_cache.insert(image.hash(), *hash, sprite.clone());
+
+                return Ok((sprite, None, LoadState::Loaded));
+            }
+            ImageDataType::Indexed8 { .. } => {
+                let hash = image.hash();
+                if let Some(sprite) = frame_cache.get(&hash) {
+                    return Ok((sprite.clone(), None, LoadState::Loaded));
+                }
+                // The atlas needs RGBA pixels; the expanded copy is only
+                // kept for as long as it takes to upload it
+                let image = match (*handle.h).clone().to_rgba8() {
+                    ImageDataType::Rgba8 {
+                        data,
+                        width,
+                        height,
+                        ..
+                    } => Image::from_raw(width as usize, height as usize, data),
+                    _ => unreachable!(),
+                };
                 let sprite = atlas
-                    .allocate_with_padding(&handle, padding, scale_down)
+                    .allocate_with_padding(&image, padding, scale_down)
                     .context("atlas.allocate_with_padding")?;
-                frame_cache.insert(*hash, sprite.clone());
+                frame_cache.insert(hash, hash, sprite.clone());
 
                 return Ok((sprite, None, LoadState::Loaded));
             }
@@ -922,7 +1119,7 @@ impl GlyphCache {
                 let mut next = None;
                 let mut decoded_frame_start = decoded.frame_start.borrow_mut();
                 let mut decoded_current_frame = decoded.current_frame.borrow_mut();
-                if frames.len() > 1 {
+                if frames.len() > 1 && playing {
                     let now = Instant::now();
 
                     // We round up the frame duration to at least the minimum
@@ -954,6 +1151,10 @@ impl GlyphCache {
                     }
 
                     next.replace(next_due);
+                } else {
+                    // Playback is paused: hold the current frame, and
+                    // give it its full duration once playback resumes
+                    *decoded_frame_start = Instant::now();
                 }
 
                 let hash = hashes[*decoded_current_frame];
@@ -966,16 +1167,9 @@ impl GlyphCache {
                     .allocate_with_padding(&handle, padding, scale_down)
                     .context("atlas.allocate_with_padding")?;
 
-                frame_cache.insert(hash, sprite.clone());
+                frame_cache.insert(image.hash(), hash, sprite.clone());
 
-                return Ok((
-                    sprite,
-                    Some(
-                        *decoded_frame_start
-                            + durations[*decoded_current_frame].max(min_frame_duration),
-                    ),
-                    LoadState::Loaded,
-                ));
+                return Ok((sprite, next, LoadState::Loaded));
             }
             ImageDataType::EncodedLease(_) | ImageDataType::EncodedFile(_) => {
                 let mut frames = decoded.frames.borrow_mut();
@@ -1006,18 +1200,25 @@ impl GlyphCache {
                 // <https://github.com/wez/wezterm/issues/3260>
                 let mut next_due =
                     *decoded_frame_start + frames.frame_duration().max(min_frame_duration);
-                if now >= next_due {
-                    // Advance to next frame
-                    if frames.load_next_frame() {
-                        *decoded_current_frame = *decoded_current_frame + 1;
-                        *decoded_frame_start = now;
-                        next_due =
-                            *decoded_frame_start + frames.frame_duration().max(min_frame_duration);
-                        handle.current_frame = *decoded_current_frame;
+                if playing {
+                    if now >= next_due {
+                        // Advance to next frame
+                        if frames.load_next_frame() {
+                            *decoded_current_frame = *decoded_current_frame + 1;
+                            *decoded_frame_start = now;
+                            next_due = *decoded_frame_start
+                                + frames.frame_duration().max(min_frame_duration);
+                            handle.current_frame = *decoded_current_frame;
+                        }
                     }
-                }
 
-                next.replace(next_due);
+                    next.replace(next_due);
+                } else {
+                    // Hold the current frame without scheduling a repaint.
+                    // Pushing the start time along means that the frame
+                    // gets its full duration once playback resumes.
+                    *decoded_frame_start = now;
+                }
 
                 let hash = frames.frame_hash();
 
@@ -1036,7 +1237,7 @@ impl GlyphCache {
                 );
                 let sprite = atlas.allocate_with_padding(&frame, padding, scale_down)?;
 
-                frame_cache.insert(hash, sprite.clone());
+                frame_cache.insert(image.hash(), hash, sprite.clone());
 
                 Ok((
                     sprite,
@@ -1047,38 +1248,255 @@ impl GlyphCache {
         }
     }
 
+    /// Resolves the sprite for the current frame of an animation whose
+    /// frames are decoded on demand by `ImageData::frame_data`.
+    /// That never blocks: when the next frame isn't ready yet, the current
+    /// frame is held a little longer, and until the first frame is ready,
+    /// a transparent placeholder is shown.
+    fn cached_lazy_image_impl(
+        frame_cache: &mut FrameCache,
+        atlas: &mut Atlas,
+        decoded: &DecodedImage,
+        image: &ImageData,
+        (width, height): (u32, u32),
+        durations: &[Duration],
+        options: ImageRenderOptions,
+    ) -> anyhow::Result<(Sprite, Option<Instant>, LoadState)> {
+        let padding = options.padding;
+        let min_frame_duration = options.min_frame_duration;
+        let scale_down = options.scale_down();
+        let playing = options.is_playing(image);
+        let mut frame_start = decoded.frame_start.borrow_mut();
+        let mut current_frame = decoded.current_frame.borrow_mut();
+        let now = Instant::now();
+
+        let mut next = None;
+        if playing {
+            // See cached_image_impl regarding the minimum frame duration
+            let mut next_due = *frame_start + durations[*current_frame].max(min_frame_duration);
+            if now >= next_due {
+                let following = (*current_frame + 1) % durations.len();
+                if image.has_frame(following) {
+                    *current_frame = following;
+                    *frame_start = now;
+                    next_due = now + durations[following].max(min_frame_duration);
+                } else {
+                    // Hold the current frame and check again shortly
+                    next_due = now + min_frame_duration;
+                }
+            }
+            next.replace(next_due);
+        } else {
+            *frame_start = now;
+        }
+
+        // Hashing the frame data on every repaint would be expensive,
+        // so the sprites are keyed by the image and the frame index
+        let mut key = image.hash().to_vec();
+        key.extend_from_slice(&current_frame.to_le_bytes());
+        let key = ImageDataType::hash_bytes(&key);
+        if let Some(sprite) = frame_cache.get(&key) {
+            return Ok((sprite.clone(), next, LoadState::Loaded));
+        }
+
+        let data = match image.frame_data(*current_frame) {
+            Some(data) => data,
+            None => {
+                // Show a placeholder, keyed by the image hash, which
+                // doesn't collide with the frame keys, until the frame
+                // has been decoded.  Restart the frame so that it gets
+                // its full duration once it is shown.
+                *frame_start = now;
+                let sprite = match frame_cache.get(&image.hash()) {
+                    Some(sprite) => sprite.clone(),
+                    None => {
+                        let sprite = atlas
+                            .allocate_with_padding(&Image::new(1, 1), padding, scale_down)
+                            .context("atlas.allocate_with_padding")?;
+                        frame_cache.insert(image.hash(), image.hash(), sprite.clone());
+                        sprite
+                    }
+                };
+                return Ok((sprite, Some(now + min_frame_duration), LoadState::Loading));
+            }
+        };
+
+        let frame = Image::from_raw(width as usize, height as usize, data.to_vec());
+        let sprite = atlas
+            .allocate_with_padding(&frame, padding, scale_down)
+            .context("atlas.allocate_with_padding")?;
+        frame_cache.insert(image.hash(), key, sprite.clone());
+
+        Ok((sprite, next, LoadState::Loaded))
+    }
+
+    /// Moves the state that tracks images from `old`, which is being
+    /// replaced by self because its atlas is full.
+    pub fn take_image_state(&mut self, old: &mut GlyphCache) {
+        // Steal the decoded image cache; without this, any animating gifs
+        // would reset back to frame 0 each time we filled the texture
+        std::mem::swap(&mut self.image_cache, &mut old.image_cache);
+        // The images that it holds remain tracked, so that they are
+        // still released once they are dropped
+        std::mem::swap(&mut self.image_ids, &mut old.image_ids);
+        std::mem::swap(&mut self.image_refs, &mut old.image_refs);
+        std::mem::swap(&mut self.dropped_images, &mut old.dropped_images);
+        std::mem::swap(&mut self.scaled_images, &mut old.scaled_images);
+        self.frame = old.frame;
+    }
+
+    /// Called at the start of each paint, so that dropped images, and
+    /// resampled copies of images that are no longer needed, can be
+    /// discarded even if no images are being drawn
+    pub fn advance_frame(&mut self) {
+        self.frame += 1;
+        self.release_dropped_images();
+        let idle = self.frame.saturating_sub(SCALED_IMAGE_IDLE_FRAMES);
+        self.scaled_images.retain(|_, image| match image.upgrade() {
+            Some(image) => image.evict_scaled(idle),
+            None => false,
+        });
+    }
+
+    /// Resolves the sprite for a still image that must be scaled down by
+    /// `factor` to fit in the atlas.  The downscaled copy is retained by
+    /// the ImageData rather than by the atlas, so that it doesn't need to
+    /// be resampled again if the atlas is recreated shortly afterwards;
+    /// advance_frame discards it once it hasn't been uploaded for
+    /// SCALED_IMAGE_IDLE_FRAMES.
+    /// Returns None for the other kinds of image.
+    fn cached_scaled_image(
+        &mut self,
+        image: &Arc<ImageData>,
+        padding: Option<usize>,
+        factor: usize,
+    ) -> anyhow::Result<Option<(Sprite, Option<Instant>, LoadState)>> {
+        let (width, height, hash) = match &*image.data() {
+            ImageDataType::Rgba8 {
+                width,
+                height,
+                hash,
+                ..
+            } => (*width, *height, *hash),
+            _ => return Ok(None),
+        };
+        let factor = factor as u32;
+        let (width, height) = ((width / factor).max(1), (height / factor).max(1));
+
+        // Keyed by the image and the scaled size, so that the sprite
+        // doesn't collide with that of the image at its full size
+        let mut key = hash.to_vec();
+        key.extend_from_slice(&width.to_le_bytes());
+        key.extend_from_slice(&height.to_le_bytes());
+        let key = ImageDataType::hash_bytes(&key);
+        if let Some(sprite) = self.frame_cache.get(&key) {
+            return Ok(Some((sprite.clone(), None, LoadState::Loaded)));
+        }
+
+        let scaled = match image.scaled(width, height, self.frame) {
+            Some(scaled) => scaled,
+            None => return Ok(None),
+        };
+        self.scaled_images.insert(image.id(), Arc::downgrade(image));
+        // The atlas holds straight alpha
+        let scaled = match (*scaled).clone().to_straight_alpha() {
+            ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => Image::from_raw(width as usize, height as usize, data),
+            _ => return Ok(None),
+        };
+        let sprite = self
+            .atlas
+            .allocate_with_padding(&scaled, padding, None)
+            .context("atlas.allocate_with_padding")?;
+        self.frame_cache.insert(hash, key, sprite.clone());
+
+        Ok(Some((sprite, None, LoadState::Loaded)))
+    }
+
+    /// Resolve the sprite for the current frame of `image_data`.
+    /// When `animations_paused` is true, an animated image holds its
+    /// current frame and no next frame time is returned for it.
     pub fn cached_image(
         &mut self,
         image_data: &Arc<ImageData>,
         padding: Option<usize>,
         allow_image: AllowImage,
+        animations_paused: bool,
     ) -> anyhow::Result<(Sprite, Option<Instant>, LoadState)> {
+        self.release_dropped_images();
+
+        let options = ImageRenderOptions {
+            padding,
+            min_frame_duration: self.min_frame_duration,
+            allow_image,
+            animations_paused,
+        };
         let hash = image_data.hash();
+        if self.image_ids.insert(image_data.id(), hash).is_none() {
+            *self.image_refs.entry(hash).or_insert(0) += 1;
+            self.dropped_images.track(image_data.id());
+        }
+
+        if let AllowImage::Scale(factor) = allow_image {
+            if let Some(res) = self.cached_scaled_image(image_data, padding, factor)? {
+                return Ok(res);
+            }
+        }
 
         if let Some(decoded) = self.image_cache.get(&hash) {
+            // The instance that populated the cache may have been
+            // dropped while another with the same content lives on
+            let image = decoded
+                .image
+                .upgrade()
+                .unwrap_or_else(|| Arc::clone(image_data));
             Self::cached_image_impl(
                 &mut self.frame_cache,
                 &mut self.atlas,
                 decoded,
-                padding,
-                self.min_frame_duration,
-                allow_image,
+                &image,
+                options,
             )
         } else {
             let decoded = DecodedImage::load(image_data);
+            let image = decoded
+                .image
+                .upgrade()
+                .unwrap_or_else(|| Arc::clone(image_data));
             let res = Self::cached_image_impl(
                 &mut self.frame_cache,
                 &mut self.atlas,
                 &decoded,
-                padding,
-                self.min_frame_duration,
-                allow_image,
+                &image,
+                options,
             )?;
             self.image_cache.put(hash, decoded);
             Ok(res)
         }
     }
 
+    /// Releases the decoded data and the sprites of the images whose
+    /// last ImageData instance has been dropped since the last call
+    fn release_dropped_images(&mut self) {
+        for id in self.dropped_images.take() {
+            let hash = match self.image_ids.remove(&id) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let refs = self.image_refs.entry(hash).or_insert(1);
+            *refs -= 1;
+            if *refs == 0 {
+                self.image_refs.remove(&hash);
+                self.image_cache.remove(&hash);
+                self.frame_cache.remove_image(&hash);
+            }
+        }
+    }
+
     pub fn cached_color(&mut self, color: RgbColor, alpha: f32) -> anyhow::Result<Sprite> {
         let key = (color, NotNan::new(alpha).unwrap());
 
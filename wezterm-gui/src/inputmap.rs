@@ -434,10 +434,22 @@ impl InputMap {
         None
     }
 
+    /// Returns the configured leader timeout, if a leader is defined
+    pub fn leader_timeout(&self) -> Option<std::time::Duration> {
+        self.leader.as_ref().map(|(_, _, timeout)| *timeout)
+    }
+
     pub fn has_table(&self, name: &str) -> bool {
         self.keys.by_name.contains_key(name)
     }
 
+    /// Returns the names of all of the key tables, in sorted order
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.keys.by_name.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
     pub fn lookup_key(
         &self,
         key: &KeyCode,
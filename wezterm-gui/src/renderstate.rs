@@ -764,12 +764,7 @@ impl RenderState {
 
         let mut glyph_cache = self.glyph_cache.borrow_mut();
 
-        // Steal the decoded image cache; without this, any animating gifs
-        // would reset back to frame 0 each time we filled the texture
-        std::mem::swap(
-            &mut glyph_cache.image_cache,
-            &mut new_glyph_cache.image_cache,
-        );
+        new_glyph_cache.take_image_state(&mut glyph_cache);
 
         *glyph_cache = new_glyph_cache;
         Ok(())
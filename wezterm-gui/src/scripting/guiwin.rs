@@ -1,18 +1,35 @@
 //! GuiWin represents a Gui TermWindow (as opposed to a Mux window) in lua code
 use super::luaerr;
-use crate::termwindow::TermWindowNotif;
+use crate::selection::{SelectionCoordinate, SelectionMode};
+use crate::termwindow::{PaneScrollTarget, TermWindowNotif};
 use crate::TermWindow;
-use config::keyassignment::{ClipboardCopyDestination, KeyAssignment};
+use config::keyassignment::{
+    ClipboardCopyDestination, ClipboardPasteSource, CopyModeAssignment, KeyAssignment,
+    SpawnTabDomain,
+};
+use config::window::WindowLevel;
+use config::Dimension;
 use luahelper::*;
-use mlua::{UserData, UserDataMethods, UserDataRef};
-use mux::pane::PaneId;
+use mlua::{FromLua, UserData, UserDataMethods, UserDataRef};
+use mux::domain::{DomainState, SplitSource};
+use mux::pane::{CachePolicy, PaneId, Pattern, SearchResult};
+use mux::renderable::RenderableDimensions;
+use mux::ssh::RemoteSshDomain;
+use mux::tab::{SplitDirection, SplitRequest, SplitSize, TabId};
 use mux::window::WindowId as MuxWindowId;
 use mux::Mux;
-use mux_lua::MuxPane;
+use mux_lua::{MuxPane, MuxTab, SplitPane};
+use std::convert::TryFrom;
+use termwiz::cell::SemanticType;
 use termwiz_funcs::lines_to_escapes;
+use wezterm_client::domain::{ClientDomain, ClientDomainConfig};
 use wezterm_dynamic::{FromDynamic, ToDynamic};
+use wezterm_term::{Line, StableRowIndex};
 use wezterm_toast_notification::ToastNotification;
-use window::{Connection, ConnectionOps, DeadKeyStatus, WindowOps, WindowState};
+use window::{
+    Connection, ConnectionOps, DeadKeyStatus, MouseCursor, WindowDecorations, WindowOps,
+    WindowState,
+};
 
 #[derive(Clone)]
 pub struct GuiWin {
@@ -29,6 +46,62 @@ impl GuiWin {
             mux_window_id,
         }
     }
+
+    fn scroll_pane(&self, pane_id: PaneId, target: PaneScrollTarget) -> mlua::Result<()> {
+        let mux = Mux::try_get()
+            .ok_or_else(|| anyhow::anyhow!("no mux?"))
+            .map_err(luaerr)?;
+        if mux.get_pane(pane_id).is_none() {
+            return Err(mlua::Error::external(format!("invalid pane {pane_id}")));
+        }
+        self.window
+            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                if let Err(err) = term_window.scroll_pane(pane_id, target) {
+                    log::error!("scroll_pane: {err:#}");
+                }
+            })));
+        Ok(())
+    }
+
+    /// Runs `f` against the TermWindow on the gui thread and
+    /// returns its result
+    async fn with_term_window<F, T>(&self, f: F) -> mlua::Result<T>
+    where
+        F: FnOnce(&mut TermWindow) -> T + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = smol::channel::bounded(1);
+        self.window
+            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                tx.try_send(f(term_window)).ok();
+            })));
+        rx.recv()
+            .await
+            .map_err(|e| anyhow::anyhow!("{:#}", e))
+            .map_err(luaerr)
+    }
+
+    /// Resolves `f` against the mux window to find the index of the
+    /// tab to activate, then activates it via the TermWindow so that
+    /// the usual focus and redraw logic is applied
+    async fn activate_tab_with<F>(&self, f: F) -> mlua::Result<()>
+    where
+        F: FnOnce(&mux::window::Window) -> anyhow::Result<usize>,
+    {
+        let tab_idx = {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            let window = mux.get_window(self.mux_window_id).ok_or_else(|| {
+                mlua::Error::external(format!("invalid window {}", self.mux_window_id))
+            })?;
+            f(&window).map_err(luaerr)?
+        };
+
+        self.with_term_window(move |term_window| term_window.activate_tab(tab_idx as isize))
+            .await?
+            .map_err(luaerr)
+    }
 }
 
 impl UserData for GuiWin {
@@ -50,8 +123,63 @@ impl UserData for GuiWin {
             let window = mux.get_window(this.mux_window_id).ok_or_else(|| {
                 mlua::Error::external(format!("invalid window {}", this.mux_window_id))
             })?;
-            Ok(window.get_active().map(|tab| mux_lua::MuxTab(tab.tab_id())))
+            Ok(window.get_active().map(|tab| MuxTab(tab.tab_id())))
         });
+        methods.add_method(
+            "set_tab_title",
+            |lua, this, (first, second): (mlua::Value, mlua::Value)| {
+                let mux =
+                    Mux::try_get().ok_or_else(|| mlua::Error::external("cannot get Mux!?"))?;
+                let (tab_id, title) = match first {
+                    mlua::Value::UserData(ud) => {
+                        let tab = ud.borrow::<MuxTab>()?;
+                        (tab.0, Option::<String>::from_lua(second, lua)?)
+                    }
+                    title => {
+                        let window = mux.get_window(this.mux_window_id).ok_or_else(|| {
+                            mlua::Error::external(format!("invalid window {}", this.mux_window_id))
+                        })?;
+                        let tab = window.get_active().ok_or_else(|| {
+                            mlua::Error::external(format!(
+                                "window {} has no active tab",
+                                this.mux_window_id
+                            ))
+                        })?;
+                        (tab.tab_id(), Option::<String>::from_lua(title, lua)?)
+                    }
+                };
+                let tab = mux
+                    .get_tab(tab_id)
+                    .ok_or_else(|| mlua::Error::external(format!("tab {tab_id} not found")))?;
+                // An empty title causes the tab bar to fall back to
+                // the computed title
+                tab.set_title(title.as_deref().unwrap_or(""));
+                Ok(())
+            },
+        );
+        methods.add_async_method(
+            "split_pane",
+            |_, this, args: Option<SplitPane>| async move {
+                let pane = {
+                    let mux =
+                        Mux::try_get().ok_or_else(|| mlua::Error::external("cannot get Mux!?"))?;
+                    let window = mux.get_window(this.mux_window_id).ok_or_else(|| {
+                        mlua::Error::external(format!("invalid window {}", this.mux_window_id))
+                    })?;
+                    let tab = window.get_active().ok_or_else(|| {
+                        mlua::Error::external(format!(
+                            "window {} has no active tab",
+                            this.mux_window_id
+                        ))
+                    })?;
+                    let pane = tab.get_active_pane().ok_or_else(|| {
+                        mlua::Error::external(format!("tab {} has no active pane", tab.tab_id()))
+                    })?;
+                    MuxPane(pane.pane_id())
+                };
+                args.unwrap_or_default().run(&pane).await
+            },
+        );
 
         methods.add_method(
             "set_inner_size",
@@ -77,6 +205,49 @@ impl UserData for GuiWin {
             this.window.toggle_fullscreen();
             Ok(())
         });
+        methods.add_method("set_always_on_top", |_, this, on_top: bool| {
+            this.window.set_window_level(if on_top {
+                WindowLevel::AlwaysOnTop
+            } else {
+                WindowLevel::Normal
+            });
+            Ok(())
+        });
+        methods.add_async_method("get_always_on_top", |_, this, _: ()| async move {
+            this.with_term_window(move |term_window| {
+                term_window
+                    .window_state
+                    .contains(WindowState::ALWAYS_ON_TOP)
+            })
+            .await
+        });
+        methods.add_async_method("set_app_id", |_, this, app_id: String| async move {
+            this.with_term_window(move |term_window| {
+                let can_change = term_window
+                    .window
+                    .as_ref()
+                    .map(|window| window.can_change_app_id())
+                    .unwrap_or(false);
+                if can_change {
+                    if let Some(window) = term_window.window.as_ref() {
+                        window.set_app_id(&app_id);
+                    }
+                    term_window.app_id = app_id;
+                } else {
+                    log::warn!(
+                        "set_app_id({app_id:?}): the app id of an existing window \
+                         cannot be changed on this system; it remains {:?}",
+                        term_window.app_id
+                    );
+                }
+                term_window.app_id.clone()
+            })
+            .await
+        });
+        methods.add_async_method("get_app_id", |_, this, _: ()| async move {
+            this.with_term_window(move |term_window| term_window.app_id.clone())
+                .await
+        });
         methods.add_method("focus", |_, this, _: ()| {
             this.window.focus();
             Ok(())
@@ -96,6 +267,10 @@ impl UserData for GuiWin {
         methods.add_method("get_appearance", |_, _, _: ()| {
             Ok(Connection::get().unwrap().get_appearance().to_string())
         });
+        methods.add_method("invalidate", |_, this, _: ()| {
+            this.window.notify(TermWindowNotif::Invalidate);
+            Ok(())
+        });
         methods.add_method("set_right_status", |_, this, status: String| {
             this.window.notify(TermWindowNotif::SetRightStatus(status));
             Ok(())
@@ -107,7 +282,7 @@ impl UserData for GuiWin {
         methods.add_async_method("get_dimensions", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetDimensions(tx));
-            let (dims, window_state) = rx
+            let (dims, window_state, terminal_size, cell_size, chrome) = rx
                 .recv()
                 .await
                 .map_err(|e| anyhow::anyhow!("{:#}", e))
@@ -119,6 +294,16 @@ impl UserData for GuiWin {
                 pixel_height: usize,
                 dpi: usize,
                 is_full_screen: bool,
+                cols: usize,
+                rows: usize,
+                cell_pixel_width: usize,
+                cell_pixel_height: usize,
+                tab_bar_height: usize,
+                tab_bar_at_bottom: bool,
+                padding_left: usize,
+                padding_right: usize,
+                padding_top: usize,
+                padding_bottom: usize,
             }
             impl_lua_conversion_dynamic!(Dims);
 
@@ -127,6 +312,16 @@ impl UserData for GuiWin {
                 pixel_height: dims.pixel_height,
                 dpi: dims.dpi,
                 is_full_screen: window_state.contains(WindowState::FULL_SCREEN),
+                cols: terminal_size.cols,
+                rows: terminal_size.rows,
+                cell_pixel_width: cell_size.width as usize,
+                cell_pixel_height: cell_size.height as usize,
+                tab_bar_height: chrome.tab_bar_height,
+                tab_bar_at_bottom: chrome.tab_bar_at_bottom,
+                padding_left: chrome.padding_left,
+                padding_right: chrome.padding_right,
+                padding_top: chrome.padding_top,
+                padding_bottom: chrome.padding_bottom,
                 // FIXME: expose other states here
             };
             Ok(dims)
@@ -148,13 +343,51 @@ impl UserData for GuiWin {
                 Ok(text)
             },
         );
+        #[derive(FromDynamic, ToDynamic)]
+        struct SelectionPoint {
+            x: usize,
+            y: StableRowIndex,
+        }
+
+        #[derive(FromDynamic, ToDynamic)]
+        struct PaneSelection {
+            start: SelectionPoint,
+            end: SelectionPoint,
+            #[dynamic(default = "default_selection_mode")]
+            mode: SelectionMode,
+        }
+        impl_lua_conversion_dynamic!(PaneSelection);
+
+        fn default_selection_mode() -> SelectionMode {
+            SelectionMode::Cell
+        }
+
+        methods.add_async_method(
+            "set_selection_for_pane",
+            |_, this, (pane, selection): (UserDataRef<MuxPane>, Option<PaneSelection>)| async move {
+                let pane_id = pane.0;
+                this.with_term_window(move |term_window| -> anyhow::Result<()> {
+                    let pane = Mux::get()
+                        .get_pane(pane_id)
+                        .ok_or_else(|| anyhow::anyhow!("invalid pane {pane_id}"))?;
+                    let point = |p: SelectionPoint| SelectionCoordinate::x_y(p.x, p.y);
+                    let (range, mode) = match selection {
+                        Some(PaneSelection { start, end, mode }) => {
+                            (Some((point(start), point(end))), mode)
+                        }
+                        None => (None, SelectionMode::Cell),
+                    };
+                    term_window.set_selection_for_pane(&pane, range, mode);
+                    Ok(())
+                })
+                .await?
+                .map_err(luaerr)
+            },
+        );
         methods.add_async_method("current_event", |lua, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
-            this.window
-                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(term_window.current_event.to_dynamic()).ok();
-                })));
-            let result = rx.recv().await.map_err(mlua::Error::external)?;
+            let result = this
+                .with_term_window(move |term_window| term_window.current_event.to_dynamic())
+                .await?;
             luahelper::dynamic_to_lua_value(lua, result)
         });
         methods.add_async_method(
@@ -170,6 +403,53 @@ impl UserData for GuiWin {
                 result.map_err(mlua::Error::external)
             },
         );
+        methods.add_async_method(
+            "perform_actions",
+            |_, this, list: Vec<mlua::Table>| async move {
+                // Resolve all of the arguments up front, so that a malformed
+                // entry doesn't cause a partially applied batch
+                let actions = list
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, entry)| {
+                        let resolve = || -> mlua::Result<(KeyAssignment, PaneId)> {
+                            let assignment: KeyAssignment = entry.get(1)?;
+                            let pane: UserDataRef<MuxPane> = entry.get(2)?;
+                            Ok((assignment, pane.0))
+                        };
+                        resolve().map_err(|err| {
+                            mlua::Error::external(format!("invalid action {}: {err}", idx + 1))
+                        })
+                    })
+                    .collect::<mlua::Result<Vec<_>>>()?;
+
+                this.with_term_window(move |term_window| {
+                    let mut result = Ok(());
+                    for (idx, (assignment, pane_id)) in actions.iter().enumerate() {
+                        if let Err(err) =
+                            term_window.perform_assignment_for_pane(*pane_id, assignment)
+                        {
+                            result = Err(anyhow::anyhow!("action {} failed: {err:#}", idx + 1));
+                            break;
+                        }
+                    }
+                    if let Some(window) = term_window.window.as_ref() {
+                        window.invalidate();
+                    }
+                    result
+                })
+                .await?
+                .map_err(luaerr)
+            },
+        );
+        methods.add_async_method(
+            "emit",
+            |lua, this, (name, args): (String, mlua::MultiValue)| async move {
+                let mut args = args.into_vec();
+                args.insert(0, mlua::Value::UserData(lua.create_userdata(this.clone())?));
+                config::lua::emit_event(lua, (name, mlua::MultiValue::from_vec(args))).await
+            },
+        );
         methods.add_async_method("effective_config", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetEffectiveConfig(tx));
@@ -181,6 +461,105 @@ impl UserData for GuiWin {
 
             Ok((*config).clone())
         });
+        methods.add_async_method(
+            "pane_dimensions",
+            |_, this, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                let dims = this
+                    .with_term_window(move |_term_window| {
+                        Mux::get()
+                            .get_pane(pane_id)
+                            .map(|pane| pane.get_dimensions())
+                    })
+                    .await?;
+
+                #[derive(FromDynamic, ToDynamic)]
+                struct PaneDims {
+                    cols: usize,
+                    rows: usize,
+                    pixel_width: usize,
+                    pixel_height: usize,
+                    dpi: u32,
+                }
+                impl_lua_conversion_dynamic!(PaneDims);
+
+                Ok(dims.map(|dims| PaneDims {
+                    cols: dims.cols,
+                    rows: dims.viewport_rows,
+                    pixel_width: dims.pixel_width,
+                    pixel_height: dims.pixel_height,
+                    dpi: dims.dpi,
+                }))
+            },
+        );
+        methods.add_async_method(
+            "search_pane",
+            |_, _this, args: (UserDataRef<MuxPane>, String, Option<SearchPaneOpts>)| async move {
+                let (pane, pattern, opts) = args;
+                let opts = opts.unwrap_or_default();
+                let pane_id = pane.0;
+                let pane = Mux::get()
+                    .get_pane(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {} not found", pane_id)))?;
+                let dims = pane.get_dimensions();
+                let range =
+                    dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex;
+
+                let pattern = match (opts.regex, opts.case_sensitive) {
+                    (true, true) => Pattern::Regex(pattern),
+                    (true, false) => Pattern::Regex(format!("(?i){}", pattern)),
+                    (false, true) => Pattern::CaseSensitiveString(pattern),
+                    (false, false) => Pattern::CaseInSensitiveString(pattern),
+                };
+                let results = pane
+                    .search(pattern, range, Some(opts.max_matches))
+                    .await
+                    .map_err(luaerr)?;
+
+                #[derive(FromDynamic, ToDynamic)]
+                struct SearchCoord {
+                    x: usize,
+                    y: StableRowIndex,
+                }
+
+                #[derive(FromDynamic, ToDynamic)]
+                struct SearchMatch {
+                    start: SearchCoord,
+                    end: SearchCoord,
+                    text: String,
+                }
+                impl_lua_conversion_dynamic!(SearchMatch);
+
+                // Fetch the rows spanned by all of the matches at once,
+                // rather than separately for each match
+                let rows = match (
+                    results.iter().map(|res| res.start_y).min(),
+                    results.iter().map(|res| res.end_y).max(),
+                ) {
+                    (Some(start), Some(end)) => start..end + 1,
+                    _ => return Ok(vec![]),
+                };
+                let (first_row, lines) = pane.get_lines(rows);
+
+                Ok(results
+                    .iter()
+                    .map(|res| SearchMatch {
+                        start: SearchCoord {
+                            x: res.start_x,
+                            y: res.start_y,
+                        },
+                        // SearchResult::end_x is exclusive, but we report
+                        // the last cell of the match, in the same way as
+                        // pane:get_text_from_region expects
+                        end: SearchCoord {
+                            x: res.end_x.saturating_sub(1),
+                            y: res.end_y,
+                        },
+                        text: search_result_text(first_row, &lines, res),
+                    })
+                    .collect::<Vec<_>>())
+            },
+        );
         methods.add_async_method("get_config_overrides", |lua, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetConfigOverrides(tx));
@@ -198,106 +577,1057 @@ impl UserData for GuiWin {
                 .notify(TermWindowNotif::SetConfigOverrides(value));
             Ok(())
         });
-        methods.add_async_method("is_focused", |_, this, _: ()| async move {
+        methods.add_async_method("get_effective_color_scheme", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
-            this.window
-                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(term_window.focused.is_some()).ok();
-                })));
-            let result = rx
+            this.window.notify(TermWindowNotif::GetEffectiveConfig(tx));
+            let config = rx
                 .recv()
                 .await
                 .map_err(|e| anyhow::anyhow!("{:#}", e))
                 .map_err(luaerr)?;
 
+            Ok(config.color_scheme.clone())
+        });
+        methods.add_async_method("set_color_scheme", |_, this, name: String| async move {
+            this.with_term_window(move |term_window| term_window.set_color_scheme(&name))
+                .await?
+                .map_err(luaerr)
+        });
+        methods.add_async_method("is_focused", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.focused.is_some())
+                .await?;
+
             Ok(result)
         });
         methods.add_async_method("leader_is_active", |_, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
+            let result = this
+                .with_term_window(move |term_window| term_window.leader_is_active())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_method("activate_leader", |_, this, timeout: Option<u64>| {
             this.window
                 .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(term_window.leader_is_active()).ok();
+                    let duration = match timeout {
+                        Some(timeout) => Some(std::time::Duration::from_millis(timeout)),
+                        None => term_window.leader_activation_timeout(term_window.leader_timeout()),
+                    };
+                    term_window.activate_leader(duration);
                 })));
-            let result = rx
-                .recv()
-                .await
-                .map_err(|e| anyhow::anyhow!("{:#}", e))
-                .map_err(luaerr)?;
+            Ok(())
+        });
+        methods.add_async_method("get_leader_timeout", |_, this, _: ()| async move {
+            let timeout = this
+                .with_term_window(move |term_window| term_window.leader_timeout())
+                .await?;
 
-            Ok(result)
+            Ok(timeout.as_millis() as u64)
         });
-        methods.add_async_method("composition_status", |_, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
+        methods.add_method("set_leader_timeout", |_, this, timeout: i64| {
+            let timeout = u64::try_from(timeout).map_err(|_| {
+                mlua::Error::external(format!(
+                    "leader timeout must not be negative, but got {}",
+                    timeout
+                ))
+            })?;
             this.window
                 .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(match term_window.composition_status() {
-                        DeadKeyStatus::None => None,
-                        DeadKeyStatus::Composing(s) => Some(s.clone()),
-                    })
-                    .ok();
+                    term_window.set_leader_timeout(std::time::Duration::from_millis(timeout));
                 })));
-            let result = rx
-                .recv()
-                .await
-                .map_err(|e| anyhow::anyhow!("{:#}", e))
-                .map_err(luaerr)?;
-
-            Ok(result)
+            Ok(())
         });
-        methods.add_async_method("active_key_table", |_, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
+        methods.add_method("set_key_trace", |_, this, enable: bool| {
             this.window
                 .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(term_window.current_key_table_name()).ok();
+                    term_window.set_key_trace(enable);
                 })));
-            let result = rx
-                .recv()
-                .await
-                .map_err(|e| anyhow::anyhow!("{:#}", e))
-                .map_err(luaerr)?;
+            Ok(())
+        });
+        methods.add_async_method("is_key_trace_enabled", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.is_key_trace_enabled())
+                .await?;
 
             Ok(result)
         });
-        methods.add_async_method("keyboard_modifiers", |_, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
+        methods.add_async_method("composition_status", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| match term_window.composition_status() {
+                    DeadKeyStatus::None => None,
+                    DeadKeyStatus::Composing(s) => Some(s.clone()),
+                })
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_method("clear_composition", |_, this, _: ()| {
             this.window
                 .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(term_window.current_modifier_and_led_state())
-                        .ok();
+                    term_window.clear_composition();
                 })));
-            let (mods, leds) = rx
-                .recv()
+            Ok(())
+        });
+        methods.add_async_method("active_key_table", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.current_key_table_name())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_async_method("set_font", |_, this, family: String| async move {
+            let family = super::resolve_font_family(family).await.map_err(luaerr)?;
+            this.with_term_window(move |term_window| {
+                term_window.set_font_family(&family);
+            })
+            .await
+        });
+        methods.add_async_method(
+            "set_animations_paused",
+            |_, this, paused: bool| async move {
+                this.with_term_window(move |term_window| {
+                    term_window.set_animations_paused(paused);
+                })
                 .await
-                .map_err(|e| anyhow::anyhow!("{:#}", e))
-                .map_err(luaerr)?;
+            },
+        );
+        methods.add_async_method(
+            "activate_copy_mode",
+            |_, this, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                this.with_term_window(move |term_window| {
+                    term_window.activate_copy_mode_for_pane(pane_id)
+                })
+                .await?
+                .map_err(luaerr)
+            },
+        );
+        methods.add_async_method(
+            "copy_mode_move",
+            |_, this, (pane, motion): (UserDataRef<MuxPane>, String)| async move {
+                let assignment = copy_mode_motion(&motion).ok_or_else(|| {
+                    mlua::Error::external(format!("invalid copy mode motion {}", motion))
+                })?;
+                let pane_id = pane.0;
+                this.with_term_window(move |term_window| {
+                    term_window.perform_copy_mode_assignment(pane_id, assignment)
+                })
+                .await?
+                .map_err(luaerr)
+            },
+        );
+        methods.add_async_method(
+            "copy_mode_selection",
+            |_, this, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                this.with_term_window(move |term_window| {
+                    if term_window.is_in_copy_mode(pane_id) {
+                        Mux::get()
+                            .get_pane(pane_id)
+                            .map(|pane| term_window.selection_text(&pane))
+                    } else {
+                        None
+                    }
+                })
+                .await
+            },
+        );
+        methods.add_async_method(
+            "close_copy_mode",
+            |_, this, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                this.with_term_window(move |term_window| term_window.close_copy_mode(pane_id))
+                    .await
+            },
+        );
+        methods.add_async_method(
+            "set_window_padding",
+            |_, this, padding: WindowPaddingOpts| async move {
+                this.with_term_window(move |term_window| {
+                    term_window.set_window_padding(
+                        padding.left,
+                        padding.right,
+                        padding.top,
+                        padding.bottom,
+                    );
+                })
+                .await
+            },
+        );
+        methods.add_async_method("get_window_padding", |_, this, _: ()| async move {
+            let chrome = this
+                .with_term_window(move |term_window| term_window.chrome_dimensions())
+                .await?;
 
-            Ok((mods.to_string(), leds.to_string()))
+            #[derive(FromDynamic, ToDynamic)]
+            struct Padding {
+                left: usize,
+                right: usize,
+                top: usize,
+                bottom: usize,
+            }
+            impl_lua_conversion_dynamic!(Padding);
+
+            Ok(Padding {
+                left: chrome.padding_left,
+                right: chrome.padding_right,
+                top: chrome.padding_top,
+                bottom: chrome.padding_bottom,
+            })
         });
-        methods.add_async_method("active_pane", |_, this, _: ()| async move {
-            let (tx, rx) = smol::channel::bounded(1);
-            this.window
-                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                    tx.try_send(
+        methods.add_async_method("get_decorations", |_, this, _: ()| async move {
+            let decorations = this
+                .with_term_window(move |term_window| term_window.window_decorations())
+                .await?;
+            let decorations: String = (&decorations).into();
+            Ok(decorations)
+        });
+        methods.add_async_method("set_decorations", |_, this, mode: String| async move {
+            let decorations = WindowDecorations::try_from(mode).map_err(mlua::Error::external)?;
+            let decorations = this
+                .with_term_window(move |term_window| {
+                    term_window.set_window_decorations(decorations)
+                })
+                .await?;
+            let decorations: String = (&decorations).into();
+            Ok(decorations)
+        });
+        methods.add_async_method("get_title", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.get_window_title().to_string())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_async_method("get_user_var", |_, this, name: String| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.user_vars().get(&name).cloned())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_async_method(
+            "set_user_var",
+            |_, this, (name, value): (String, String)| async move {
+                this.with_term_window(move |term_window| {
+                    term_window.user_vars().insert(name, value);
+                })
+                .await
+            },
+        );
+        methods.add_async_method(
+            "get_or_assign_user_var",
+            |_, this, (name, default): (String, String)| async move {
+                let result = this
+                    .with_term_window(move |term_window| {
                         term_window
+                            .user_vars()
+                            .entry(name)
+                            .or_insert(default)
+                            .clone()
+                    })
+                    .await?;
+
+                Ok(result)
+            },
+        );
+        methods.add_async_method("key_table_names", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.key_table_names())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_async_method(
+            "activate_key_table",
+            |_, this, (name, opts): (String, Option<ActivateKeyTableOpts>)| async move {
+                let opts = opts.unwrap_or_default();
+
+                let (names, pane_id) = this
+                    .with_term_window(move |term_window| {
+                        let pane_id = term_window
                             .get_active_pane_or_overlay()
-                            .map(|pane| MuxPane(pane.pane_id())),
-                    )
-                    .ok();
-                })));
-            let result = rx
-                .recv()
+                            .map(|pane| pane.pane_id());
+                        (term_window.key_table_names(), pane_id)
+                    })
+                    .await?;
+
+                if !names.contains(&name) {
+                    return Err(anyhow::anyhow!(
+                        "no key_table named {name}; valid names are: {}",
+                        names.join(", ")
+                    ))
+                    .map_err(luaerr);
+                }
+                let pane_id = pane_id
+                    .ok_or_else(|| anyhow::anyhow!("window has no active pane"))
+                    .map_err(luaerr)?;
+
+                let (tx, rx) = smol::channel::bounded(1);
+                this.window.notify(TermWindowNotif::PerformAssignment {
+                    pane_id,
+                    assignment: KeyAssignment::ActivateKeyTable {
+                        name,
+                        timeout_milliseconds: opts.timeout_milliseconds,
+                        replace_current: opts.replace_current,
+                        one_shot: opts.one_shot,
+                        until_unknown: false,
+                        prevent_fallback: false,
+                    },
+                    tx: Some(tx),
+                });
+                let result = rx.recv().await.map_err(mlua::Error::external)?;
+                result.map_err(mlua::Error::external)
+            },
+        );
+        methods.add_async_method("keyboard_modifiers", |_, this, _: ()| async move {
+            let (mods, leds) = this
+                .with_term_window(move |term_window| term_window.current_modifier_and_led_state())
+                .await?;
+
+            Ok((mods.to_string(), leds.to_string()))
+        });
+        methods.add_async_method("active_pane", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| {
+                    term_window
+                        .get_active_pane_or_overlay()
+                        .map(|pane| MuxPane(pane.pane_id()))
+                })
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_method("pane_is_alt_screen", |_, _, pane: UserDataRef<MuxPane>| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            Ok(mux.get_pane(pane.0).map(|pane| pane.is_alt_screen_active()))
+        });
+        methods.add_method(
+            "scroll_pane",
+            |_, this, (pane, lines): (UserDataRef<MuxPane>, isize)| {
+                // Positive values scroll up into the scrollback
+                this.scroll_pane(pane.0, PaneScrollTarget::Lines(lines.saturating_neg()))
+            },
+        );
+        methods.add_method(
+            "scroll_to_prompt",
+            |_, this, (pane, delta): (UserDataRef<MuxPane>, isize)| {
+                this.scroll_pane(pane.0, PaneScrollTarget::Prompt(delta))
+            },
+        );
+        methods.add_method("pane_semantic_zones", |_, _, pane: UserDataRef<MuxPane>| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            let pane = mux
+                .get_pane(pane.0)
+                .ok_or_else(|| anyhow::anyhow!("invalid pane {}", pane.0))
+                .map_err(luaerr)?;
+
+            #[derive(FromDynamic, ToDynamic)]
+            struct ZoneCoord {
+                x: usize,
+                y: StableRowIndex,
+            }
+
+            #[derive(FromDynamic, ToDynamic)]
+            struct PaneSemanticZone {
+                kind: SemanticType,
+                start: ZoneCoord,
+                end: ZoneCoord,
+            }
+            impl_lua_conversion_dynamic!(PaneSemanticZone);
+
+            // Panes that never emitted OSC 133 have no zones
+            let zones = pane.get_semantic_zones().unwrap_or_else(|_| vec![]);
+            Ok(zones
+                .into_iter()
+                .map(|zone| PaneSemanticZone {
+                    kind: zone.semantic_type,
+                    start: ZoneCoord {
+                        x: zone.start_x,
+                        y: zone.start_y,
+                    },
+                    end: ZoneCoord {
+                        x: zone.end_x,
+                        y: zone.end_y,
+                    },
+                })
+                .collect::<Vec<_>>())
+        });
+        methods.add_method(
+            "pane_reported_pixel_size",
+            |_, _, pane: UserDataRef<MuxPane>| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| anyhow::anyhow!("invalid pane {}", pane.0))
+                    .map_err(luaerr)?;
+
+                #[derive(FromDynamic, ToDynamic)]
+                struct ReportedPixelSize {
+                    pixel_width: u16,
+                    pixel_height: u16,
+                }
+                impl_lua_conversion_dynamic!(ReportedPixelSize);
+
+                Ok(pane.get_pty_size().map(|size| ReportedPixelSize {
+                    pixel_width: size.pixel_width,
+                    pixel_height: size.pixel_height,
+                }))
+            },
+        );
+        methods.add_method(
+            "pane_last_exit_status",
+            |_, _, pane: UserDataRef<MuxPane>| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| anyhow::anyhow!("invalid pane {}", pane.0))
+                    .map_err(luaerr)?;
+                Ok(pane.get_last_command_status())
+            },
+        );
+        methods.add_method(
+            "scroll_pane_to",
+            |_, this, (pane, target): (UserDataRef<MuxPane>, mlua::Value)| {
+                let target = match target {
+                    mlua::Value::String(s) => match s.to_str()? {
+                        "top" => PaneScrollTarget::Top,
+                        "bottom" => PaneScrollTarget::Bottom,
+                        other => {
+                            return Err(mlua::Error::external(format!(
+                                "invalid scroll target {other:?}, \
+                                 expected \"top\", \"bottom\" or a row number"
+                            )))
+                        }
+                    },
+                    mlua::Value::Integer(row) => PaneScrollTarget::Row(row as _),
+                    other => {
+                        return Err(mlua::Error::external(format!(
+                            "invalid scroll target of type {}, \
+                             expected \"top\", \"bottom\" or a row number",
+                            other.type_name()
+                        )))
+                    }
+                };
+                this.scroll_pane(pane.0, target)
+            },
+        );
+        methods.add_method(
+            "set_pane_cwd",
+            |_, _, (pane, cwd): (UserDataRef<MuxPane>, String)| {
+                let url = if cwd.contains("://") {
+                    url::Url::parse(&cwd).map_err(|err| {
+                        mlua::Error::external(format!("invalid URI {cwd:?}: {err:#}"))
+                    })?
+                } else {
+                    let path = std::path::Path::new(&cwd);
+                    if !path.is_absolute() {
+                        return Err(mlua::Error::external(format!(
+                            "{cwd:?} is a relative path; the working directory \
+                             must be an absolute path"
+                        )));
+                    }
+                    url::Url::from_file_path(path).map_err(|()| {
+                        mlua::Error::external(format!("cannot represent {cwd:?} as a URI"))
+                    })?
+                };
+
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {}", pane.0)))?;
+                pane.set_current_working_dir(url).map_err(luaerr)
+            },
+        );
+        methods.add_method("pane_cwd", |_, _, pane: UserDataRef<MuxPane>| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            Ok(mux
+                .get_pane(pane.0)
+                .and_then(|pane| pane.get_current_working_dir(CachePolicy::FetchImmediate))
+                .map(|url| url.to_string()))
+        });
+        methods.add_async_method(
+            "break_pane_to_window",
+            |_, _, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                mux.resolve_pane_id(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+                let (tab, window_id) = mux
+                    .move_pane_to_new_tab(pane_id, None, None)
+                    .await
+                    .map_err(luaerr)?;
+
+                // Reconciling creates the gui window for the new mux window
+                let fe = crate::frontend::try_front_end()
+                    .ok_or_else(|| mlua::Error::external("not called on gui thread"))?;
+                let _ = fe.reconcile_workspace().await;
+                let window = fe.gui_window_for_mux_window(window_id).ok_or_else(|| {
+                    mlua::Error::external(format!(
+                        "mux window id {window_id} is not associated with a gui window"
+                    ))
+                })?;
+                Ok((window, MuxTab(tab.tab_id())))
+            },
+        );
+        #[derive(Debug, Default, FromDynamic, ToDynamic)]
+        enum MovePaneDirection {
+            Left,
+            #[default]
+            Right,
+            Top,
+            Bottom,
+        }
+
+        #[derive(Debug, Default, FromDynamic, ToDynamic)]
+        struct MovePanePlacement {
+            #[dynamic(default)]
+            direction: MovePaneDirection,
+            size: Option<f32>,
+        }
+        impl_lua_conversion_dynamic!(MovePanePlacement);
+
+        impl MovePanePlacement {
+            /// Interprets `size` the same way as `pane:split`: a value
+            /// below 1.0 is a fraction of the available space, and any
+            /// other value is a number of cells.
+            /// If `size` is not specified, the `moved` pane keeps its
+            /// current size, as far as the `target` pane has room for it.
+            fn split_request(
+                &self,
+                moved: &RenderableDimensions,
+                target: &RenderableDimensions,
+            ) -> SplitRequest {
+                let direction = match self.direction {
+                    MovePaneDirection::Left | MovePaneDirection::Right => {
+                        SplitDirection::Horizontal
+                    }
+                    MovePaneDirection::Top | MovePaneDirection::Bottom => SplitDirection::Vertical,
+                };
+
+                let size = match self.size {
+                    Some(size) if size == 0.0 => SplitSize::Percent(50),
+                    Some(size) if size < 1.0 => SplitSize::Percent((size * 100.).floor() as u8),
+                    Some(size) => SplitSize::Cells(size as usize),
+                    None => {
+                        let (wanted, available) = match direction {
+                            SplitDirection::Horizontal => (moved.cols, target.cols),
+                            SplitDirection::Vertical => (moved.viewport_rows, target.viewport_rows),
+                        };
+                        // Leave room for the split divider and at least one
+                        // cell of the target pane
+                        SplitSize::Cells(wanted.min(available.saturating_sub(2)).max(1))
+                    }
+                };
+
+                SplitRequest {
+                    direction,
+                    target_is_second: matches!(
+                        self.direction,
+                        MovePaneDirection::Right | MovePaneDirection::Bottom
+                    ),
+                    top_level: false,
+                    size,
+                }
+            }
+        }
+
+        methods.add_async_method(
+            "move_pane_to_tab",
+            |_,
+             this,
+             (pane, tab, placement): (
+                UserDataRef<MuxPane>,
+                Option<UserDataRef<MuxTab>>,
+                Option<MovePanePlacement>,
+            )| async move {
+                let pane_id = pane.0;
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let (_domain_id, _window_id, src_tab_id) = mux
+                    .resolve_pane_id(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+
+                let tab_id = match tab {
+                    Some(tab) => tab.0,
+                    None => {
+                        let (tab, _window_id) = mux
+                            .move_pane_to_new_tab(pane_id, Some(this.mux_window_id), None)
+                            .await
+                            .map_err(luaerr)?;
+                        return Ok(MuxTab(tab.tab_id()));
+                    }
+                };
+
+                if tab_id == src_tab_id {
+                    return Err(mlua::Error::external(format!(
+                        "pane {pane_id} is already in tab {tab_id}"
+                    )));
+                }
+                let target_pane = mux
+                    .get_tab(tab_id)
+                    .ok_or_else(|| mlua::Error::external(format!("tab {tab_id} not found")))?
+                    .get_active_pane()
+                    .ok_or_else(|| {
+                        mlua::Error::external(format!("tab {tab_id} has no active pane"))
+                    })?;
+                let moved_pane = mux
+                    .get_pane(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+
+                // The moved pane is split off from the active pane of the
+                // target tab; by default it is placed to the right.
+                // If it was the only pane in its original tab, that tab
+                // is removed as part of the move.
+                let request = placement
+                    .unwrap_or_default()
+                    .split_request(&moved_pane.get_dimensions(), &target_pane.get_dimensions());
+                mux.split_pane(
+                    target_pane.pane_id(),
+                    request,
+                    SplitSource::MovePane(pane_id),
+                    SpawnTabDomain::CurrentPaneDomain,
+                )
                 .await
-                .map_err(|e| anyhow::anyhow!("{:#}", e))
                 .map_err(luaerr)?;
 
-            Ok(result)
+                Ok(MuxTab(tab_id))
+            },
+        );
+        methods.add_async_method("activate_tab", |_, this, index: isize| async move {
+            this.activate_tab_with(|window| {
+                let num_tabs = window.len();
+                let tab_idx = if index < 0 {
+                    num_tabs.checked_sub(index.unsigned_abs())
+                } else {
+                    Some(index as usize)
+                };
+                match tab_idx {
+                    Some(tab_idx) if tab_idx < num_tabs => Ok(tab_idx),
+                    _ => anyhow::bail!(
+                        "tab index {index} is out of range for a window with {num_tabs} tabs"
+                    ),
+                }
+            })
+            .await
+        });
+        methods.add_method(
+            "move_tab",
+            |_, this, (tab, index): (UserDataRef<MuxTab>, isize)| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let mut window = mux.get_window_mut(this.mux_window_id).ok_or_else(|| {
+                    mlua::Error::external(format!("invalid window {}", this.mux_window_id))
+                })?;
+                let from_idx = window.idx_by_id(tab.0).ok_or_else(|| {
+                    mlua::Error::external(format!(
+                        "tab {} is not in window {}",
+                        tab.0, this.mux_window_id
+                    ))
+                })?;
+                // Negative indices count back from the end, and
+                // out of range indices are clamped to the ends
+                let num_tabs = window.len() as isize;
+                let to_idx = if index < 0 { num_tabs + index } else { index };
+                let to_idx = to_idx.clamp(0, num_tabs - 1) as usize;
+                // The mux notifies the gui to redraw the tab bar
+                window.move_tab(from_idx, to_idx).map_err(luaerr)
+            },
+        );
+        methods.add_async_method("activate_tab_by_id", |_, this, tab_id: TabId| async move {
+            this.activate_tab_with(|window| {
+                window
+                    .idx_by_id(tab_id)
+                    .ok_or_else(|| anyhow::anyhow!("tab {tab_id} is not in this window"))
+            })
+            .await
+        });
+        methods.add_async_method(
+            "close_pane",
+            |_, this, (pane, confirm): (UserDataRef<MuxPane>, Option<bool>)| async move {
+                let pane_id = pane.0;
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let (_domain_id, window_id, _tab_id) = mux
+                    .resolve_pane_id(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+                if window_id != this.mux_window_id {
+                    return Err(mlua::Error::external(format!(
+                        "pane {pane_id} is not in this window"
+                    )));
+                }
+                let pane = mux
+                    .get_pane(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {pane_id}")))?;
+
+                this.with_term_window(move |term_window| {
+                    term_window.close_pane(&pane, confirm.unwrap_or(true))
+                })
+                .await
+            },
+        );
+        methods.add_async_method(
+            "close_tab",
+            |_, this, (tab, confirm): (UserDataRef<MuxTab>, Option<bool>)| async move {
+                let tab_id = tab.0;
+                let tab_idx = {
+                    let mux = Mux::try_get()
+                        .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                        .map_err(luaerr)?;
+                    let window = mux.get_window(this.mux_window_id).ok_or_else(|| {
+                        mlua::Error::external(format!("invalid window {}", this.mux_window_id))
+                    })?;
+                    window.idx_by_id(tab_id).ok_or_else(|| {
+                        mlua::Error::external(format!("tab {tab_id} is not in this window"))
+                    })?
+                };
+
+                this.with_term_window(move |term_window| {
+                    term_window.close_specific_tab(tab_idx, confirm.unwrap_or(true))
+                })
+                .await
+            },
+        );
+        methods.add_method("toggle_pane_zoom", |_, this, _: ()| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            if let Some(tab) = mux.get_active_tab_for_window(this.mux_window_id) {
+                tab.toggle_zoom();
+            }
+            Ok(())
         });
+        methods.add_method(
+            "set_pane_zoom",
+            |_, _, (pane, zoomed): (UserDataRef<MuxPane>, bool)| {
+                let pane_id = pane.0;
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {pane_id}")))?;
+                let (_domain_id, _window_id, tab_id) = mux
+                    .resolve_pane_id(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+                let tab = mux
+                    .get_tab(tab_id)
+                    .ok_or_else(|| mlua::Error::external(format!("tab {tab_id} not found")))?;
+
+                let zoomed_pane_id = tab.get_zoomed_pane().map(|p| p.pane_id());
+                if zoomed {
+                    if zoomed_pane_id == Some(pane_id) {
+                        return Ok(());
+                    }
+                    // Only one pane can be zoomed at a time; un-zoom
+                    // whichever pane currently holds the zoom before
+                    // activating and zooming the requested pane
+                    if zoomed_pane_id.is_some() {
+                        tab.set_zoomed(false);
+                    }
+                    tab.set_active_pane(&pane);
+                    tab.set_zoomed(true);
+                } else if zoomed_pane_id == Some(pane_id) {
+                    tab.set_zoomed(false);
+                }
+                Ok(())
+            },
+        );
+        methods.add_async_method(
+            "is_pane_zoomed",
+            |_, _, pane: UserDataRef<MuxPane>| async move {
+                let pane_id = pane.0;
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let (_domain_id, _window_id, tab_id) = mux
+                    .resolve_pane_id(pane_id)
+                    .ok_or_else(|| mlua::Error::external(format!("pane {pane_id} not found")))?;
+                let tab = mux
+                    .get_tab(tab_id)
+                    .ok_or_else(|| mlua::Error::external(format!("tab {tab_id} not found")))?;
+                Ok(tab
+                    .get_zoomed_pane()
+                    .map(|p| p.pane_id() == pane_id)
+                    .unwrap_or(false))
+            },
+        );
+        methods.add_method(
+            "pane_foreground_process",
+            |_, _, pane: UserDataRef<MuxPane>| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {}", pane.0)))?;
+
+                #[derive(FromDynamic, ToDynamic)]
+                struct ForegroundProcess {
+                    name: String,
+                    pid: u32,
+                    argv: Vec<String>,
+                    cwd: String,
+                }
+                impl_lua_conversion_dynamic!(ForegroundProcess);
+
+                // This is the same cached information that is used to
+                // determine the process name shown in the tab title;
+                // it is only available for local panes.
+                Ok(pane
+                    .get_foreground_process_info(CachePolicy::AllowStale)
+                    .map(|info| ForegroundProcess {
+                        name: info.name,
+                        pid: info.pid,
+                        argv: info.argv,
+                        cwd: info.cwd.to_string_lossy().into_owned(),
+                    }))
+            },
+        );
+        methods.add_method(
+            "get_pane_scrollback_lines",
+            |_, _, pane: UserDataRef<MuxPane>| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {}", pane.0)))?;
+                Ok(pane.get_scrollback_size())
+            },
+        );
+        methods.add_method(
+            "set_pane_scrollback_lines",
+            |_, _, (pane, lines): (UserDataRef<MuxPane>, Option<usize>)| {
+                let mux = Mux::try_get()
+                    .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                    .map_err(luaerr)?;
+                let pane = mux
+                    .get_pane(pane.0)
+                    .ok_or_else(|| mlua::Error::external(format!("invalid pane {}", pane.0)))?;
+                pane.set_scrollback_size(lines).map_err(luaerr)
+            },
+        );
         methods.add_method("active_workspace", |_, _, _: ()| {
             let mux = Mux::try_get()
                 .ok_or_else(|| anyhow::anyhow!("no mux?"))
                 .map_err(luaerr)?;
             Ok(mux.active_workspace().to_string())
         });
+        methods.add_method("switch_to_previous_workspace", |_, _, _: ()| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            let front_end = crate::frontend::try_front_end()
+                .ok_or_else(|| mlua::Error::external("not called on gui thread"))?;
+            match mux.recent_workspaces().get(1) {
+                Some(workspace) => {
+                    front_end.switch_workspace(workspace);
+                    Ok(Some(workspace.to_string()))
+                }
+                None => Ok(None),
+            }
+        });
+        methods.add_method("pane_palette", |_, _, pane: UserDataRef<MuxPane>| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            let pane = match mux.get_pane(pane.0) {
+                Some(pane) => pane,
+                None => return Ok(None),
+            };
+
+            #[derive(FromDynamic, ToDynamic)]
+            struct PanePalette {
+                foreground: String,
+                background: String,
+                cursor_fg: String,
+                cursor_bg: String,
+                ansi: Vec<String>,
+            }
+            impl_lua_conversion_dynamic!(PanePalette);
+
+            // This is the live palette, which reflects any changes
+            // made by the application via OSC 4, 10, 11 and so on
+            let palette = pane.palette();
+            Ok(Some(PanePalette {
+                foreground: palette.foreground.to_rgb_string(),
+                background: palette.background.to_rgb_string(),
+                cursor_fg: palette.cursor_fg.to_rgb_string(),
+                cursor_bg: palette.cursor_bg.to_rgb_string(),
+                ansi: palette.colors.0[0..16]
+                    .iter()
+                    .map(|c| c.to_rgb_string())
+                    .collect(),
+            }))
+        });
+        methods.add_method("pane_domain", |_, _, pane: UserDataRef<MuxPane>| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+            let domain = match mux
+                .get_pane(pane.0)
+                .and_then(|pane| mux.get_domain(pane.domain_id()))
+            {
+                Some(domain) => domain,
+                None => return Ok(None),
+            };
+
+            #[derive(FromDynamic, ToDynamic)]
+            struct PaneDomain {
+                name: String,
+                kind: String,
+                state: String,
+            }
+            impl_lua_conversion_dynamic!(PaneDomain);
+
+            let (kind, connecting) = match domain.downcast_ref::<ClientDomain>() {
+                Some(client) => {
+                    let kind = match client.config() {
+                        ClientDomainConfig::Unix(_) => "unix",
+                        ClientDomainConfig::Tls(_) => "tls",
+                        ClientDomainConfig::Ssh(_) => "ssh",
+                    };
+                    (kind, client.is_connecting())
+                }
+                None if domain.downcast_ref::<RemoteSshDomain>().is_some() => ("ssh", false),
+                None => ("local", false),
+            };
+            let state = if connecting {
+                "connecting"
+            } else {
+                match domain.state() {
+                    DomainState::Attached => "connected",
+                    DomainState::Detached => "disconnected",
+                }
+            };
+
+            Ok(Some(PaneDomain {
+                name: domain.domain_name().to_string(),
+                kind: kind.to_string(),
+                state: state.to_string(),
+            }))
+        });
+        methods.add_method("workspace_stats", |_, _, _: ()| {
+            let mux = Mux::try_get()
+                .ok_or_else(|| anyhow::anyhow!("no mux?"))
+                .map_err(luaerr)?;
+
+            #[derive(FromDynamic, ToDynamic)]
+            struct WorkspaceStats {
+                windows: usize,
+                tabs: usize,
+                panes: usize,
+            }
+            impl_lua_conversion_dynamic!(WorkspaceStats);
+
+            let mut stats = WorkspaceStats {
+                windows: 0,
+                tabs: 0,
+                panes: 0,
+            };
+            for window_id in mux.iter_windows_in_workspace(&mux.active_workspace()) {
+                if let Some(window) = mux.get_window(window_id) {
+                    stats.windows += 1;
+                    for tab in window.iter() {
+                        stats.tabs += 1;
+                        // Ignore zoom so that panes hidden by a zoomed
+                        // pane are also counted
+                        stats.panes += tab.iter_panes_ignoring_zoom().len();
+                    }
+                }
+            }
+            Ok(stats)
+        });
+        methods.add_async_method("get_font_scale", |_, this, _: ()| async move {
+            let result = this
+                .with_term_window(move |term_window| term_window.get_font_scale())
+                .await?;
+
+            Ok(result)
+        });
+        methods.add_method("set_font_scale", |_, this, font_scale: f64| {
+            const MIN_FONT_SCALE: f64 = 0.1;
+            const MAX_FONT_SCALE: f64 = 10.0;
+            if !(MIN_FONT_SCALE..=MAX_FONT_SCALE).contains(&font_scale) {
+                return Err(mlua::Error::external(format!(
+                    "font scale {font_scale} is outside of the supported \
+                     range {MIN_FONT_SCALE} - {MAX_FONT_SCALE}"
+                )));
+            }
+            this.window
+                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    term_window.set_font_scale(font_scale);
+                })));
+            Ok(())
+        });
+        methods.add_method("visual_bell", |_, this, _: ()| {
+            this.window
+                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    if let Some(pane) = term_window.get_active_pane_or_overlay() {
+                        term_window.trigger_visual_bell(pane.pane_id());
+                    }
+                })));
+            Ok(())
+        });
+        methods.add_method("set_cursor_visible", |_, this, visible: bool| {
+            this.window
+                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    term_window.set_mouse_cursor_visible(visible);
+                })));
+            Ok(())
+        });
+        methods.add_method("set_cursor_shape", |_, this, shape: Option<String>| {
+            let shape = match shape.as_deref() {
+                None => None,
+                Some("arrow" | "default") => Some(MouseCursor::Arrow),
+                Some("text") => Some(MouseCursor::Text),
+                Some("hand" | "pointer") => Some(MouseCursor::Hand),
+                Some("size_up_down" | "ns-resize") => Some(MouseCursor::SizeUpDown),
+                Some("size_left_right" | "ew-resize") => Some(MouseCursor::SizeLeftRight),
+                Some(other) => {
+                    return Err(mlua::Error::external(format!(
+                        "unknown cursor shape {other:?}; expected one of \
+                         \"arrow\", \"text\", \"hand\", \"size_up_down\" \
+                         or \"size_left_right\""
+                    )))
+                }
+            };
+            this.window
+                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    term_window.set_mouse_cursor_shape(shape);
+                })));
+            Ok(())
+        });
+        methods.add_method("open_url", |_, this, url: String| {
+            url::Url::parse(&url)
+                .map_err(|err| mlua::Error::external(format!("invalid URL {url:?}: {err:#}")))?;
+            this.window.notify(TermWindowNotif::Apply(Box::new(
+                move |term_window| match term_window.get_active_pane_or_overlay() {
+                    Some(pane) => term_window.open_uri(&pane, url),
+                    None => log::error!("open_url: window has no active pane"),
+                },
+            )));
+            Ok(())
+        });
         methods.add_method(
             "copy_to_clipboard",
             |_, this, (text, clipboard): (String, Option<ClipboardCopyDestination>)| {
@@ -309,6 +1639,23 @@ impl UserData for GuiWin {
                 Ok(())
             },
         );
+        methods.add_async_method(
+            "get_clipboard",
+            |_, this, clipboard: Option<ClipboardPasteSource>| async move {
+                let (tx, rx) = smol::channel::bounded(1);
+                this.window.notify(TermWindowNotif::GetClipboard {
+                    clipboard: clipboard.unwrap_or_default(),
+                    tx,
+                });
+                let text = rx
+                    .recv()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{:#}", e))
+                    .map_err(luaerr)?;
+
+                Ok(text)
+            },
+        );
         methods.add_async_method(
             "get_selection_escapes_for_pane",
             |_, this, pane: UserDataRef<MuxPane>| async move {
@@ -337,3 +1684,135 @@ impl UserData for GuiWin {
         );
     }
 }
+
+#[derive(Debug, FromDynamic, ToDynamic)]
+struct ActivateKeyTableOpts {
+    #[dynamic(default = "default_one_shot")]
+    one_shot: bool,
+    #[dynamic(default)]
+    timeout_milliseconds: Option<u64>,
+    #[dynamic(default)]
+    replace_current: bool,
+}
+impl_lua_conversion_dynamic!(ActivateKeyTableOpts);
+
+impl Default for ActivateKeyTableOpts {
+    fn default() -> Self {
+        Self {
+            one_shot: default_one_shot(),
+            timeout_milliseconds: None,
+            replace_current: false,
+        }
+    }
+}
+
+fn default_one_shot() -> bool {
+    true
+}
+
+#[derive(Debug, FromDynamic, ToDynamic)]
+struct SearchPaneOpts {
+    #[dynamic(default)]
+    regex: bool,
+    #[dynamic(default = "default_case_sensitive")]
+    case_sensitive: bool,
+    #[dynamic(default = "default_max_matches")]
+    max_matches: u32,
+}
+impl_lua_conversion_dynamic!(SearchPaneOpts);
+
+impl Default for SearchPaneOpts {
+    fn default() -> Self {
+        Self {
+            regex: false,
+            case_sensitive: default_case_sensitive(),
+            max_matches: default_max_matches(),
+        }
+    }
+}
+
+fn default_case_sensitive() -> bool {
+    true
+}
+
+fn default_max_matches() -> u32 {
+    2000
+}
+
+/// Returns the text of `res`, taken from `lines`, the first of which is
+/// `first_row`.  A row that wraps onto the next is joined to it without
+/// a newline, and trailing whitespace is trimmed from the other rows,
+/// in the same way as when copying a selection.
+fn search_result_text(first_row: StableRowIndex, lines: &[Line], res: &SearchResult) -> String {
+    let mut text = String::new();
+    for row in res.start_y..=res.end_y {
+        let line = match usize::try_from(row - first_row)
+            .ok()
+            .and_then(|idx| lines.get(idx))
+        {
+            Some(line) => line,
+            None => break,
+        };
+        let start = if row == res.start_y { res.start_x } else { 0 };
+        if row == res.end_y {
+            text.push_str(&line.columns_as_str(start..res.end_x));
+        } else if line.last_cell_was_wrapped() {
+            text.push_str(&line.columns_as_str(start..usize::MAX));
+        } else {
+            text.push_str(line.columns_as_str(start..usize::MAX).trim_end());
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Maps the motion names accepted by `window:copy_mode_move` to the
+/// equivalent copy mode assignment.  The full `CopyModeAssignment`
+/// variant names, such as `MoveToStartOfLine`, are also accepted.
+fn copy_mode_motion(motion: &str) -> Option<CopyModeAssignment> {
+    use CopyModeAssignment::*;
+    let assignment = match motion {
+        "Left" => MoveLeft,
+        "Right" => MoveRight,
+        "Up" => MoveUp,
+        "Down" => MoveDown,
+        "StartOfLine" => MoveToStartOfLine,
+        "StartOfLineContent" => MoveToStartOfLineContent,
+        "EndOfLine" => MoveToEndOfLineContent,
+        "StartOfNextLine" => MoveToStartOfNextLine,
+        "NextWord" => MoveForwardWord,
+        "PrevWord" => MoveBackwardWord,
+        "EndOfWord" => MoveForwardWordEnd,
+        "Top" => MoveToScrollbackTop,
+        "Bottom" => MoveToScrollbackBottom,
+        "ViewportTop" => MoveToViewportTop,
+        "ViewportMiddle" => MoveToViewportMiddle,
+        "ViewportBottom" => MoveToViewportBottom,
+        "PageUp" => PageUp,
+        "PageDown" => PageDown,
+        "NextZone" => MoveForwardSemanticZone,
+        "PrevZone" => MoveBackwardSemanticZone,
+        "SelectionOtherEnd" => MoveToSelectionOtherEnd,
+        _ => {
+            return CopyModeAssignment::from_dynamic(
+                &motion.to_string().to_dynamic(),
+                Default::default(),
+            )
+            .ok()
+        }
+    };
+    Some(assignment)
+}
+
+#[derive(Debug, Default, FromDynamic, ToDynamic)]
+struct WindowPaddingOpts {
+    #[dynamic(try_from = "config::OptPixelUnit", default)]
+    left: Option<Dimension>,
+    #[dynamic(try_from = "config::OptPixelUnit", default)]
+    right: Option<Dimension>,
+    #[dynamic(try_from = "config::OptPixelUnit", default)]
+    top: Option<Dimension>,
+    #[dynamic(try_from = "config::OptPixelUnit", default)]
+    bottom: Option<Dimension>,
+}
+impl_lua_conversion_dynamic!(WindowPaddingOpts);
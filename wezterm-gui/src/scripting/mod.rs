@@ -6,8 +6,9 @@ use config::lua::mlua::{self, Lua};
 use config::{DeferredKeyCode, GpuInfo, Key, KeyNoAction};
 use luahelper::dynamic_to_lua_value;
 use mux::window::WindowId as MuxWindowId;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use wezterm_dynamic::ToDynamic;
+use wezterm_font::FontConfiguration;
 
 pub mod guiwin;
 
@@ -15,6 +16,43 @@ fn luaerr(err: anyhow::Error) -> mlua::Error {
     mlua::Error::external(err)
 }
 
+/// Returns the sorted, de-duplicated family names of the fonts in the
+/// font_dirs, the built-in fonts and the fonts known to the system
+/// font locator
+pub fn enumerate_font_families(fonts: &FontConfiguration) -> Vec<String> {
+    let mut families: BTreeSet<String> = fonts
+        .list_fonts_in_font_dirs()
+        .into_iter()
+        .map(|font| font.names().family.clone())
+        .collect();
+    match fonts.list_system_fonts() {
+        Ok(sys_fonts) => {
+            families.extend(
+                sys_fonts
+                    .into_iter()
+                    .map(|font| font.names().family.clone()),
+            );
+        }
+        Err(err) => log::error!("Unable to list system fonts: {:#}", err),
+    }
+    families.into_iter().collect()
+}
+
+/// Returns the name of the font family that matches `family`, ignoring
+/// case.  Enumerating the fonts can take a while, so it is done on a
+/// background thread rather than on the GUI thread.
+pub async fn resolve_font_family(family: String) -> anyhow::Result<String> {
+    let dpi = ::window::default_dpi() as usize;
+    smol::unblock(move || {
+        let fonts = FontConfiguration::new(None, dpi)?;
+        enumerate_font_families(&fonts)
+            .into_iter()
+            .find(|candidate| candidate.eq_ignore_ascii_case(&family))
+            .ok_or_else(|| anyhow::anyhow!("font family \"{}\" was not found", family))
+    })
+    .await
+}
+
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
     let window_mod = get_or_create_sub_module(lua, "gui")?;
 
@@ -78,6 +116,21 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    window_mod.set(
+        "enumerate_fonts",
+        lua.create_async_function(|_, _: ()| async move {
+            // Listing the system fonts can take a while, so keep it
+            // off the GUI thread
+            let dpi = ::window::default_dpi() as usize;
+            smol::unblock(move || {
+                let fonts = FontConfiguration::new(None, dpi)?;
+                Ok(enumerate_font_families(&fonts))
+            })
+            .await
+            .map_err(luaerr)
+        })?,
+    )?;
+
     window_mod.set(
         "enumerate_gpus",
         lua.create_function(|_, _: ()| {
@@ -11,14 +11,97 @@ use config::{
     GradientOrientation,
 };
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use termwiz::image::{ImageData, ImageDataType};
 use wezterm_term::StableRowIndex;
+use window::WindowOps;
 
 lazy_static::lazy_static! {
     static ref IMAGE_CACHE: Mutex<HashMap<String, CachedImage>> = Mutex::new(HashMap::new());
+    static ref PENDING_IMAGES: Mutex<HashMap<String, PendingImage>> = Mutex::new(HashMap::new());
     static ref GRADIENT_CACHE: Mutex<Vec<CachedGradient>> = Mutex::new(vec![]);
+    static ref LARGEST_WINDOW: Mutex<(u32, u32)> = Mutex::new((0, 0));
+}
+
+/// Incremented each time an image has finished being downscaled on a
+/// background thread; see `background_generation`
+static BACKGROUND_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a value that changes whenever an image has finished being
+/// downscaled on a background thread, at which point the windows need
+/// to load their backgrounds again to pick it up
+pub fn background_generation() -> usize {
+    BACKGROUND_GENERATION.load(Ordering::Acquire)
+}
+
+/// Background images are downscaled when they are loaded, so that they
+/// are no larger than is needed to cover the largest window seen so far
+/// multiplied by this factor.  The headroom allows for some cropping or
+/// offsetting of the image without a visible loss of detail.
+const BACKGROUND_SUPERSAMPLE: f32 = 1.5;
+
+/// Returns the size that a background image needs to cover in order
+/// to render at full quality in a window of the specified size
+fn supersampled_size((width, height): (u32, u32)) -> (u32, u32) {
+    (
+        (width as f32 * BACKGROUND_SUPERSAMPLE).ceil() as u32,
+        (height as f32 * BACKGROUND_SUPERSAMPLE).ceil() as u32,
+    )
+}
+
+/// The size that background images need to cover is rounded up to a
+/// multiple of this many pixels, so that growing a window only
+/// occasionally requires the images to be downscaled again
+const BACKGROUND_LIMIT_STEP: u32 = 1024;
+
+/// Records that a window of size `dimensions` exists, and returns the
+/// size that background images need to cover in order to render at full
+/// quality in the largest window seen so far
+fn background_image_limit(dimensions: &Dimensions) -> (u32, u32) {
+    let mut largest = LARGEST_WINDOW.lock().unwrap();
+    largest.0 = largest.0.max(dimensions.pixel_width as u32);
+    largest.1 = largest.1.max(dimensions.pixel_height as u32);
+    let (width, height) = supersampled_size(*largest);
+    let round_up = |n: u32| n.div_ceil(BACKGROUND_LIMIT_STEP).max(1) * BACKGROUND_LIMIT_STEP;
+    (round_up(width), round_up(height))
+}
+
+/// Returns the smallest size that covers `limit` while preserving the
+/// aspect ratio of `data`, if `data` is a still image that is larger
+/// than that.  Animations cannot be resized.
+/// This only reads the headers of the file, so it is cheap enough to
+/// call on the GUI thread.
+fn downscaled_size(data: &ImageDataType, limit: (u32, u32)) -> Option<(u32, u32)> {
+    let (width, height) = data.dimensions().ok()?;
+    let scale = (limit.0 as f32 / width as f32).max(limit.1 as f32 / height as f32);
+    if scale >= 1.0 || data.is_animated() {
+        return None;
+    }
+    Some((
+        ((width as f32 * scale).ceil() as u32).clamp(1, width),
+        ((height as f32 * scale).ceil() as u32).clamp(1, height),
+    ))
+}
+
+/// Decodes `data` and resamples it to `size`, which was computed by
+/// `downscaled_size`.  This is expensive for a large image, so it must
+/// not be called on the GUI thread.
+/// Returns the image along with its new dimensions, or `None` if it
+/// could not be downscaled.
+fn downscale_background(
+    data: ImageDataType,
+    (width, height): (u32, u32),
+) -> (ImageDataType, Option<(u32, u32)>) {
+    let data = data.decode();
+    match data.resize(width, height) {
+        Some(resized) => {
+            log::trace!("downscaled background image to {width}x{height}");
+            (resized, Some((width, height)))
+        }
+        None => (data, None),
+    }
 }
 
 struct CachedGradient {
@@ -189,18 +272,61 @@ struct CachedImage {
     image: Arc<ImageData>,
     marked: bool,
     speed: f32,
+    /// The size to which the image was downscaled, if it was larger
+    /// than needed at the time that it was loaded
+    downscaled: Option<(u32, u32)>,
+}
+
+/// A background image that is being decoded and downscaled on a
+/// background thread; see `CachedImage::load`
+struct PendingImage {
+    modified: SystemTime,
+    speed: f32,
+    limit: (u32, u32),
+}
+
+impl PendingImage {
+    fn covers(&self, modified: SystemTime, speed: f32, limit: (u32, u32)) -> bool {
+        self.modified == modified
+            && self.speed == speed
+            && self.limit.0 >= limit.0
+            && self.limit.1 >= limit.1
+    }
 }
 
 impl CachedImage {
-    fn load(path: &str, speed: f32) -> anyhow::Result<Arc<ImageData>> {
+    /// Returns the image at `path`, along with the size to which it was
+    /// downscaled, if any.
+    /// An image that is larger than is needed to cover `limit` is decoded
+    /// and downscaled on a background thread, after which the windows
+    /// load their backgrounds again.  Until then, the version of the
+    /// image that was previously loaded is returned, if there is one,
+    /// and otherwise `None`.
+    fn load(
+        path: &str,
+        speed: f32,
+        limit: (u32, u32),
+    ) -> anyhow::Result<Option<(Arc<ImageData>, Option<(u32, u32)>)>> {
         let modified = std::fs::metadata(path)
             .and_then(|m| m.modified())
             .with_context(|| format!("getting metadata for {}", path))?;
-        let mut cache = IMAGE_CACHE.lock().unwrap();
-        if let Some(cached) = cache.get_mut(path) {
-            if cached.modified == modified && cached.speed == speed {
+        let previous = match IMAGE_CACHE.lock().unwrap().get_mut(path) {
+            Some(cached) => {
                 cached.marked = false;
-                return Ok(Arc::clone(&cached.image));
+                if cached.modified == modified
+                    && cached.speed == speed
+                    && !is_below_limit(cached.downscaled, limit)
+                {
+                    return Ok(Some((Arc::clone(&cached.image), cached.downscaled)));
+                }
+                Some((Arc::clone(&cached.image), cached.downscaled))
+            }
+            None => None,
+        };
+
+        if let Some(pending) = PENDING_IMAGES.lock().unwrap().get(path) {
+            if pending.covers(modified, speed, limit) {
+                return Ok(previous);
             }
         }
 
@@ -208,20 +334,82 @@ impl CachedImage {
             .with_context(|| format!("Failed to load window_background_image {}", path))?;
         log::trace!("loaded {}", path);
         let mut data = ImageDataType::EncodedFile(data);
-        data.adjust_speed(speed);
-        let image = Arc::new(ImageData::with_data(data));
 
-        cache.insert(
+        if let Some(size) = downscaled_size(&data, limit) {
+            Self::spawn_downscale(path.to_string(), data, size, modified, speed, limit);
+            return Ok(previous);
+        }
+
+        data.adjust_speed(speed);
+        let image = Arc::new(ImageData::with_data(data).with_source(path));
+        IMAGE_CACHE.lock().unwrap().insert(
             path.to_string(),
             Self {
                 modified,
                 image: Arc::clone(&image),
                 marked: false,
                 speed,
+                downscaled: None,
             },
         );
 
-        Ok(image)
+        Ok(Some((image, None)))
+    }
+
+    /// Decodes `data` and downscales it to `size` on a background thread,
+    /// then caches the result and repaints the windows, which load their
+    /// backgrounds again because `background_generation` has changed
+    fn spawn_downscale(
+        path: String,
+        data: ImageDataType,
+        size: (u32, u32),
+        modified: SystemTime,
+        speed: f32,
+        limit: (u32, u32),
+    ) {
+        PENDING_IMAGES.lock().unwrap().insert(
+            path.clone(),
+            PendingImage {
+                modified,
+                speed,
+                limit,
+            },
+        );
+
+        std::thread::spawn(move || {
+            let (mut data, downscaled) = downscale_background(data, size);
+            data.adjust_speed(speed);
+            let image = Arc::new(ImageData::with_data(data).with_source(path.as_str()));
+
+            {
+                let mut pending = PENDING_IMAGES.lock().unwrap();
+                // Discard the result if a later request for the same
+                // path has superseded this one
+                match pending.get(&path) {
+                    Some(p) if p.modified == modified && p.speed == speed && p.limit == limit => {}
+                    _ => return,
+                }
+                IMAGE_CACHE.lock().unwrap().insert(
+                    path.clone(),
+                    Self {
+                        modified,
+                        image,
+                        marked: false,
+                        speed,
+                        downscaled,
+                    },
+                );
+                pending.remove(&path);
+            }
+            BACKGROUND_GENERATION.fetch_add(1, Ordering::AcqRel);
+
+            promise::spawn::spawn_into_main_thread(async move {
+                for win in crate::frontend::front_end().gui_windows() {
+                    win.window.invalidate();
+                }
+            })
+            .detach();
+        });
     }
 
     fn mark() {
@@ -245,13 +433,37 @@ impl CachedImage {
 pub struct LoadedBackgroundLayer {
     pub source: Arc<ImageData>,
     pub def: BackgroundLayer,
+    /// The size to which an image file was downscaled when it was
+    /// loaded, if it was larger than needed at that time
+    pub downscaled: Option<(u32, u32)>,
 }
 
+/// Returns true if an image that was downscaled to `downscaled` is
+/// no longer large enough to cover `limit`
+fn is_below_limit(downscaled: Option<(u32, u32)>, limit: (u32, u32)) -> bool {
+    match downscaled {
+        Some((width, height)) => width < limit.0 || height < limit.1,
+        None => false,
+    }
+}
+
+/// Returns true if any of the image files in `layers` were downscaled
+/// to a size that is too small to render at full quality in a window
+/// of size `dimensions`, and should therefore be reloaded
+pub fn background_needs_reload(layers: &[LoadedBackgroundLayer], dimensions: &Dimensions) -> bool {
+    let limit = background_image_limit(dimensions);
+    layers
+        .iter()
+        .any(|layer| is_below_limit(layer.downscaled, limit))
+}
+
+/// Loads `layer`, returning `None` if it is an image that is still
+/// being downscaled; see `CachedImage::load`
 fn load_background_layer(
     layer: &BackgroundLayer,
     dimensions: &Dimensions,
     render_metrics: &RenderMetrics,
-) -> anyhow::Result<LoadedBackgroundLayer> {
+) -> anyhow::Result<Option<LoadedBackgroundLayer>> {
     let h_context = DimensionContext {
         dpi: dimensions.dpi as f32,
         pixel_max: dimensions.pixel_width as f32,
@@ -263,6 +475,7 @@ fn load_background_layer(
         pixel_cell: render_metrics.cell_size.height as f32,
     };
 
+    let mut downscaled = None;
     let data = match &layer.source {
         BackgroundSource::Gradient(g) => {
             let mut width = match layer.width {
@@ -327,13 +540,23 @@ fn load_background_layer(
                 size, size, data,
             )))
         }
-        BackgroundSource::File(source) => CachedImage::load(&source.path, source.speed)?,
+        BackgroundSource::File(source) => {
+            let limit = background_image_limit(dimensions);
+            match CachedImage::load(&source.path, source.speed, limit)? {
+                Some((image, size)) => {
+                    downscaled = size;
+                    image
+                }
+                None => return Ok(None),
+            }
+        }
     };
 
-    Ok(LoadedBackgroundLayer {
+    Ok(Some(LoadedBackgroundLayer {
         source: data,
         def: layer.clone(),
-    })
+        downscaled,
+    }))
 }
 
 pub fn load_background_image(
@@ -345,10 +568,13 @@ pub fn load_background_image(
     for layer in &config.background {
         let load_start = std::time::Instant::now();
         match load_background_layer(layer, dimensions, render_metrics) {
-            Ok(layer) => {
+            Ok(Some(layer)) => {
                 log::trace!("loaded layer in {:?}", load_start.elapsed());
                 layers.push(layer);
             }
+            Ok(None) => {
+                log::trace!("layer is still loading");
+            }
             Err(err) => {
                 log::error!("Failed to load background: {:#}", err);
             }
@@ -394,6 +620,22 @@ pub fn reload_background_image(
 }
 
 impl crate::TermWindow {
+    /// Loads the background layers again if an image has finished being
+    /// downscaled on a background thread since they were last loaded
+    pub fn reload_background_if_downscaled(&mut self) {
+        let generation = background_generation();
+        if generation == self.background_generation {
+            return;
+        }
+        self.background_generation = generation;
+        self.window_background = reload_background_image(
+            &self.config,
+            &self.window_background,
+            &self.dimensions,
+            &self.render_metrics,
+        );
+    }
+
     pub fn render_backgrounds(
         &self,
         bg_color: LinearRgba,
@@ -429,6 +671,7 @@ impl crate::TermWindow {
             &layer.source,
             None,
             self.allow_images,
+            false,
         )?;
         self.update_next_frame_time(next_due);
 
@@ -607,3 +850,61 @@ impl crate::TermWindow {
         Ok(emitted)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oversized_background_is_downscaled() {
+        let mut png = vec![];
+        image::RgbaImage::new(800, 400)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        // A 100x40 window needs a 150x60 image; preserving the aspect
+        // ratio of the 800x400 image requires 150x75 to cover that
+        let limit = supersampled_size((100, 40));
+        assert_eq!(limit, (150, 60));
+        let (data, downscaled) = downscale_background(png.clone(), limit);
+        assert_eq!(downscaled, Some((150, 75)));
+        assert_eq!(data.dimensions().unwrap(), (150, 75));
+        assert!(matches!(data, ImageDataType::Rgba8 { .. }));
+
+        assert!(!is_below_limit(downscaled, limit));
+        assert!(is_below_limit(downscaled, supersampled_size((200, 40))));
+
+        // An image that is already small enough is left encoded,
+        // for the glyph cache to decode as usual
+        let (data, downscaled) = downscale_background(png, supersampled_size((1000, 1000)));
+        assert_eq!(downscaled, None);
+        assert!(matches!(data, ImageDataType::EncodedFile(_)));
+    }
+
+    #[test]
+    fn oversized_animation_is_left_encoded() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame};
+
+        let mut gif = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            let frames = (0..2).map(|_| {
+                Frame::from_parts(
+                    image::RgbaImage::new(800, 400),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(100, 1),
+                )
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        let (data, downscaled) = downscale_background(gif.clone(), supersampled_size((100, 40)));
+        assert_eq!(downscaled, None);
+        match data {
+            ImageDataType::EncodedFile(encoded) => assert_eq!(encoded, gif),
+            other => panic!("expected EncodedFile, got {:?}", other),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::TermWindow;
 use config::keyassignment::{ClipboardCopyDestination, ClipboardPasteSource};
 use mux::pane::Pane;
 use mux::Mux;
+use smol::channel::Sender;
 use std::sync::Arc;
 use window::{Clipboard, WindowOps};
 
@@ -57,4 +58,29 @@ impl TermWindow {
         .detach();
         self.maybe_scroll_to_bottom_for_input(&pane);
     }
+
+    /// Asynchronously reads the text content of the specified clipboard,
+    /// sending the result via `tx`.
+    /// Some platforms may fail or deny the read, in which case `None`
+    /// is sent and a warning is logged.
+    pub fn get_clipboard_text(&self, clipboard: ClipboardPasteSource, tx: Sender<Option<String>>) {
+        let window = self.window.as_ref().unwrap().clone();
+        let clipboard = match clipboard {
+            ClipboardPasteSource::Clipboard => Clipboard::Clipboard,
+            ClipboardPasteSource::PrimarySelection => Clipboard::PrimarySelection,
+        };
+        let future = window.get_clipboard(clipboard);
+        promise::spawn::spawn(async move {
+            let text = match future.await {
+                Ok(text) if text.is_empty() => None,
+                Ok(text) => Some(text),
+                Err(err) => {
+                    log::warn!("failed to read {clipboard:?}: {err:#}");
+                    None
+                }
+            };
+            tx.try_send(text).ok();
+        })
+        .detach();
+    }
 }
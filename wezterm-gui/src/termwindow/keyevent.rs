@@ -1,4 +1,4 @@
-use crate::termwindow::InputMap;
+use crate::termwindow::{InputMap, LeaderState};
 use ::window::{
     DeadKeyStatus, KeyCode, KeyEvent, KeyboardLedStatus, Modifiers, RawKeyEvent, WindowOps,
 };
@@ -251,19 +251,7 @@ impl super::TermWindow {
         if is_down && !leader_active {
             // Check to see if this key-press is the leader activating
             if let Some(duration) = self.input_map.is_leader(&keycode, raw_modifiers) {
-                // Yes; record its expiration
-                let target = std::time::Instant::now() + duration;
-                self.leader_is_down.replace(target);
-                self.update_title();
-                // schedule an invalidation so that the cursor or status
-                // area will be repainted at the right time
-                if let Some(window) = self.window.clone() {
-                    promise::spawn::spawn(async move {
-                        Timer::at(target).await;
-                        window.invalidate();
-                    })
-                    .detach();
-                }
+                self.activate_leader(self.leader_activation_timeout(duration));
                 return true;
             }
         }
@@ -291,7 +279,7 @@ impl super::TermWindow {
                 raw_modifiers | leader_mod,
                 only_key_bindings,
             ) {
-                if self.config.debug_key_events {
+                if self.debug_key_events() {
                     log::info!(
                         "{}{:?} {:?} -> perform {:?}",
                         match table_name {
@@ -363,7 +351,7 @@ impl super::TermWindow {
                     let mut did_encode = false;
                     if let Some(key_event) = key_event {
                         if let Some(encoded) = self.encode_win32_input(&pane, &key_event) {
-                            if self.config.debug_key_events {
+                            if self.debug_key_events() {
                                 log::info!("win32: Encoded input as {:?}", encoded);
                             }
                             pane.writer()
@@ -372,7 +360,7 @@ impl super::TermWindow {
                                 .ok();
                             did_encode = true;
                         } else if let Some(encoded) = self.encode_kitty_input(&pane, &key_event) {
-                            if self.config.debug_key_events {
+                            if self.debug_key_events() {
                                 log::info!("kitty: Encoded input as {:?}", encoded);
                             }
                             pane.writer()
@@ -383,7 +371,7 @@ impl super::TermWindow {
                         }
                     };
                     if !did_encode {
-                        if self.config.debug_key_events {
+                        if self.debug_key_events() {
                             log::info!(
                                 "{:?} {:?} -> send to pane {:?} {:?}",
                                 keycode,
@@ -438,7 +426,7 @@ impl super::TermWindow {
             (false, Modifiers::NONE)
         };
 
-        if self.config.debug_key_events {
+        if self.debug_key_events() {
             log::info!(
                 "key_event {:?} {}",
                 key,
@@ -527,35 +515,127 @@ impl super::TermWindow {
         }
     }
 
+    /// Returns true if key events should be logged, either because
+    /// debug_key_events is enabled or because of window:set_key_trace
+    pub fn debug_key_events(&self) -> bool {
+        self.key_trace || self.config.debug_key_events
+    }
+
+    /// Enables or disables logging of key events for this window
+    pub fn set_key_trace(&mut self, enable: bool) {
+        self.key_trace = enable;
+    }
+
+    /// Returns true if key event logging was enabled by set_key_trace
+    pub fn is_key_trace_enabled(&self) -> bool {
+        self.key_trace
+    }
+
     pub fn current_modifier_and_led_state(&self) -> (Modifiers, KeyboardLedStatus) {
         self.current_modifier_and_leds
     }
 
     pub fn leader_is_active(&self) -> bool {
-        match self.leader_is_down.as_ref() {
-            Some(expiry) if *expiry > std::time::Instant::now() => {
-                self.update_next_frame_time(Some(*expiry));
+        match self.leader_state {
+            LeaderState::Down { until: None } => true,
+            LeaderState::Down {
+                until: Some(expiry),
+            } if expiry > Instant::now() => {
+                self.update_next_frame_time(Some(expiry));
                 true
             }
-            Some(_) => false,
-            None => false,
+            LeaderState::Down { .. } | LeaderState::Up => false,
+        }
+    }
+
+    /// Puts the leader into the active state for the specified duration.
+    /// If the leader is already active, its expiration is replaced
+    /// rather than extended.
+    /// A duration of None keeps the leader active until the next key press.
+    pub fn activate_leader(&mut self, duration: Option<std::time::Duration>) {
+        let duration = match duration {
+            Some(duration) => duration,
+            None => {
+                self.leader_state = LeaderState::Down { until: None };
+                self.update_title();
+                if let Some(window) = self.window.as_ref() {
+                    window.invalidate();
+                }
+                return;
+            }
+        };
+
+        // Record its expiration
+        let target = std::time::Instant::now() + duration;
+        self.leader_state = LeaderState::Down {
+            until: Some(target),
+        };
+        self.update_title();
+        // schedule an invalidation so that the cursor or status
+        // area will be repainted at the right time
+        if let Some(window) = self.window.clone() {
+            window.invalidate();
+            promise::spawn::spawn(async move {
+                Timer::at(target).await;
+                window.invalidate();
+            })
+            .detach();
+        }
+    }
+
+    /// Returns the leader timeout set by set_leader_timeout, if any,
+    /// otherwise the configured leader timeout, or the default
+    /// leader timeout if no leader is configured
+    pub fn leader_timeout(&self) -> std::time::Duration {
+        self.leader_timeout_override
+            .or_else(|| self.input_map.leader_timeout())
+            .unwrap_or(std::time::Duration::from_millis(1000))
+    }
+
+    /// Returns the duration for which the leader should be activated,
+    /// given its `configured` timeout.  The timeout set by
+    /// set_leader_timeout takes precedence, and a zero override means
+    /// that the leader has no timeout, in which case None is returned.
+    /// A configured timeout of zero keeps its usual meaning.
+    pub fn leader_activation_timeout(
+        &self,
+        configured: std::time::Duration,
+    ) -> Option<std::time::Duration> {
+        match self.leader_timeout_override {
+            Some(duration) if duration.is_zero() => None,
+            Some(duration) => Some(duration),
+            None => Some(configured),
         }
     }
 
+    /// Replaces the leader timeout for this window, taking effect
+    /// the next time that the leader is activated.
+    /// A zero duration means that the leader has no timeout.
+    pub fn set_leader_timeout(&mut self, duration: std::time::Duration) {
+        self.leader_timeout_override.replace(duration);
+    }
+
     pub fn leader_is_active_mut(&mut self) -> bool {
-        match self.leader_is_down.as_ref() {
-            Some(expiry) if *expiry > std::time::Instant::now() => {
-                self.update_next_frame_time(Some(*expiry));
+        match self.leader_state {
+            LeaderState::Down { until: None } => true,
+            LeaderState::Down {
+                until: Some(expiry),
+            } if expiry > Instant::now() => {
+                self.update_next_frame_time(Some(expiry));
                 true
             }
-            Some(_) => {
+            LeaderState::Down { .. } => {
                 self.leader_done();
                 false
             }
-            None => false,
+            LeaderState::Up => false,
         }
     }
 
+    pub fn key_table_names(&self) -> Vec<String> {
+        self.input_map.table_names()
+    }
+
     pub fn current_key_table_name(&mut self) -> Option<String> {
         let mut name = None;
 
@@ -588,8 +668,21 @@ impl super::TermWindow {
         &self.dead_key_status
     }
 
+    /// Discards any in-progress dead key or IME composition text.
+    /// This is a no-op if there is no active composition.
+    pub fn clear_composition(&mut self) {
+        if self.dead_key_status == DeadKeyStatus::None {
+            return;
+        }
+        self.dead_key_status = DeadKeyStatus::None;
+        self.update_title();
+        if let Some(window) = &self.window {
+            window.invalidate();
+        }
+    }
+
     fn leader_done(&mut self) {
-        self.leader_is_down.take();
+        self.leader_state = LeaderState::Up;
         self.update_title();
         if let Some(window) = &self.window {
             window.invalidate();
@@ -612,7 +705,7 @@ impl super::TermWindow {
             (false, Modifiers::NONE)
         };
 
-        if self.config.debug_key_events {
+        if self.debug_key_events() {
             log::info!(
                 "key_event {:?} {}",
                 window_key,
@@ -672,21 +765,21 @@ impl super::TermWindow {
                 }
 
                 let res = if let Some(encoded) = self.encode_win32_input(&pane, &window_key) {
-                    if self.config.debug_key_events {
+                    if self.debug_key_events() {
                         log::info!("win32: Encoded input as {:?}", encoded);
                     }
                     pane.writer()
                         .write_all(encoded.as_bytes())
                         .context("sending win32-input-mode encoded data")
                 } else if let Some(encoded) = self.encode_kitty_input(&pane, &window_key) {
-                    if self.config.debug_key_events {
+                    if self.debug_key_events() {
                         log::info!("kitty: Encoded input as {:?}", encoded);
                     }
                     pane.writer()
                         .write_all(encoded.as_bytes())
                         .context("sending kitty encoded data")
                 } else {
-                    if self.config.debug_key_events {
+                    if self.debug_key_events() {
                         log::info!(
                             "send to pane {} key={:?} mods={:?}",
                             if window_key.key_is_down { "DOWN" } else { "UP" },
@@ -732,7 +825,7 @@ impl super::TermWindow {
                     return;
                 }
                 self.key_table_state.did_process_key();
-                if self.config.debug_key_events {
+                if self.debug_key_events() {
                     log::info!("send to pane string={:?}", s);
                 }
                 pane.writer().write_all(s.as_bytes()).ok();
@@ -12,11 +12,11 @@ use crate::overlay::{
 use crate::resize_increment_calculator::ResizeIncrementCalculator;
 use crate::scripting::guiwin::GuiWin;
 use crate::scrollbar::*;
-use crate::selection::Selection;
+use crate::selection::{Selection, SelectionRange};
 use crate::shapecache::*;
 use crate::tabbar::{TabBarItem, TabBarState};
 use crate::termwindow::background::{
-    load_background_image, reload_background_image, LoadedBackgroundLayer,
+    background_generation, load_background_image, reload_background_image, LoadedBackgroundLayer,
 };
 use crate::termwindow::keyevent::{KeyTableArgs, KeyTableState};
 use crate::termwindow::modal::Modal;
@@ -30,13 +30,14 @@ use ::wezterm_term::input::{ClickPosition, MouseButton as TMB};
 use ::window::*;
 use anyhow::{anyhow, ensure, Context};
 use config::keyassignment::{
-    KeyAssignment, PaneDirection, Pattern, PromptInputLine, QuickSelectArguments,
-    RotationDirection, SpawnCommand, SplitSize,
+    ClipboardPasteSource, CopyModeAssignment, KeyAssignment, PaneDirection, Pattern,
+    PromptInputLine, QuickSelectArguments, RotationDirection, SpawnCommand, SplitSize,
 };
 use config::window::WindowLevel;
 use config::{
-    configuration, AudibleBell, ConfigHandle, Dimension, DimensionContext, FrontEndSelection,
-    GeometryOrigin, GuiPosition, TermConfig, WindowCloseConfirmation,
+    configuration, AudibleBell, ConfigHandle, Dimension, DimensionContext, FontAttributes,
+    FrontEndSelection, GeometryOrigin, GuiPosition, TermConfig, WindowCloseConfirmation,
+    WindowPadding,
 };
 use lfucache::*;
 use mlua::{FromLua, UserData, UserDataFields};
@@ -62,7 +63,7 @@ use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use termwiz::hyperlink::Hyperlink;
 use termwiz::surface::SequenceNo;
-use wezterm_dynamic::Value;
+use wezterm_dynamic::{ToDynamic, Value};
 use wezterm_font::FontConfiguration;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::input::LastMouseClick;
@@ -87,6 +88,11 @@ use crate::spawn::SpawnWhere;
 use prevcursor::PrevCursorPos;
 
 const ATLAS_SIZE: usize = 128;
+/// The minimum interval between `bell` events emitted for a given pane
+const BELL_EVENT_MIN_INTERVAL: Duration = Duration::from_millis(250);
+/// How long to wait after the selection changes before emitting
+/// the `selection-changed` event
+const SELECTION_EVENT_DELAY: Duration = Duration::from_millis(150);
 
 lazy_static::lazy_static! {
     static ref WINDOW_CLASS: Mutex<String> = Mutex::new(wezterm_gui_subcommands::DEFAULT_WINDOW_CLASS.to_owned());
@@ -117,6 +123,8 @@ pub enum MouseCapture {
 /// context of the window-specific event loop
 pub enum TermWindowNotif {
     InvalidateShapeCache,
+    /// Discard the cached quads and tab bar and schedule a repaint
+    Invalidate,
     PerformAssignment {
         pane_id: PaneId,
         assignment: KeyAssignment,
@@ -124,7 +132,18 @@ pub enum TermWindowNotif {
     },
     SetLeftStatus(String),
     SetRightStatus(String),
-    GetDimensions(Sender<(Dimensions, WindowState)>),
+    /// Responds with the window dimensions and state, the terminal
+    /// grid size, the cell size in pixels and the size of the
+    /// tab bar and padding
+    GetDimensions(
+        Sender<(
+            Dimensions,
+            WindowState,
+            TerminalSize,
+            Size,
+            ChromeDimensions,
+        )>,
+    ),
     GetSelectionForPane {
         pane_id: PaneId,
         tx: Sender<String>,
@@ -149,6 +168,38 @@ pub enum TermWindowNotif {
         width: usize,
         height: usize,
     },
+    GetClipboard {
+        clipboard: ClipboardPasteSource,
+        tx: Sender<Option<String>>,
+    },
+}
+
+/// The sizes, in pixels, of the tab bar and padding that surround
+/// the terminal content in the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChromeDimensions {
+    /// Zero when the tab bar is not shown
+    pub tab_bar_height: usize,
+    pub tab_bar_at_bottom: bool,
+    pub padding_left: usize,
+    pub padding_right: usize,
+    pub padding_top: usize,
+    pub padding_bottom: usize,
+}
+
+/// Describes where to move the viewport of a pane
+/// in TermWindow::scroll_pane
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaneScrollTarget {
+    /// Scroll by the specified number of lines;
+    /// negative values scroll up into the scrollback
+    Lines(isize),
+    Top,
+    Bottom,
+    Row(StableRowIndex),
+    /// Scroll to the Nth next (positive) or previous (negative)
+    /// prompt, as recorded by OSC 133 semantic zones
+    Prompt(isize),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -203,6 +254,19 @@ pub struct PaneState {
     pub overlay: Option<OverlayState>,
 
     bell_start: Option<Instant>,
+    /// When we last emitted the `bell` event for this pane; used to
+    /// rate limit the event when a program rings the bell repeatedly
+    last_bell_event: Option<Instant>,
+    /// The selection range most recently reported via the
+    /// `selection-changed` event
+    reported_selection: Option<SelectionRange>,
+    /// Whether a deferred `selection-changed` event is pending
+    selection_event_scheduled: bool,
+    /// The pane title most recently reported via the
+    /// `pane-title-changed` event
+    reported_title: Option<String>,
+    /// Whether a deferred `pane-title-changed` event is pending
+    title_event_scheduled: bool,
     pub mouse_terminal_coords: Option<(ClickPosition, StableRowIndex)>,
 }
 
@@ -358,6 +422,17 @@ enum EventState {
     InProgressWithQueued(Option<PaneId>),
 }
 
+/// Tracks whether the LEADER modifier is active
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LeaderState {
+    Up,
+    /// The leader is active until the specified instant, or,
+    /// if that is None, until the next key press.
+    Down {
+        until: Option<Instant>,
+    },
+}
+
 pub struct TermWindow {
     pub window: Option<Window>,
     pub config: ConfigHandle,
@@ -365,11 +440,22 @@ pub struct TermWindow {
     os_parameters: Option<parameters::Parameters>,
     /// When we most recently received keyboard focus
     pub focused: Option<Instant>,
+    /// The focus state most recently passed to the
+    /// `window-focus-changed` event
+    reported_focus: bool,
+    /// Set while a deferred `window-focus-changed` event is pending
+    focus_event_scheduled: bool,
     fonts: Rc<FontConfiguration>,
     /// Window dimensions and dpi
     pub dimensions: Dimensions,
     pub window_state: WindowState,
+    /// The WM_CLASS/app_id that the window manager sees
+    pub app_id: String,
     pub resizes_pending: usize,
+    /// The pixel size most recently requested by window:set_inner_size,
+    /// until the next resize event reports the size that we got
+    requested_inner_size: Option<(usize, usize)>,
+    animations_paused: bool,
     is_repaint_pending: bool,
     pending_scale_changes: LinkedList<resize::ScaleChange>,
     /// Terminal dimensions
@@ -379,8 +465,12 @@ pub struct TermWindow {
     pub render_metrics: RenderMetrics,
     render_state: Option<RenderState>,
     input_map: InputMap,
-    /// If is_some, the LEADER modifier is active until the specified instant.
-    leader_is_down: Option<std::time::Instant>,
+    leader_state: LeaderState,
+    /// Set by window:set_leader_timeout to replace the configured timeout
+    leader_timeout_override: Option<std::time::Duration>,
+    /// Set by window:set_key_trace to log key events, in the same way
+    /// as the debug_key_events configuration option
+    key_trace: bool,
     dead_key_status: DeadKeyStatus,
     key_table_state: KeyTableState,
     show_tab_bar: bool,
@@ -404,6 +494,9 @@ pub struct TermWindow {
     semantic_zones: HashMap<PaneId, SemanticZoneCache>,
 
     window_background: Vec<LoadedBackgroundLayer>,
+    /// The value of background_generation when window_background was
+    /// loaded; see reload_background_if_downscaled
+    background_generation: usize,
 
     current_modifier_and_leds: (Modifiers, KeyboardLedStatus),
     current_mouse_buttons: Vec<MousePress>,
@@ -417,6 +510,18 @@ pub struct TermWindow {
     /// The URL over which we are currently hovering
     current_highlight: Option<Arc<Hyperlink>>,
 
+    /// Set via lua to hide the mouse cursor while it is over the window
+    mouse_cursor_hidden: bool,
+    /// Set via lua to replace the shape that we'd otherwise use
+    /// for the mouse cursor
+    mouse_cursor_shape: Option<::window::MouseCursor>,
+
+    /// The title that we most recently assigned to the window
+    window_title: String,
+    /// Arbitrary key/value pairs set via lua; unlike lua state,
+    /// these persist across config reloads
+    user_vars: HashMap<String, String>,
+
     quad_generation: usize,
     shape_generation: usize,
     shape_cache: RefCell<LfuCache<ShapeCacheKey, anyhow::Result<Rc<Vec<ShapedInfo>>>>>,
@@ -529,6 +634,7 @@ impl TermWindow {
             self.current_mouse_buttons.clear();
             self.current_mouse_capture = None;
             self.is_click_to_focus_window = false;
+            self.mouse_cursor_hidden = false;
 
             for state in self.pane_state.borrow_mut().values_mut() {
                 state.mouse_terminal_coords.take();
@@ -546,6 +652,43 @@ impl TermWindow {
         }
 
         self.update_title();
+        match self.config.window_focus_event_debounce_ms {
+            0 => self.emit_focus_changed(),
+            delay => self.schedule_focus_changed_event(Duration::from_millis(delay)),
+        }
+    }
+
+    /// Arranges to emit the `window-focus-changed` event after `delay`,
+    /// so that a burst of focus changes, such as an alt-tab storm,
+    /// results in a single event that reports the settled state.
+    fn schedule_focus_changed_event(&mut self, delay: Duration) {
+        if self.focus_event_scheduled {
+            return;
+        }
+        let window = match self.window.clone() {
+            Some(window) => window,
+            None => return,
+        };
+        self.focus_event_scheduled = true;
+        promise::spawn::spawn(async move {
+            Timer::after(delay).await;
+            window.notify(TermWindowNotif::Apply(Box::new(|term_window| {
+                term_window.emit_focus_changed();
+            })));
+        })
+        .detach();
+    }
+
+    fn emit_focus_changed(&mut self) {
+        self.focus_event_scheduled = false;
+        // Some environments report the same focus state more than once,
+        // and a burst of changes may end where it started; only advise
+        // the config of an actual change.
+        let focused = self.focused.is_some();
+        if focused == self.reported_focus {
+            return;
+        }
+        self.reported_focus = focused;
         self.emit_window_event("window-focus-changed", None);
     }
 
@@ -660,6 +803,7 @@ impl TermWindow {
         dimensions.pixel_height += (border.top + border.bottom).get() as usize;
         dimensions.pixel_width += (border.left + border.right).get() as usize;
 
+        let background_generation = background_generation();
         let window_background = load_background_image(&config, &dimensions, &render_metrics);
 
         log::trace!(
@@ -686,23 +830,31 @@ impl TermWindow {
             webgpu: None,
             window: None,
             window_background,
+            background_generation,
             config: config.clone(),
             config_overrides: wezterm_dynamic::Value::default(),
             palette: None,
             focused: None,
+            reported_focus: false,
+            focus_event_scheduled: false,
             mux_window_id,
             mux_window_id_for_subscriptions: Arc::new(Mutex::new(mux_window_id)),
             fonts: Rc::clone(&fontconfig),
             render_metrics,
             dimensions,
             window_state: WindowState::default(),
+            app_id: get_window_class(),
             resizes_pending: 0,
+            requested_inner_size: None,
+            animations_paused: false,
             is_repaint_pending: false,
             pending_scale_changes: LinkedList::new(),
             terminal_size,
             render_state,
             input_map: InputMap::new(&config),
-            leader_is_down: None,
+            leader_state: LeaderState::Up,
+            leader_timeout_override: None,
+            key_trace: false,
             dead_key_status: DeadKeyStatus::None,
             show_tab_bar,
             show_scroll_bar: config.enable_scroll_bar,
@@ -722,6 +874,10 @@ impl TermWindow {
             current_mouse_capture: None,
             last_mouse_click: None,
             current_highlight: None,
+            mouse_cursor_hidden: false,
+            mouse_cursor_shape: None,
+            window_title: String::new(),
+            user_vars: HashMap::new(),
             quad_generation: 0,
             shape_generation: 0,
             shape_cache: RefCell::new(LfuCache::new(
@@ -951,6 +1107,17 @@ impl TermWindow {
                 window_state,
                 live_resizing,
             } => {
+                if let Some((width, height)) = self.requested_inner_size.take() {
+                    if dimensions.pixel_width != width || dimensions.pixel_height != height {
+                        log::warn!(
+                            "requested an inner size of {width}x{height}, but the \
+                             window is {}x{}; the window environment may not \
+                             allow the window to be resized",
+                            dimensions.pixel_width,
+                            dimensions.pixel_height
+                        );
+                    }
+                }
                 self.resize(dimensions, window_state, window, live_resizing);
                 Ok(true)
             }
@@ -982,7 +1149,7 @@ impl TermWindow {
                 Ok(true)
             }
             WindowEvent::AdviseDeadKeyStatus(status) => {
-                if self.config.debug_key_events {
+                if self.debug_key_events() {
                     log::info!("DeadKeyStatus now: {:?}", status);
                 } else {
                     log::trace!("DeadKeyStatus now: {:?}", status);
@@ -1113,32 +1280,20 @@ impl TermWindow {
                 self.invalidate_modal();
                 window.invalidate();
             }
+            TermWindowNotif::Invalidate => {
+                self.quad_generation += 1;
+                self.invalidate_fancy_tab_bar();
+                self.invalidate_modal();
+                // Repaints are coalesced by the window, so multiple
+                // invalidations before the next paint are cheap
+                window.invalidate();
+            }
             TermWindowNotif::PerformAssignment {
                 pane_id,
                 assignment,
                 tx,
             } => {
-                let mux = Mux::get();
-                let result = || -> anyhow::Result<()> {
-                    // The CopyMode overlay doesn't exist in the mux, but aliases
-                    // itself with the overlaid pane's pane_id.
-                    // So we do a bit of fancy footwork here to resolve the overlay
-                    // and use that if it has the same pane_id, but otherwise fall
-                    // back to what we get from the mux.
-                    // <https://github.com/wez/wezterm/issues/3209>
-                    let active_pane = self
-                        .get_active_pane_or_overlay()
-                        .ok_or_else(|| anyhow!("there is no active pane!?"))?;
-                    let pane = if active_pane.pane_id() == pane_id {
-                        active_pane
-                    } else {
-                        mux.get_pane(pane_id)
-                            .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?
-                    };
-                    self.perform_key_assignment(&pane, &assignment)
-                        .context("perform_key_assignment")?;
-                    Ok(())
-                }();
+                let result = self.perform_assignment_for_pane(pane_id, &assignment);
                 window.invalidate();
                 if let Some(tx) = tx {
                     tx.try_send(result).ok();
@@ -1161,9 +1316,15 @@ impl TermWindow {
                 }
             }
             TermWindowNotif::GetDimensions(tx) => {
-                tx.try_send((self.dimensions, self.window_state))
-                    .map_err(chan_err)
-                    .context("send GetDimensions response")?;
+                tx.try_send((
+                    self.dimensions,
+                    self.window_state,
+                    self.terminal_size,
+                    self.render_metrics.cell_size,
+                    self.chrome_dimensions(),
+                ))
+                .map_err(chan_err)
+                .context("send GetDimensions response")?;
             }
             TermWindowNotif::GetEffectiveConfig(tx) => {
                 tx.try_send(self.config.clone())
@@ -1208,6 +1369,7 @@ impl TermWindow {
                     ..
                 } => {
                     self.update_title();
+                    self.schedule_pane_title_events();
                 }
                 MuxNotification::Alert {
                     alert: Alert::PaletteChanged,
@@ -1231,10 +1393,28 @@ impl TermWindow {
                     }
 
                     log::trace!("Ding! (this is the bell) in pane {}", pane_id);
-                    self.emit_window_event("bell", Some(pane_id));
 
+                    let now = Instant::now();
                     let mut per_pane = self.pane_state(pane_id);
-                    per_pane.bell_start.replace(Instant::now());
+                    per_pane.bell_start.replace(now);
+                    // Coalesce a rapid series of bells into a small number
+                    // of events so that a program spamming BEL doesn't
+                    // flood the lua event handlers
+                    let emit = per_pane
+                        .last_bell_event
+                        .map(|last| now.duration_since(last) >= BELL_EVENT_MIN_INTERVAL)
+                        .unwrap_or(true);
+                    if emit {
+                        per_pane.last_bell_event.replace(now);
+                    }
+                    drop(per_pane);
+
+                    if emit {
+                        // Dispatched directly, as the rate limit is per pane
+                        // and emit_window_event would drop this event if
+                        // another pane's bell event is already queued
+                        self.emit_pane_event("bell", pane_id);
+                    }
                     window.invalidate();
                 }
                 MuxNotification::Alert {
@@ -1309,6 +1489,9 @@ impl TermWindow {
             },
             TermWindowNotif::EmitStatusUpdate => {
                 self.emit_status_event();
+                // Titles derived from the foreground process can change
+                // without any alert, so we re-check them here too
+                self.schedule_pane_title_events();
             }
             TermWindowNotif::GetSelectionForPane { pane_id, tx } => {
                 let mux = Mux::get();
@@ -1342,8 +1525,12 @@ impl TermWindow {
                 window.invalidate();
             }
             TermWindowNotif::SetInnerSize { width, height } => {
+                self.requested_inner_size.replace((width, height));
                 self.set_inner_size(window, width, height);
             }
+            TermWindowNotif::GetClipboard { clipboard, tx } => {
+                self.get_clipboard_text(clipboard, tx);
+            }
         }
 
         Ok(())
@@ -1559,15 +1746,24 @@ impl TermWindow {
         };
         let pane = MuxPane(pane.pane_id());
         let name = name.to_string();
+        let focused = if name == "window-focus-changed" {
+            Some(self.focused.is_some())
+        } else {
+            None
+        };
 
         async fn do_event(
             lua: Option<Rc<mlua::Lua>>,
             name: String,
             window: GuiWin,
             pane: MuxPane,
+            focused: Option<bool>,
         ) -> anyhow::Result<()> {
             let again = if let Some(lua) = lua {
-                let args = lua.pack_multi((window.clone(), pane))?;
+                let args = match focused {
+                    Some(focused) => lua.pack_multi((window.clone(), pane, focused))?,
+                    None => lua.pack_multi((window.clone(), pane))?,
+                };
 
                 if let Err(err) = config::lua::emit_event(&lua, (name.clone(), args)).await {
                     log::error!("while processing {} event: {:#}", name, err);
@@ -1585,7 +1781,7 @@ impl TermWindow {
         }
 
         promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
-            do_event(lua, name, window, pane)
+            do_event(lua, name, window, pane, focused)
         }))
         .detach();
     }
@@ -1703,6 +1899,115 @@ impl TermWindow {
         self.palette.as_ref().unwrap()
     }
 
+    /// Run the visual bell animation for the specified pane, as though
+    /// the bell had been rung in it, but without the audible bell or
+    /// the `bell` event.
+    pub fn trigger_visual_bell(&mut self, pane_id: PaneId) {
+        let visual_bell = &self.config.visual_bell;
+        if visual_bell.fade_in_duration_ms == 0 && visual_bell.fade_out_duration_ms == 0 {
+            log::debug!(
+                "visual bell for pane {pane_id} suppressed as it is disabled in the config"
+            );
+            return;
+        }
+        self.pane_state(pane_id).bell_start.replace(Instant::now());
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Sets `key` to `value` in the per-window config overrides, and
+    /// reloads the config if that changed them
+    fn set_config_override(&mut self, key: &str, value: wezterm_dynamic::Value) {
+        let mut overrides = match &self.config_overrides {
+            wezterm_dynamic::Value::Object(obj) => obj.clone(),
+            _ => wezterm_dynamic::Object::default(),
+        };
+        overrides.insert(wezterm_dynamic::Value::String(key.to_string()), value);
+        let overrides = wezterm_dynamic::Value::Object(overrides);
+        if overrides != self.config_overrides {
+            self.config_overrides = overrides;
+            self.config_was_reloaded();
+        }
+    }
+
+    /// Apply `name` as the color scheme for this window by merging it
+    /// into the per-window config overrides.
+    pub fn set_color_scheme(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.config.color_scheme_by_name(name).is_none() {
+            anyhow::bail!("color scheme \"{}\" is not known", name);
+        }
+
+        self.set_config_override(
+            "color_scheme",
+            wezterm_dynamic::Value::String(name.to_string()),
+        );
+        Ok(())
+    }
+
+    /// Changes the primary font family for this window by way of its
+    /// config overrides.  The remaining fonts in the configured font
+    /// are retained as fallbacks.
+    /// `family` is expected to have been validated by
+    /// `scripting::resolve_font_family`.
+    pub fn set_font_family(&mut self, family: &str) {
+        let mut font = self.config.font.clone();
+        match font.font.first_mut() {
+            Some(first) => *first = FontAttributes::new(family),
+            None => font.font.push(FontAttributes::new(family)),
+        }
+
+        self.set_config_override("font", font.to_dynamic());
+    }
+
+    /// Returns the decorations that are in effect for this window
+    pub fn window_decorations(&self) -> WindowDecorations {
+        self.config.window_decorations
+    }
+
+    /// Changes the decorations of this window by way of its config
+    /// overrides.  If the windowing system cannot change the decorations
+    /// of an existing window, a warning is logged and the decorations
+    /// are left as they are.
+    /// Returns the decorations that are in effect after the change.
+    pub fn set_window_decorations(&mut self, decorations: WindowDecorations) -> WindowDecorations {
+        let can_change = self
+            .window
+            .as_ref()
+            .map(|window| window.can_change_decorations())
+            .unwrap_or(false);
+        if !can_change {
+            log::warn!(
+                "window decorations cannot be changed after the window \
+                 has been created on this system"
+            );
+            return self.config.window_decorations;
+        }
+
+        self.set_config_override("window_decorations", decorations.to_dynamic());
+        self.config.window_decorations
+    }
+
+    /// Replaces the window padding for the sides that are specified,
+    /// leaving the others unchanged, by way of the config overrides
+    pub fn set_window_padding(
+        &mut self,
+        left: Option<Dimension>,
+        right: Option<Dimension>,
+        top: Option<Dimension>,
+        bottom: Option<Dimension>,
+    ) {
+        let current = self.config.window_padding;
+        let padding = WindowPadding {
+            left: left.unwrap_or(current.left),
+            right: right.unwrap_or(current.right),
+            top: top.unwrap_or(current.top),
+            bottom: bottom.unwrap_or(current.bottom),
+        };
+
+        self.set_config_override("window_padding", padding.to_dynamic());
+    }
+
     pub fn config_was_reloaded(&mut self) {
         log::debug!(
             "config was reloaded, overrides: {:?}",
@@ -1772,7 +2077,7 @@ impl TermWindow {
         self.invalidate_fancy_tab_bar();
         self.invalidate_modal();
         self.input_map = InputMap::new(&config);
-        self.leader_is_down = None;
+        self.leader_state = LeaderState::Up;
         self.render_state.as_mut().map(|rs| rs.config_changed());
         let dimensions = self.dimensions;
 
@@ -1929,6 +2234,141 @@ impl TermWindow {
         .detach();
     }
 
+    /// Looks for panes in this window whose title has changed since
+    /// we last emitted the `pane-title-changed` event for them.
+    /// Unless `pane_title_changed_event_delay` is 0, the event is
+    /// deferred so that a burst of title changes is coalesced into
+    /// a single event carrying the most recent title.
+    fn schedule_pane_title_events(&mut self) {
+        let panes: Vec<Arc<dyn Pane>> = {
+            let mux = Mux::get();
+            let window = match mux.get_window(self.mux_window_id) {
+                Some(window) => window,
+                None => return,
+            };
+            window
+                .iter()
+                .flat_map(|tab| tab.iter_panes_ignoring_zoom())
+                .map(|pos| pos.pane)
+                .collect()
+        };
+        let delay = self.config.pane_title_changed_event_delay;
+
+        for pane in panes {
+            let pane_id = pane.pane_id();
+            let title = pane.get_title();
+            {
+                let mut state = self.pane_state(pane_id);
+                if state.title_event_scheduled {
+                    continue;
+                }
+                match &state.reported_title {
+                    // The first title we observe for a pane is its
+                    // initial title rather than a change
+                    None => {
+                        state.reported_title.replace(title);
+                        continue;
+                    }
+                    Some(reported) if *reported == title => continue,
+                    Some(_) => {}
+                }
+                if delay > 0 {
+                    state.title_event_scheduled = true;
+                }
+            }
+
+            if delay == 0 {
+                self.emit_pane_title_changed(pane_id);
+            } else if let Some(window) = self.window.clone() {
+                promise::spawn::spawn(async move {
+                    Timer::after(Duration::from_millis(delay)).await;
+                    window.notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                        term_window.emit_pane_title_changed(pane_id);
+                    })));
+                })
+                .detach();
+            }
+        }
+    }
+
+    /// Emits the `name` event for `pane_id`, passing the window and pane
+    /// to the handlers.  Unlike `emit_window_event`, which has a single
+    /// queued slot per event name, this dispatches immediately so that
+    /// an event for one pane cannot cause the event for another to be
+    /// dropped.  Callers are responsible for their own rate limiting.
+    pub fn emit_pane_event(&mut self, name: &str, pane_id: PaneId) {
+        self.emit_pane_event_with_args(name, pane_id, vec![]);
+    }
+
+    /// Like `emit_pane_event`, but passes `extra_args` to the handlers
+    /// after the window and pane
+    fn emit_pane_event_with_args(&mut self, name: &str, pane_id: PaneId, extra_args: Vec<String>) {
+        if Mux::get().get_pane(pane_id).is_none() {
+            return;
+        }
+        let window = GuiWin::new(self);
+        let pane = MuxPane(pane_id);
+        let name = name.to_string();
+
+        async fn do_event(
+            lua: Option<Rc<mlua::Lua>>,
+            name: String,
+            window: GuiWin,
+            pane: MuxPane,
+            extra_args: Vec<String>,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let extra_args: mlua::Variadic<String> = extra_args.into_iter().collect();
+                let args = lua.pack_multi((window, pane, extra_args))?;
+                if let Err(err) = config::lua::emit_event(&lua, (name.clone(), args)).await {
+                    log::error!("while processing {} event: {:#}", name, err);
+                }
+            }
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, name, window, pane, extra_args)
+        }))
+        .detach();
+    }
+
+    fn emit_pane_title_changed(&mut self, pane_id: PaneId) {
+        let mux = Mux::get();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let title = pane.get_title();
+        {
+            let mut state = self.pane_state(pane_id);
+            state.title_event_scheduled = false;
+            if state.reported_title.as_ref() == Some(&title) {
+                return;
+            }
+            state.reported_title.replace(title.clone());
+        }
+
+        self.emit_pane_event_with_args("pane-title-changed", pane_id, vec![title]);
+    }
+
+    pub fn get_window_title(&self) -> &str {
+        &self.window_title
+    }
+
+    pub fn user_vars(&mut self) -> &mut HashMap<String, String> {
+        &mut self.user_vars
+    }
+
+    /// Pauses or resumes playback of the animated images that are
+    /// rendered in the panes of this window.
+    pub fn set_animations_paused(&mut self, paused: bool) {
+        self.animations_paused = paused;
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
     /// Called by window:set_right_status after the status has
     /// been updated; let's update the bar
     pub fn update_title_post_status(&mut self) {
@@ -2050,6 +2490,7 @@ impl TermWindow {
 
         if let Some(window) = self.window.as_ref() {
             window.set_title(&title);
+            self.window_title = title;
 
             let show_tab_bar = if num_tabs == 1 {
                 self.config.enable_tab_bar && !self.config.hide_tab_bar_if_only_one_tab
@@ -2153,7 +2594,7 @@ impl TermWindow {
         Ok(())
     }
 
-    fn activate_tab(&mut self, tab_idx: isize) -> anyhow::Result<()> {
+    pub fn activate_tab(&mut self, tab_idx: isize) -> anyhow::Result<()> {
         let mux = Mux::get();
         let mut window = mux
             .get_window_mut(self.mux_window_id)
@@ -2498,6 +2939,34 @@ impl TermWindow {
         self.move_tab(tab)
     }
 
+    /// Performs `assignment` against the pane with the specified id.
+    /// The caller is responsible for invalidating the window.
+    pub fn perform_assignment_for_pane(
+        &mut self,
+        pane_id: PaneId,
+        assignment: &KeyAssignment,
+    ) -> anyhow::Result<()> {
+        // The CopyMode overlay doesn't exist in the mux, but aliases
+        // itself with the overlaid pane's pane_id.
+        // So we do a bit of fancy footwork here to resolve the overlay
+        // and use that if it has the same pane_id, but otherwise fall
+        // back to what we get from the mux.
+        // <https://github.com/wez/wezterm/issues/3209>
+        let active_pane = self
+            .get_active_pane_or_overlay()
+            .ok_or_else(|| anyhow!("there is no active pane!?"))?;
+        let pane = if active_pane.pane_id() == pane_id {
+            active_pane
+        } else {
+            Mux::get()
+                .get_pane(pane_id)
+                .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?
+        };
+        self.perform_key_assignment(&pane, assignment)
+            .context("perform_key_assignment")?;
+        Ok(())
+    }
+
     pub fn perform_key_assignment(
         &mut self,
         pane: &Arc<dyn Pane>,
@@ -2822,36 +3291,7 @@ impl TermWindow {
             }
             ActivateCopyMode => {
                 if let Some(pane) = self.get_active_pane_or_overlay() {
-                    let mut replace_current = false;
-                    if let Some(existing) = pane.downcast_ref::<CopyOverlay>() {
-                        let mut params = existing.get_params();
-                        params.editing_search = false;
-                        existing.apply_params(params);
-                        replace_current = true;
-                    } else {
-                        let copy = CopyOverlay::with_pane(
-                            self,
-                            &pane,
-                            CopyModeParams {
-                                pattern: MuxPattern::default(),
-                                editing_search: false,
-                            },
-                        )?;
-                        self.assign_overlay_for_pane(pane.pane_id(), copy);
-                    }
-                    self.pane_state(pane.pane_id())
-                        .overlay
-                        .as_mut()
-                        .map(|overlay| {
-                            overlay.key_table_state.activate(KeyTableArgs {
-                                name: "copy_mode",
-                                timeout_milliseconds: None,
-                                replace_current,
-                                one_shot: false,
-                                until_unknown: false,
-                                prevent_fallback: false,
-                            });
-                        });
+                    self.activate_copy_mode(&pane)?;
                 }
             }
             AdjustPaneSize(direction, amount) => {
@@ -3077,51 +3517,57 @@ impl TermWindow {
 
     fn do_open_link_at_mouse_cursor(&self, pane: &Arc<dyn Pane>) {
         // They clicked on a link, so let's open it!
+        if let Some(link) = self.current_highlight.as_ref().cloned() {
+            self.open_uri(pane, link.uri().to_string());
+        }
+    }
+
+    /// Open `uri` using the `open-uri` event handler, if one is
+    /// defined, falling back to the system URL handler.
+    pub fn open_uri(&self, pane: &Arc<dyn Pane>, uri: String) {
         // We need to ensure that we spawn the `open` call outside of the context
         // of our window loop; on Windows it can cause a panic due to
         // triggering our WndProc recursively.
         // We get that assurance for free as part of the async dispatch that we
         // perform below; here we allow the user to define an `open-uri` event
         // handler that can bypass the normal `open_url` functionality.
-        if let Some(link) = self.current_highlight.as_ref().cloned() {
-            let window = GuiWin::new(self);
-            let pane = MuxPane(pane.pane_id());
-
-            async fn open_uri(
-                lua: Option<Rc<mlua::Lua>>,
-                window: GuiWin,
-                pane: MuxPane,
-                link: String,
-            ) -> anyhow::Result<()> {
-                let default_click = match lua {
-                    Some(lua) => {
-                        let args = lua.pack_multi((window, pane, link.clone()))?;
-                        config::lua::emit_event(&lua, ("open-uri".to_string(), args))
-                            .await
-                            .map_err(|e| {
-                                log::error!("while processing open-uri event: {:#}", e);
-                                e
-                            })?
-                    }
-                    None => true,
-                };
-                if default_click {
-                    log::info!("clicking {}", link);
-                    wezterm_open_url::open_url(&link);
+        let window = GuiWin::new(self);
+        let pane = MuxPane(pane.pane_id());
+
+        async fn open_uri(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: MuxPane,
+            link: String,
+        ) -> anyhow::Result<()> {
+            let default_click = match lua {
+                Some(lua) => {
+                    let args = lua.pack_multi((window, pane, link.clone()))?;
+                    config::lua::emit_event(&lua, ("open-uri".to_string(), args))
+                        .await
+                        .map_err(|e| {
+                            log::error!("while processing open-uri event: {:#}", e);
+                            e
+                        })?
                 }
-                Ok(())
+                None => true,
+            };
+            if default_click {
+                log::info!("clicking {}", link);
+                wezterm_open_url::open_url(&link);
             }
-
-            promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
-                open_uri(lua, window, pane, link.uri().to_string())
-            }))
-            .detach();
+            Ok(())
         }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            open_uri(lua, window, pane, uri)
+        }))
+        .detach();
     }
+
     fn close_current_pane(&mut self, confirm: bool) {
-        let mux_window_id = self.mux_window_id;
         let mux = Mux::get();
-        let tab = match mux.get_active_tab_for_window(mux_window_id) {
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
             Some(tab) => tab,
             None => return,
         };
@@ -3130,37 +3576,69 @@ impl TermWindow {
             None => return,
         };
 
+        self.close_pane(&pane, confirm);
+    }
+
+    /// Closes the specified pane, which must belong to this window.
+    /// If `confirm` is true and the pane cannot be closed without
+    /// prompting, its tab is activated and the confirmation prompt
+    /// is shown.
+    /// Returns true if the pane was closed, or false if closing it
+    /// is pending the outcome of the prompt.
+    pub fn close_pane(&mut self, pane: &Arc<dyn Pane>, confirm: bool) -> bool {
+        let mux_window_id = self.mux_window_id;
+        let mux = Mux::get();
+
         let pane_id = pane.pane_id();
         if confirm && !pane.can_close_without_prompting(CloseReason::Pane) {
+            let tab_idx =
+                mux.resolve_pane_id(pane_id)
+                    .and_then(|(_domain_id, _window_id, tab_id)| {
+                        mux.get_window(mux_window_id)?.idx_by_id(tab_id)
+                    });
+            if let Some(tab_idx) = tab_idx {
+                if self.activate_tab(tab_idx as isize).is_err() {
+                    return false;
+                }
+            }
+
             let window = self.window.clone().unwrap();
-            let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+            let (overlay, future) = start_overlay_pane(self, pane, move |pane_id, term| {
                 confirm_close_pane(pane_id, term, mux_window_id, window)
             });
             self.assign_overlay_for_pane(pane_id, overlay);
             promise::spawn::spawn(future).detach();
+            false
         } else {
             mux.remove_pane(pane_id);
+            true
         }
     }
 
-    fn close_specific_tab(&mut self, tab_idx: usize, confirm: bool) {
+    /// Closes the tab at `tab_idx` in this window.
+    /// If `confirm` is true and the tab cannot be closed without
+    /// prompting, the tab is activated and the confirmation prompt
+    /// is shown.
+    /// Returns true if the tab was closed, or false if there is no
+    /// such tab or closing it is pending the outcome of the prompt.
+    pub fn close_specific_tab(&mut self, tab_idx: usize, confirm: bool) -> bool {
         let mux = Mux::get();
         let mux_window_id = self.mux_window_id;
         let mux_window = match mux.get_window(mux_window_id) {
             Some(w) => w,
-            None => return,
+            None => return false,
         };
 
         let tab = match mux_window.get_by_idx(tab_idx) {
             Some(tab) => Arc::clone(tab),
-            None => return,
+            None => return false,
         };
         drop(mux_window);
 
         let tab_id = tab.tab_id();
         if confirm && !tab.can_close_without_prompting(CloseReason::Tab) {
             if self.activate_tab(tab_idx as isize).is_err() {
-                return;
+                return false;
             }
 
             let window = self.window.clone().unwrap();
@@ -3169,8 +3647,10 @@ impl TermWindow {
             });
             self.assign_overlay(tab_id, overlay);
             promise::spawn::spawn(future).detach();
+            false
         } else {
             mux.remove_tab(tab_id);
+            true
         }
     }
 
@@ -3289,6 +3769,33 @@ impl TermWindow {
         self.pane_state(pane.pane_id()).viewport = None;
     }
 
+    /// Adjusts the viewport of the specified pane, clamping to the
+    /// available scrollback.  This is a no-op for a pane that is
+    /// in the alternate screen, as there is no scrollback there.
+    pub fn scroll_pane(&mut self, pane_id: PaneId, target: PaneScrollTarget) -> anyhow::Result<()> {
+        let mux = Mux::get();
+        let pane = mux
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?;
+        if pane.is_alt_screen_active() {
+            return Ok(());
+        }
+        match target {
+            PaneScrollTarget::Lines(amount) => self.scroll_by_line(amount, &pane)?,
+            PaneScrollTarget::Top => self.scroll_to_top(&pane),
+            PaneScrollTarget::Bottom => self.scroll_to_bottom(&pane),
+            PaneScrollTarget::Row(row) => {
+                let dims = pane.get_dimensions();
+                self.set_viewport(pane_id, Some(row), dims);
+            }
+            PaneScrollTarget::Prompt(amount) => self.scroll_to_prompt(amount, &pane)?,
+        }
+        if let Some(win) = self.window.as_ref() {
+            win.invalidate();
+        }
+        Ok(())
+    }
+
     fn get_active_pane_no_overlay(&self) -> Option<Arc<dyn Pane>> {
         let mux = Mux::get();
         mux.get_active_tab_for_window(self.mux_window_id)
@@ -3494,6 +4001,94 @@ impl TermWindow {
         self.update_title();
     }
 
+    /// Activates copy mode for the pane, which may be an existing overlay.
+    /// If the pane is already in copy mode, its search editing state is
+    /// reset and the copy_mode key table is re-activated.
+    fn activate_copy_mode(&mut self, pane: &Arc<dyn Pane>) -> anyhow::Result<()> {
+        let mut replace_current = false;
+        if let Some(existing) = pane.downcast_ref::<CopyOverlay>() {
+            let mut params = existing.get_params();
+            params.editing_search = false;
+            existing.apply_params(params);
+            replace_current = true;
+        } else {
+            let copy = CopyOverlay::with_pane(
+                self,
+                pane,
+                CopyModeParams {
+                    pattern: MuxPattern::default(),
+                    editing_search: false,
+                },
+            )?;
+            self.assign_overlay_for_pane(pane.pane_id(), copy);
+        }
+        self.pane_state(pane.pane_id())
+            .overlay
+            .as_mut()
+            .map(|overlay| {
+                overlay.key_table_state.activate(KeyTableArgs {
+                    name: "copy_mode",
+                    timeout_milliseconds: None,
+                    replace_current,
+                    one_shot: false,
+                    until_unknown: false,
+                    prevent_fallback: false,
+                });
+            });
+        Ok(())
+    }
+
+    /// Activates copy mode for the pane with the specified id
+    pub fn activate_copy_mode_for_pane(&mut self, pane_id: PaneId) -> anyhow::Result<()> {
+        let overlay = self
+            .pane_state(pane_id)
+            .overlay
+            .as_ref()
+            .map(|overlay| overlay.pane.clone());
+        let pane = match overlay {
+            Some(overlay) => overlay,
+            None => Mux::get()
+                .get_pane(pane_id)
+                .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?,
+        };
+        self.activate_copy_mode(&pane)
+    }
+
+    /// Returns the copy mode overlay for the pane, if it is in copy mode
+    fn copy_mode_overlay(&self, pane_id: PaneId) -> Option<Arc<dyn Pane>> {
+        self.pane_state(pane_id)
+            .overlay
+            .as_ref()
+            .map(|overlay| overlay.pane.clone())
+            .filter(|pane| pane.downcast_ref::<CopyOverlay>().is_some())
+    }
+
+    /// Returns true if the pane with the specified id is in copy mode
+    pub fn is_in_copy_mode(&self, pane_id: PaneId) -> bool {
+        self.copy_mode_overlay(pane_id).is_some()
+    }
+
+    /// Applies a copy mode assignment to the pane with the specified id.
+    /// Returns an error if that pane is not in copy mode.
+    pub fn perform_copy_mode_assignment(
+        &self,
+        pane_id: PaneId,
+        assignment: CopyModeAssignment,
+    ) -> anyhow::Result<()> {
+        let overlay = self
+            .copy_mode_overlay(pane_id)
+            .ok_or_else(|| anyhow!("pane id {} is not in copy mode", pane_id))?;
+        overlay.perform_assignment(&KeyAssignment::CopyMode(assignment));
+        Ok(())
+    }
+
+    /// Leaves copy mode for the pane with the specified id.
+    /// Returns false if that pane was not in copy mode.
+    pub fn close_copy_mode(&self, pane_id: PaneId) -> bool {
+        self.perform_copy_mode_assignment(pane_id, CopyModeAssignment::Close)
+            .is_ok()
+    }
+
     pub fn assign_overlay(&mut self, tab_id: TabId, overlay: Arc<dyn Pane>) {
         self.cancel_overlay_for_tab(tab_id, None);
         self.tab_state(tab_id).overlay.replace(OverlayState {
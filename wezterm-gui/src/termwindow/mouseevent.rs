@@ -58,6 +58,30 @@ impl super::TermWindow {
         }
     }
 
+    /// Sets the mouse cursor, taking into account the visibility
+    /// and shape overrides that may have been set via lua
+    fn set_mouse_cursor(&self, context: &dyn WindowOps, cursor: Option<MouseCursor>) {
+        if self.mouse_cursor_hidden {
+            context.set_cursor(None);
+        } else {
+            context.set_cursor(cursor.map(|cursor| self.mouse_cursor_shape.unwrap_or(cursor)));
+        }
+    }
+
+    pub fn set_mouse_cursor_visible(&mut self, visible: bool) {
+        self.mouse_cursor_hidden = !visible;
+        if let Some(window) = self.window.as_ref() {
+            self.set_mouse_cursor(window, Some(MouseCursor::Arrow));
+        }
+    }
+
+    pub fn set_mouse_cursor_shape(&mut self, shape: Option<MouseCursor>) {
+        self.mouse_cursor_shape = shape;
+        if let Some(window) = self.window.as_ref() {
+            self.set_mouse_cursor(window, Some(MouseCursor::Arrow));
+        }
+    }
+
     pub fn mouse_event_impl(&mut self, event: MouseEvent, context: &dyn WindowOps) {
         log::trace!("{:?}", event);
         let pane = match self.get_active_pane_or_overlay() {
@@ -247,7 +271,7 @@ impl super::TermWindow {
     pub fn mouse_leave_impl(&mut self, context: &dyn WindowOps) {
         self.current_mouse_event = None;
         self.update_title();
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
         context.invalidate();
     }
 
@@ -398,7 +422,7 @@ impl super::TermWindow {
             }
             _ => {}
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
     }
 
     fn do_new_tab_button_click(&mut self, button: MousePress) {
@@ -560,7 +584,7 @@ impl super::TermWindow {
             }
             _ => {}
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
     }
 
     pub fn mouse_event_above_scroll_thumb(
@@ -585,7 +609,7 @@ impl super::TermWindow {
             );
             context.invalidate();
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
     }
 
     pub fn mouse_event_below_scroll_thumb(
@@ -610,7 +634,7 @@ impl super::TermWindow {
             );
             context.invalidate();
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
     }
 
     pub fn mouse_event_scroll_thumb(
@@ -625,7 +649,7 @@ impl super::TermWindow {
             // self.scroll_drag_start = Some(from_top);
             self.dragging = Some((item, event));
         }
-        context.set_cursor(Some(MouseCursor::Arrow));
+        self.set_mouse_cursor(context, Some(MouseCursor::Arrow));
     }
 
     pub fn mouse_event_split(
@@ -635,10 +659,13 @@ impl super::TermWindow {
         event: MouseEvent,
         context: &dyn WindowOps,
     ) {
-        context.set_cursor(Some(match &split.direction {
-            SplitDirection::Horizontal => MouseCursor::SizeLeftRight,
-            SplitDirection::Vertical => MouseCursor::SizeUpDown,
-        }));
+        self.set_mouse_cursor(
+            context,
+            Some(match &split.direction {
+                SplitDirection::Horizontal => MouseCursor::SizeLeftRight,
+                SplitDirection::Vertical => MouseCursor::SizeUpDown,
+            }),
+        );
 
         if event.kind == WMEK::Press(MousePress::Left) {
             self.dragging.replace((item, event));
@@ -833,15 +860,18 @@ impl super::TermWindow {
             || event.coords.y < 0
             || event.coords.y as usize > self.dimensions.pixel_height;
 
-        context.set_cursor(Some(if self.current_highlight.is_some() {
-            // When hovering over a hyperlink, show an appropriate
-            // mouse cursor to give the cue that it is clickable
-            MouseCursor::Hand
-        } else if pane.is_mouse_grabbed() || outside_window {
-            MouseCursor::Arrow
-        } else {
-            MouseCursor::Text
-        }));
+        self.set_mouse_cursor(
+            context,
+            Some(if self.current_highlight.is_some() {
+                // When hovering over a hyperlink, show an appropriate
+                // mouse cursor to give the cue that it is clickable
+                MouseCursor::Hand
+            } else if pane.is_mouse_grabbed() || outside_window {
+                MouseCursor::Arrow
+            } else {
+                MouseCursor::Text
+            }),
+        );
 
         let event_trigger_type = match &event.kind {
             WMEK::Press(press) => {
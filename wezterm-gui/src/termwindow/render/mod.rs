@@ -7,7 +7,9 @@ use crate::quad::{
 };
 use crate::shapecache::*;
 use crate::termwindow::render::paint::AllowImage;
-use crate::termwindow::{BorrowedShapeCacheKey, RenderState, ShapedInfo, TermWindowNotif};
+use crate::termwindow::{
+    BorrowedShapeCacheKey, ChromeDimensions, RenderState, ShapedInfo, TermWindowNotif,
+};
 use crate::utilsprites::RenderMetrics;
 use ::window::bitmaps::{TextureCoord, TextureRect, TextureSize};
 use ::window::{DeadKeyStatus, PointF, RectF, SizeF, WindowOps};
@@ -363,6 +365,39 @@ impl crate::TermWindow {
         (padding_left, padding_top)
     }
 
+    /// Returns the live, dpi-scaled, sizes of the tab bar and the
+    /// padding around the terminal content
+    pub fn chrome_dimensions(&self) -> ChromeDimensions {
+        let (padding_left, padding_top) = self.padding_left_top();
+        let v_context = DimensionContext {
+            dpi: self.dimensions.dpi as f32,
+            pixel_max: self.terminal_size.pixel_height as f32,
+            pixel_cell: self.render_metrics.cell_size.height as f32,
+        };
+        let padding_bottom = self
+            .config
+            .window_padding
+            .bottom
+            .evaluate_as_pixels(v_context);
+        let tab_bar_height = if self.show_tab_bar {
+            self.tab_bar_pixel_height().unwrap_or_else(|err| {
+                log::error!("tab_bar_pixel_height: {err:#}");
+                0.
+            })
+        } else {
+            0.
+        };
+
+        ChromeDimensions {
+            tab_bar_height: tab_bar_height as usize,
+            tab_bar_at_bottom: self.config.tab_bar_at_bottom,
+            padding_left: padding_left as usize,
+            padding_right: self.effective_right_padding(&self.config),
+            padding_top: padding_top as usize,
+            padding_bottom: padding_bottom as usize,
+        }
+    }
+
     fn resolve_lock_glyph(
         &self,
         style: &TextStyle,
@@ -436,7 +471,12 @@ impl crate::TermWindow {
         let (sprite, next_due, _load_state) = gl_state
             .glyph_cache
             .borrow_mut()
-            .cached_image(image.image_data(), Some(padding), self.allow_images)
+            .cached_image(
+                image.image_data(),
+                Some(padding),
+                self.allow_images,
+                self.animations_paused,
+            )
             .context("cached_image")?;
         self.update_next_frame_time(next_due);
         let width = sprite.coords.size.width;
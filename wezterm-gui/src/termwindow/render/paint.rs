@@ -21,6 +21,10 @@ impl crate::TermWindow {
         *self.has_animation.borrow_mut() = None;
         // Start with the assumption that we should allow images to render
         self.allow_images = AllowImage::Yes;
+        if let Some(render_state) = self.render_state.as_ref() {
+            render_state.glyph_cache.borrow_mut().advance_frame();
+        }
+        self.reload_background_if_downscaled();
 
         let start = Instant::now();
 
@@ -115,6 +119,10 @@ impl crate::TermWindow {
         metrics::histogram!("gui.paint.impl").record(self.last_frame_duration);
         metrics::histogram!("gui.paint.impl.rate").record(1.);
 
+        // Any change to the selection causes a repaint, so this is
+        // a convenient place to notice that it has changed
+        self.schedule_selection_changed_events();
+
         // If self.has_animation is some, then the last render detected
         // image attachments with multiple frames, so we also need to
         // invalidate the viewport when the next frame is due
@@ -13,6 +13,7 @@ use std::rc::Rc;
 use std::time::Instant;
 use termwiz::cell::{unicode_column_width, Blink};
 use termwiz::color::LinearRgba;
+use termwiz::image::ImageLayer;
 use termwiz::surface::CursorShape;
 use wezterm_bidi::Direction;
 use wezterm_term::color::ColorAttribute;
@@ -472,7 +473,7 @@ impl crate::TermWindow {
 
                 for glyph_idx in 0..info.pos.num_cells as usize {
                     for img in &images {
-                        if img.z_index() < 0 {
+                        if img.layer() != ImageLayer::OverText {
                             self.populate_image_quad(
                                 &img,
                                 gl_state,
@@ -667,7 +668,7 @@ impl crate::TermWindow {
 
                 for glyph_idx in 0..info.pos.num_cells as usize {
                     for img in &images {
-                        if img.z_index() >= 0 {
+                        if img.layer() == ImageLayer::OverText {
                             overlay_images.push((
                                 visual_cell_idx + glyph_idx,
                                 img.clone(),
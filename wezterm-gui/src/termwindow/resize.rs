@@ -1,4 +1,5 @@
 use crate::resize_increment_calculator::ResizeIncrementCalculator;
+use crate::termwindow::background::{background_needs_reload, reload_background_image};
 use crate::utilsprites::RenderMetrics;
 use ::window::{Dimensions, ResizeIncrement, Window, WindowOps, WindowState};
 use config::{ConfigHandle, DimensionContext};
@@ -144,6 +145,17 @@ impl super::TermWindow {
         self.dimensions = *dimensions;
         self.quad_generation += 1;
 
+        if background_needs_reload(&self.window_background, dimensions) {
+            // The window has grown beyond the size for which the
+            // background images were downscaled, so load them again
+            self.window_background = reload_background_image(
+                &self.config,
+                &self.window_background,
+                dimensions,
+                &self.render_metrics,
+            );
+        }
+
         if scale_changed_cells.is_some() && !self.window_state.can_resize() {
             log::warn!(
                 "cannot resize window to match {:?} because window_state is {:?}",
@@ -472,6 +484,16 @@ impl super::TermWindow {
         self.apply_pending_scale_changes();
     }
 
+    pub fn get_font_scale(&self) -> f64 {
+        self.fonts.get_font_scale()
+    }
+
+    pub fn set_font_scale(&mut self, font_scale: f64) {
+        self.pending_scale_changes
+            .push_back(ScaleChange::Absolute(font_scale));
+        self.apply_pending_scale_changes();
+    }
+
     pub fn set_window_size(&mut self, size: TerminalSize, window: &Window) -> anyhow::Result<()> {
         let config = &self.config;
         let fontconfig = Rc::new(FontConfiguration::new(
@@ -1,6 +1,8 @@
 use crate::selection::{Selection, SelectionCoordinate, SelectionMode, SelectionRange, SelectionX};
+use crate::termwindow::TermWindowNotif;
 use ::window::WindowOps;
 use mux::pane::{Pane, PaneId};
+use smol::Timer;
 use std::cell::RefMut;
 use std::sync::Arc;
 use termwiz::surface::Line;
@@ -11,6 +13,49 @@ impl super::TermWindow {
         RefMut::map(self.pane_state(pane_id), |state| &mut state.selection)
     }
 
+    /// Looks for panes whose selection has changed since we last
+    /// emitted the `selection-changed` event for them, and arranges
+    /// to emit the event after a short delay.  Deferring the event
+    /// coalesces a rapid series of changes, such as those made while
+    /// dragging out a selection, into a small number of events.
+    pub fn schedule_selection_changed_events(&mut self) {
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+        for (pane_id, state) in self.pane_state.borrow_mut().iter_mut() {
+            if state.selection_event_scheduled || state.selection.range == state.reported_selection
+            {
+                continue;
+            }
+            state.selection_event_scheduled = true;
+
+            let pane_id = *pane_id;
+            let window = window.clone();
+            promise::spawn::spawn(async move {
+                Timer::after(super::SELECTION_EVENT_DELAY).await;
+                window.notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    term_window.emit_selection_changed(pane_id);
+                })));
+            })
+            .detach();
+        }
+    }
+
+    fn emit_selection_changed(&mut self, pane_id: PaneId) {
+        {
+            let mut state = self.pane_state(pane_id);
+            state.selection_event_scheduled = false;
+            if state.selection.range == state.reported_selection {
+                return;
+            }
+            state.reported_selection = state.selection.range;
+        }
+        // Dispatched directly, rather than via emit_window_event, as that
+        // would drop this event if another pane's event is already queued
+        self.emit_pane_event("selection-changed", pane_id);
+    }
+
     /// Returns the selection region as a series of Line
     pub fn selection_lines(&self, pane: &Arc<dyn Pane>) -> Vec<Line> {
         let mut result = vec![];
@@ -240,6 +285,44 @@ impl super::TermWindow {
         self.window.as_ref().unwrap().invalidate();
     }
 
+    /// Replaces the selection in `pane` with the cells from `start` to
+    /// `end` inclusive, expanded to whole words, lines or semantic
+    /// zones according to `mode`.  If `range` is None, the selection
+    /// is cleared instead.
+    pub fn set_selection_for_pane(
+        &mut self,
+        pane: &Arc<dyn Pane>,
+        range: Option<(SelectionCoordinate, SelectionCoordinate)>,
+        mode: SelectionMode,
+    ) {
+        let pane_id = pane.pane_id();
+        match range {
+            None => self.selection(pane_id).clear(),
+            Some((start, end)) => {
+                let range = match mode {
+                    SelectionMode::Cell | SelectionMode::Block => {
+                        SelectionRange::start(start).extend(end)
+                    }
+                    SelectionMode::Word => SelectionRange::word_around(start, &**pane)
+                        .extend_with(SelectionRange::word_around(end, &**pane)),
+                    SelectionMode::Line => SelectionRange::line_around(start, &**pane)
+                        .extend_with(SelectionRange::line_around(end, &**pane)),
+                    SelectionMode::SemanticZone => SelectionRange::zone_around(start, &**pane)
+                        .extend_with(SelectionRange::zone_around(end, &**pane)),
+                };
+                let mut selection = self.selection(pane_id);
+                selection.origin = Some(range.start);
+                selection.range = Some(range);
+                selection.rectangular = mode == SelectionMode::Block;
+            }
+        }
+
+        self.selection(pane_id).seqno = pane.get_current_seqno();
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
     pub fn select_text_at_mouse_cursor(&mut self, mode: SelectionMode, pane: &Arc<dyn Pane>) {
         let (x, y) = match self.pane_state(pane.pane_id()).mouse_terminal_coords {
             Some(coords) => (coords.0.column, coords.1),
@@ -320,6 +320,8 @@ pub trait WindowOps {
     fn set_clipboard(&self, clipboard: Clipboard, text: String);
 
     /// Set window level. Depending on the environment and user preferences
+    /// this may have no effect; the window state reported in subsequent
+    /// resize events reflects the level that was actually applied.
     fn set_window_level(&self, _level: WindowLevel) {}
 
     /// Set the icon for the window.
@@ -335,6 +337,27 @@ pub trait WindowOps {
 
     fn config_did_change(&self, _config: &config::ConfigHandle) {}
 
+    /// Returns true if this backend applies a change to the
+    /// window_decorations config to an existing window when
+    /// config_did_change is called.  Some backends can only
+    /// set up decorations when the window is created.
+    fn can_change_decorations(&self) -> bool {
+        false
+    }
+
+    /// Returns true if this backend can change the application
+    /// identifier (WM_CLASS on X11, app_id on Wayland) of an
+    /// existing window via set_app_id.  Other backends can only
+    /// set it when the window is created.
+    fn can_change_app_id(&self) -> bool {
+        false
+    }
+
+    /// Change the application identifier that the window manager
+    /// or compositor uses to match the window against its rules.
+    /// This has no effect unless can_change_app_id returns true.
+    fn set_app_id(&self, _app_id: &str) {}
+
     /// Configure the Window so that the desktop environment
     /// will constrain resizes so that they are multiples of
     /// the x and y values specified.
@@ -860,6 +860,10 @@ impl WindowOps for Window {
         });
     }
 
+    fn can_change_decorations(&self) -> bool {
+        true
+    }
+
     fn get_os_parameters(
         &self,
         _config: &ConfigHandle,
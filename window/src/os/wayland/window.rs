@@ -13,6 +13,7 @@ use std::time::{Duration, Instant};
 use anyhow::{anyhow, bail};
 use async_io::Timer;
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::ConfigHandle;
 use promise::{Future, Promise};
 use raw_window_handle::{
@@ -411,6 +412,26 @@ impl WindowOps for WaylandWindow {
         });
     }
 
+    fn can_change_app_id(&self) -> bool {
+        true
+    }
+
+    fn set_app_id(&self, app_id: &str) {
+        let app_id = app_id.to_owned();
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            if let Some(window) = inner.window.as_ref() {
+                window.set_app_id(app_id);
+            }
+            Ok(())
+        });
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        // The compositor is solely responsible for stacking order;
+        // there is no protocol for clients to request a window level
+        log::warn!("set_window_level({level:?}) is not supported on Wayland");
+    }
+
     fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         let mut promise = Promise::new();
         let future = promise.get_future().unwrap();
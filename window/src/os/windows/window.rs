@@ -9,6 +9,7 @@ use crate::{
 };
 use anyhow::{bail, Context};
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::{ConfigHandle, ImePreeditRendering, SystemBackdrop};
 use lazy_static::lazy_static;
 use promise::Future;
@@ -678,6 +679,35 @@ impl WindowInner {
         self.apply_decoration();
     }
 
+    fn set_window_level(&mut self, level: WindowLevel) {
+        let insert_after = match level {
+            WindowLevel::AlwaysOnTop => HWND_TOPMOST,
+            WindowLevel::Normal => HWND_NOTOPMOST,
+            WindowLevel::AlwaysOnBottom => HWND_BOTTOM,
+        };
+        unsafe {
+            SetWindowPos(
+                self.hwnd.0,
+                insert_after,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            );
+        }
+
+        // Changing the z-order doesn't resize the window, so we need
+        // to explicitly report the new state
+        if let Some(dimensions) = self.last_size {
+            self.events.dispatch(WindowEvent::Resized {
+                dimensions,
+                window_state: get_window_state(self.hwnd.0),
+                live_resizing: self.in_size_move,
+            });
+        }
+    }
+
     fn toggle_fullscreen(&mut self) {
         unsafe {
             let hwnd = self.hwnd.0;
@@ -874,6 +904,13 @@ impl WindowOps for Window {
         });
     }
 
+    fn set_window_level(&self, level: WindowLevel) {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        });
+    }
+
     fn config_did_change(&self, config: &ConfigHandle) {
         let config = config.clone();
         Connection::with_window_inner(self.0, move |inner| {
@@ -882,6 +919,10 @@ impl WindowOps for Window {
         });
     }
 
+    fn can_change_decorations(&self) -> bool {
+        true
+    }
+
     fn set_text_cursor_position(&self, cursor: Rect) {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_text_cursor_position(cursor);
@@ -1147,7 +1188,7 @@ unsafe fn wm_nccalcsize(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM)
         requested_client_rect.right -= frame_x + padding;
         requested_client_rect.left += frame_x + padding;
 
-        let is_maximized = get_window_state(hwnd) == WindowState::MAXIMIZED;
+        let is_maximized = get_window_state(hwnd).contains(WindowState::MAXIMIZED);
 
         // Handle bugged top window border on Windows 10
         if *IS_WIN10 {
@@ -1214,7 +1255,7 @@ unsafe fn wm_nchittest(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
     let coords = mouse_coords(lparam);
     let screen_point = ScreenPoint::new(coords.x, coords.y);
     let cursor_point = screen_to_client(hwnd, screen_point);
-    let is_maximized = get_window_state(hwnd) == WindowState::MAXIMIZED;
+    let is_maximized = get_window_state(hwnd).contains(WindowState::MAXIMIZED);
 
     // check if mouse is in any of the resize areas (HTTOP, HTBOTTOM, etc)
 
@@ -1279,7 +1320,7 @@ fn get_window_state(hwnd: HWND) -> WindowState {
             0
         };
 
-    match placement {
+    let mut state = match placement {
         SW_SHOWMAXIMIZED => WindowState::MAXIMIZED,
         SW_SHOWMINIMIZED => WindowState::HIDDEN,
         _ => unsafe {
@@ -1300,7 +1341,13 @@ fn get_window_state(hwnd: HWND) -> WindowState {
                 WindowState::default()
             }
         },
+    };
+
+    if unsafe { GetWindowLongW(hwnd, GWL_EXSTYLE) } as u32 & WS_EX_TOPMOST != 0 {
+        state |= WindowState::ALWAYS_ON_TOP;
     }
+
+    state
 }
 
 /// "Blur behind" is the old vista term for a cool blurring
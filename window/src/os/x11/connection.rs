@@ -58,6 +58,8 @@ pub struct XConnection {
     pub atom_state_maximized_horz: Atom,
     pub atom_state_hidden: Atom,
     pub atom_state_fullscreen: Atom,
+    pub atom_state_above: Atom,
+    pub atom_state_below: Atom,
     pub atom_net_wm_state: Atom,
     pub atom_motif_wm_hints: Atom,
     pub atom_net_wm_pid: Atom,
@@ -661,6 +663,8 @@ impl XConnection {
         let atom_state_maximized_horz = Self::intern_atom(&conn, "_NET_WM_STATE_MAXIMIZED_HORZ")?;
         let atom_state_hidden = Self::intern_atom(&conn, "_NET_WM_STATE_HIDDEN")?;
         let atom_state_fullscreen = Self::intern_atom(&conn, "_NET_WM_STATE_FULLSCREEN")?;
+        let atom_state_above = Self::intern_atom(&conn, "_NET_WM_STATE_ABOVE")?;
+        let atom_state_below = Self::intern_atom(&conn, "_NET_WM_STATE_BELOW")?;
         let atom_net_wm_state = Self::intern_atom(&conn, "_NET_WM_STATE")?;
         let atom_motif_wm_hints = Self::intern_atom(&conn, "_MOTIF_WM_HINTS")?;
         let atom_net_wm_pid = Self::intern_atom(&conn, "_NET_WM_PID")?;
@@ -798,6 +802,8 @@ impl XConnection {
             atom_state_maximized_horz,
             atom_state_hidden,
             atom_state_fullscreen,
+            atom_state_above,
+            atom_state_below,
             atom_net_wm_state,
             atom_motif_wm_hints,
             atom_net_wm_pid,
@@ -10,6 +10,7 @@ use crate::{
 };
 use anyhow::{anyhow, Context as _};
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::ConfigHandle;
 use promise::{Future, Promise};
 use raw_window_handle::{
@@ -1201,6 +1202,10 @@ impl XWindowInner {
                 window_state |= WindowState::MAXIMIZED;
             } else if s == conn.atom_state_hidden.resource_id() {
                 window_state |= WindowState::HIDDEN;
+            } else if s == conn.atom_state_above.resource_id() {
+                window_state |= WindowState::ALWAYS_ON_TOP;
+            } else if s == conn.atom_state_below.resource_id() {
+                window_state |= WindowState::ALWAYS_ON_BOTTOM;
             }
         }
 
@@ -1256,6 +1261,24 @@ impl XWindowInner {
         )
     }
 
+    fn set_window_level_hint(&mut self, level: WindowLevel) -> anyhow::Result<()> {
+        let (above, below) = match level {
+            WindowLevel::AlwaysOnTop => (true, false),
+            WindowLevel::AlwaysOnBottom => (false, true),
+            WindowLevel::Normal => (false, false),
+        };
+        self.set_wm_state(
+            NetWmStateAction::with_bool(above),
+            self.conn().atom_state_above,
+            None,
+        )?;
+        self.set_wm_state(
+            NetWmStateAction::with_bool(below),
+            self.conn().atom_state_below,
+            None,
+        )
+    }
+
     #[allow(clippy::identity_op)]
     fn adjust_decorations(&mut self, decorations: WindowDecorations) -> anyhow::Result<()> {
         // Set the motif hints to disable decorations.
@@ -1492,19 +1515,12 @@ impl XWindow {
             }))
         };
 
-        // WM_CLASS is encoded as the class and instance name,
-        // null terminated
-        let mut class_string = class_name.as_bytes().to_vec();
-        class_string.push(0);
-        class_string.extend_from_slice(class_name.as_bytes());
-        class_string.push(0);
-
         conn.send_request_no_reply(&xcb::x::ChangeProperty {
             mode: PropMode::Replace,
             window: window_id,
             property: xcb::x::ATOM_WM_CLASS,
             r#type: xcb::x::ATOM_STRING,
-            data: &class_string,
+            data: &wm_class_property(class_name),
         })?;
 
         conn.send_request_no_reply(&xcb::x::ChangeProperty {
@@ -1673,6 +1689,12 @@ impl XWindowInner {
         }
     }
 
+    fn set_window_level(&mut self, level: WindowLevel) {
+        if let Err(err) = self.set_window_level_hint(level.clone()) {
+            log::error!("Failed to set window level to {level:?}: {err:#}");
+        }
+    }
+
     fn toggle_fullscreen(&mut self) {
         let fullscreen = match self.get_window_state() {
             Ok(f) => f.contains(WindowState::FULL_SCREEN),
@@ -1791,6 +1813,20 @@ impl XWindowInner {
         });
     }
 
+    /// Change the WM_CLASS for the window manager.
+    /// ICCCM only requires window managers to read it when the
+    /// window is mapped, but most of them also track later changes.
+    fn set_app_id(&mut self, app_id: &str) {
+        self.conn()
+            .send_request_no_reply_log(&xcb::x::ChangeProperty {
+                mode: PropMode::Replace,
+                window: self.window_id,
+                property: xcb::x::ATOM_WM_CLASS,
+                r#type: xcb::x::ATOM_STRING,
+                data: &wm_class_property(app_id),
+            });
+    }
+
     /// Change the title for the window manager
     fn set_title(&mut self, title: &str) {
         if title == self.title {
@@ -1990,6 +2026,13 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn set_window_level(&self, level: WindowLevel) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        });
+    }
+
     fn restore(&self) {
         XConnection::with_window_inner(self.0, |inner| {
             inner.restore();
@@ -2005,6 +2048,10 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn can_change_decorations(&self) -> bool {
+        true
+    }
+
     fn focus(&self) {
         XConnection::with_window_inner(self.0, |inner| {
             inner.focus();
@@ -2033,6 +2080,18 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn can_change_app_id(&self) -> bool {
+        true
+    }
+
+    fn set_app_id(&self, app_id: &str) {
+        let app_id = app_id.to_owned();
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_app_id(&app_id);
+            Ok(())
+        });
+    }
+
     fn set_title(&self, title: &str) {
         let title = title.to_owned();
         XConnection::with_window_inner(self.0, move |inner| {
@@ -2161,6 +2220,16 @@ impl WindowOps for XWindow {
     }
 }
 
+/// WM_CLASS is encoded as the instance and class name,
+/// each null terminated
+fn wm_class_property(class_name: &str) -> Vec<u8> {
+    let mut class_string = class_name.as_bytes().to_vec();
+    class_string.push(0);
+    class_string.extend_from_slice(class_name.as_bytes());
+    class_string.push(0);
+    class_string
+}
+
 fn parse_texturi_list(url_list: &[u8]) -> Vec<PathBuf> {
     String::from_utf8_lossy(url_list)
         .lines()
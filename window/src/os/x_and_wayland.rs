@@ -13,6 +13,7 @@ use crate::{
     ScreenPoint, WindowEvent, WindowOps,
 };
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::ConfigHandle;
 use promise::*;
 use raw_window_handle::{
@@ -290,6 +291,14 @@ impl WindowOps for Window {
         }
     }
 
+    fn can_change_decorations(&self) -> bool {
+        match self {
+            Self::X11(x) => x.can_change_decorations(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.can_change_decorations(),
+        }
+    }
+
     fn show(&self) {
         match self {
             Self::X11(x) => x.show(),
@@ -330,6 +339,22 @@ impl WindowOps for Window {
         }
     }
 
+    fn can_change_app_id(&self) -> bool {
+        match self {
+            Self::X11(x) => x.can_change_app_id(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.can_change_app_id(),
+        }
+    }
+
+    fn set_app_id(&self, app_id: &str) {
+        match self {
+            Self::X11(x) => x.set_app_id(app_id),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_app_id(app_id),
+        }
+    }
+
     fn set_icon(&self, image: crate::bitmaps::Image) {
         match self {
             Self::X11(x) => x.set_icon(image),
@@ -346,6 +371,14 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_window_level(&self, level: WindowLevel) {
+        match self {
+            Self::X11(x) => x.set_window_level(level),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_level(level),
+        }
+    }
+
     fn restore(&self) {
         match self {
             Self::X11(x) => x.restore(),